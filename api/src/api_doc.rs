@@ -0,0 +1,204 @@
+use crate::api_error::ApiError;
+use crate::api_token_dto::{ApiTokenDTO, CreatedApiTokenDTO};
+use crate::auth::SESSION_COOKIE;
+use crate::chart_dto::{ChartDTO, PointDTO, TimeAxisDTO, ValueAxisDTO};
+use crate::datapoint_dto::{DatapointDTO, TrashedDatapointDTO};
+use crate::import_dto::{ImportReport, RowErrorDTO};
+use crate::pagination::DatapointPage;
+use crate::plot_options_form::PlotOptionsForm;
+use crate::revision_dto::RevisionDTO;
+use crate::summary_dto::SummaryDTO;
+use crate::webhook_dto::{DeliveryDTO, WebhookDTO};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// The OpenAPI 3 description of every route mounted under `/api`, built from the
+/// `utoipa::path` attribute on each route and the `ToSchema` derive on each DTO.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "TaPAS", description = "Tag-based personal analytics"),
+    servers((url = "/api")),
+    modifiers(&SessionAuth),
+    security(("bearer" = []), ("session_cookie" = [])),
+    paths(
+        crate::list_datapoints,
+        crate::create_datapoint,
+        crate::get_datapoint,
+        crate::replace_datapoint,
+        crate::patch_datapoint,
+        crate::delete_datapoint,
+        crate::input,
+        crate::bulkinput,
+        crate::import,
+        crate::import_health,
+        crate::query,
+        crate::export,
+        crate::backup,
+        crate::restore_backup,
+        crate::plot,
+        crate::tags,
+        crate::predict,
+        crate::chart,
+        crate::update,
+        crate::delete,
+        crate::trash,
+        crate::restore,
+        crate::duplicates,
+        crate::merge,
+        crate::history,
+        crate::revert,
+        crate::batchedit,
+        crate::undo,
+        crate::redo,
+        crate::comparison,
+        crate::register,
+        crate::login,
+        crate::logout,
+        crate::create_token,
+        crate::tokens,
+        crate::revoke_token,
+        crate::create_webhook,
+        crate::webhooks,
+        crate::delete_webhook,
+        crate::deliveries,
+        crate::events,
+        crate::openapi,
+    ),
+    components(schemas(
+        ApiError,
+        DatapointDTO,
+        TrashedDatapointDTO,
+        DatapointPage,
+        RevisionDTO,
+        SummaryDTO,
+        ImportReport,
+        RowErrorDTO,
+        crate::Form,
+        crate::TagEdit,
+        crate::LineResult,
+        crate::UpdateForm,
+        crate::MappingForm,
+        crate::ImportRequest,
+        crate::HealthImportReport,
+        crate::EditRequest,
+        crate::StepsRequest,
+        crate::DeleteKey,
+        crate::DeleteConfirmation,
+        crate::MergeRequest,
+        crate::RevertRequest,
+        crate::RestoreReport,
+        crate::PlotRequest,
+        PlotOptionsForm,
+        crate::Image,
+        crate::CompareForm,
+        crate::ComparisonResults,
+        crate::Prediction,
+        crate::PredictionForm,
+        crate::ChartRequest,
+        ChartDTO,
+        TimeAxisDTO,
+        ValueAxisDTO,
+        PointDTO,
+        crate::Tag,
+        crate::Credentials,
+        crate::Account,
+        crate::SessionToken,
+        crate::TokenRequest,
+        ApiTokenDTO,
+        CreatedApiTokenDTO,
+        crate::WebhookRequest,
+        WebhookDTO,
+        DeliveryDTO,
+    ))
+)]
+pub struct ApiDoc;
+
+/// Declares the two ways of authenticating: a session or API token sent as a bearer
+/// token, or the session cookie set on login.
+struct SessionAuth;
+
+impl Modify for SessionAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "session_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE))),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::{serde_json, Value};
+    use std::collections::BTreeSet;
+
+    fn spec() -> Value {
+        serde_json::to_value(ApiDoc::openapi()).unwrap()
+    }
+
+    /// Turns a Rocket route such as `/datapoints/<key>?<q>` into its OpenAPI path,
+    /// `/datapoints/{key}`.
+    fn openapi_path(route: &rocket::Route) -> String {
+        route
+            .uri
+            .path()
+            .to_string()
+            .replace('<', "{")
+            .replace('>', "}")
+    }
+
+    #[test]
+    fn every_mounted_route_is_documented_and_nothing_else() {
+        let mounted: BTreeSet<(String, String)> = crate::api_routes()
+            .iter()
+            .map(|route| (route.method.as_str().to_lowercase(), openapi_path(route)))
+            .collect();
+        let spec = spec();
+        let mut documented = BTreeSet::new();
+        for (path, operations) in spec["paths"].as_object().unwrap() {
+            for method in operations.as_object().unwrap().keys() {
+                documented.insert((method.to_string(), path.to_string()));
+            }
+        }
+
+        assert_eq!(mounted, documented);
+    }
+
+    #[test]
+    fn every_referenced_schema_is_defined() {
+        let spec = spec();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        let text = spec.to_string();
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "{} is not defined", name);
+        }
+    }
+
+    #[test]
+    fn the_field_names_the_client_relies_on_are_documented() {
+        let spec = spec();
+        let schemas = &spec["components"]["schemas"];
+        for (schema, field) in [
+            ("Form", "fieldInput"),
+            ("PlotRequest", "withRegression"),
+            ("PredictionForm", "targetGoal"),
+            ("Prediction", "willIntercept"),
+            ("CompareForm", "fieldInputs"),
+            ("ChartDTO", "fittedLine"),
+            ("ApiError", "code"),
+        ] {
+            assert!(
+                schemas[schema]["properties"][field].is_object(),
+                "{} has no field {}",
+                schema,
+                field
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, status, Responder};
+use rocket::serde::json::{serde_json, Json, Value};
+use rocket::serde::Serialize;
+use utoipa::ToSchema;
+
+/// The body of every failed API request: a machine readable `code`, a human readable
+/// `message` and optional `details` such as per-line parse errors.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiError {
+    #[serde(skip)]
+    status: Status,
+    code: String,
+    message: String,
+    #[schema(value_type = Option<Object>)]
+    details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, code: &str, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            code: code.to_string(),
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn bad_request(code: &str, message: impl Into<String>) -> ApiError {
+        ApiError::new(Status::BadRequest, code, message)
+    }
+
+    pub fn not_found(code: &str, message: impl Into<String>) -> ApiError {
+        ApiError::new(Status::NotFound, code, message)
+    }
+
+    pub fn unprocessable(code: &str, message: impl Into<String>) -> ApiError {
+        ApiError::new(Status::UnprocessableEntity, code, message)
+    }
+
+    pub fn storage_failure(message: impl Into<String>) -> ApiError {
+        ApiError::new(Status::InternalServerError, "storage_failure", message)
+    }
+
+    pub fn render_failure(message: impl Into<String>) -> ApiError {
+        ApiError::new(Status::InternalServerError, "render_failed", message)
+    }
+
+    pub fn with_details<T: Serialize>(mut self, details: T) -> ApiError {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        status::Custom(self.status, Json(self)).respond_to(request)
+    }
+}
+
+pub type ApiResult<T> = Result<Json<T>, ApiError>;
+
+fn code_for(status: Status) -> &'static str {
+    match status.code {
+        400 => "bad_request",
+        401 => "unauthorized",
+        403 => "forbidden",
+        404 => "not_found",
+        413 => "payload_too_large",
+        415 => "unsupported_media_type",
+        422 => "unprocessable_entity",
+        500 => "internal_error",
+        _ => "error",
+    }
+}
+
+/// Turns every error Rocket raises on its own, such as an unknown route or a body that
+/// does not deserialize, into the same shape the routes return.
+#[rocket::catch(default)]
+pub fn default_catcher(status: Status, request: &Request<'_>) -> ApiError {
+    let message = match status.code {
+        404 => format!("no route matches {} {}", request.method(), request.uri()),
+        _ => status.reason_lossy().to_lowercase(),
+    };
+    ApiError::new(status, code_for(status), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+    use rocket::serde::Deserialize;
+    use rocket::{catchers, get, routes, uri};
+
+    #[derive(Deserialize)]
+    #[serde(crate = "rocket::serde")]
+    struct Body {
+        a: u64,
+    }
+
+    #[get("/fails")]
+    fn fails() -> ApiResult<()> {
+        Err(ApiError::unprocessable("bad_thing", "that was bad").with_details(vec![1, 2]))
+    }
+
+    #[rocket::post("/echo", format = "application/json", data = "<body>")]
+    fn echo(body: Json<Body>) -> Json<u64> {
+        Json(body.a)
+    }
+
+    fn client() -> Client {
+        let rocket = rocket::build()
+            .mount("/api", routes![fails, echo])
+            .register("/api", catchers![default_catcher]);
+        Client::tracked(rocket).unwrap()
+    }
+
+    #[test]
+    fn route_errors_carry_status_code_message_and_details() {
+        let client = client();
+        let response = client.get(uri!("/api", fails)).dispatch();
+
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["code"], "bad_thing");
+        assert_eq!(body["message"], "that was bad");
+        assert_eq!(body["details"], serde_json::json!([1, 2]));
+    }
+
+    #[test]
+    fn unknown_routes_are_caught_as_not_found() {
+        let client = client();
+        let response = client.get("/api/missing").dispatch();
+
+        assert_eq!(response.status(), Status::NotFound);
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["message"], "no route matches GET /api/missing");
+        assert_eq!(body["details"], Value::Null);
+    }
+
+    #[test]
+    fn malformed_bodies_are_caught_as_unprocessable() {
+        let client = client();
+        let response = client
+            .post(uri!("/api", echo))
+            .header(rocket::http::ContentType::JSON)
+            .body(r#"{"b": 1}"#)
+            .dispatch();
+
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["code"], "unprocessable_entity");
+    }
+}
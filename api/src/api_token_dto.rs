@@ -0,0 +1,45 @@
+use domain::apitoken::ApiToken;
+use rocket::serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiTokenDTO {
+    id: u64,
+    name: String,
+    scope: String,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+    #[serde(rename = "lastUsedAt")]
+    last_used_at: Option<String>,
+}
+
+impl From<ApiToken> for ApiTokenDTO {
+    fn from(api_token: ApiToken) -> ApiTokenDTO {
+        ApiTokenDTO {
+            id: api_token.get_id(),
+            name: api_token.get_name().to_string(),
+            scope: api_token.get_scope().as_str().to_string(),
+            created_at: api_token.get_created_at().to_rfc3339(),
+            last_used_at: api_token.get_last_used_at().map(|used| used.to_rfc3339()),
+        }
+    }
+}
+
+/// A token just created, the only time the token itself is handed out.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CreatedApiTokenDTO {
+    #[serde(flatten)]
+    api_token: ApiTokenDTO,
+    token: String,
+}
+
+impl CreatedApiTokenDTO {
+    pub fn new(api_token: ApiToken, token: String) -> CreatedApiTokenDTO {
+        CreatedApiTokenDTO {
+            api_token: ApiTokenDTO::from(api_token),
+            token,
+        }
+    }
+}
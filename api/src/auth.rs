@@ -0,0 +1,144 @@
+use domain::apitoken::TokenScope;
+use persistence::repository::Repository;
+use rocket::http::{Cookie, SameSite, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use std::ops::Deref;
+use std::sync::Arc;
+
+pub const SESSION_COOKIE: &str = "session";
+
+/// The user a request is made by, identified by a session or API token sent as
+/// `Authorization: Bearer <token>`, or by the `session` cookie set on login. Requests
+/// without a valid token fail with 401 Unauthorized. Any token may read; routes that
+/// need more ask for `WriteAccess` or `AdminAccess` instead.
+pub struct AuthenticatedUser {
+    id: u64,
+    scope: TokenScope,
+    token: String,
+}
+
+impl AuthenticatedUser {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn scope(&self) -> TokenScope {
+        self.scope
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let token = match request.headers().get_one("Authorization") {
+            Some(header) => bearer_token(header),
+            None => request
+                .cookies()
+                .get(SESSION_COOKIE)
+                .map(|cookie| cookie.value().to_string()),
+        };
+        let (Some(token), Some(repository)) = (token, request.rocket().state::<Arc<Repository>>())
+        else {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        };
+        match repository.authenticate(&token).await {
+            Some((id, scope)) => Outcome::Success(AuthenticatedUser { id, scope, token }),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// A user whose token may change datapoints. Read-only tokens fail with 403 Forbidden.
+pub struct WriteAccess(AuthenticatedUser);
+
+/// A user whose token may manage the account, such as its API tokens and backups. Tokens
+/// with a lower scope fail with 403 Forbidden.
+pub struct AdminAccess(AuthenticatedUser);
+
+async fn with_scope(
+    request: &Request<'_>,
+    required: TokenScope,
+) -> request::Outcome<AuthenticatedUser, ()> {
+    match request.guard::<AuthenticatedUser>().await {
+        Outcome::Success(user) if !user.scope().allows(required) => {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+        outcome => outcome,
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WriteAccess {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        with_scope(request, TokenScope::Write)
+            .await
+            .map(WriteAccess)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAccess {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        with_scope(request, TokenScope::Admin)
+            .await
+            .map(AdminAccess)
+    }
+}
+
+impl Deref for WriteAccess {
+    type Target = AuthenticatedUser;
+
+    fn deref(&self) -> &AuthenticatedUser {
+        &self.0
+    }
+}
+
+impl Deref for AdminAccess {
+    type Target = AuthenticatedUser;
+
+    fn deref(&self) -> &AuthenticatedUser {
+        &self.0
+    }
+}
+
+fn bearer_token(header: &str) -> Option<String> {
+    let (scheme, token) = header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") || token.trim().is_empty() {
+        return None;
+    }
+    Some(token.trim().to_string())
+}
+
+/// The cookie holding the session token, readable by the server only.
+pub fn session_cookie(token: String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_tokens_are_read_from_the_authorization_header() {
+        assert_eq!(bearer_token("Bearer abc123"), Some("abc123".to_string()));
+        assert_eq!(bearer_token("bearer abc123"), Some("abc123".to_string()));
+        assert_eq!(bearer_token("Basic abc123"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("abc123"), None);
+    }
+}
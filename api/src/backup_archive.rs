@@ -0,0 +1,314 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use domain::backup::{Backup, BACKUP_VERSION};
+use domain::datapoint::Datapoint;
+use domain::revision::{Revision, RevisionKind};
+use domain::trasheddatapoint::TrashedDatapoint;
+use rocket::serde::{json, Deserialize, Serialize};
+
+const ARCHIVE_FORMAT: &str = "tapas-backup";
+
+/// A backup archive is a JSON lines file. The first line holds the metadata, every other
+/// line a datapoint (live or trashed) or a revision.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "lowercase")]
+enum ArchiveLine {
+    Metadata(MetadataLine),
+    Datapoint(DatapointLine),
+    Revision(RevisionLine),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct MetadataLine {
+    format: String,
+    version: u32,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+    datapoints: usize,
+    trashed: usize,
+    revisions: usize,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Snapshot {
+    timestamp: String,
+    data: String,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct DatapointLine {
+    key: u64,
+    #[serde(flatten)]
+    snapshot: Snapshot,
+    #[serde(rename = "deletedAt")]
+    deleted_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct RevisionLine {
+    id: u64,
+    key: u64,
+    kind: String,
+    #[serde(rename = "recordedAt")]
+    recorded_at: String,
+    before: Option<Snapshot>,
+    after: Option<Snapshot>,
+}
+
+impl From<&Datapoint> for Snapshot {
+    fn from(datapoint: &Datapoint) -> Snapshot {
+        Snapshot {
+            timestamp: datapoint.get_datetime().to_rfc3339(),
+            data: datapoint.get_data().to_owned(),
+            tags: datapoint.get_tags().to_owned(),
+        }
+    }
+}
+
+impl Snapshot {
+    fn into_datapoint(self, key: u64) -> Result<Datapoint, String> {
+        Ok(Datapoint::new(
+            parse_timestamp(&self.timestamp)?,
+            self.data,
+            self.tags,
+            key,
+        ))
+    }
+}
+
+/// Renders the backup as archive lines, ready to be streamed.
+pub fn write_archive(
+    backup: Backup,
+    created_at: DateTime<Utc>,
+) -> impl Iterator<Item = String> + Send {
+    let metadata = ArchiveLine::Metadata(MetadataLine {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: created_at.to_rfc3339(),
+        datapoints: backup.get_datapoints().len(),
+        trashed: backup.get_trash().len(),
+        revisions: backup.get_revisions().len(),
+        tags: backup.get_tags(),
+    });
+    let datapoints: Vec<ArchiveLine> = backup
+        .get_datapoints()
+        .iter()
+        .map(|datapoint| datapoint_line(datapoint, None))
+        .collect();
+    let trash: Vec<ArchiveLine> = backup
+        .get_trash()
+        .iter()
+        .map(|trashed| datapoint_line(trashed.get_datapoint(), Some(trashed.get_deleted_at())))
+        .collect();
+    let revisions: Vec<ArchiveLine> = backup.get_revisions().iter().map(revision_line).collect();
+    std::iter::once(metadata)
+        .chain(datapoints)
+        .chain(trash)
+        .chain(revisions)
+        .map(|line| {
+            format!(
+                "{}\n",
+                json::to_string(&line).expect("archive lines always serialize")
+            )
+        })
+}
+
+fn datapoint_line(datapoint: &Datapoint, deleted_at: Option<&DateTime<Utc>>) -> ArchiveLine {
+    ArchiveLine::Datapoint(DatapointLine {
+        key: datapoint.get_key(),
+        snapshot: Snapshot::from(datapoint),
+        deleted_at: deleted_at.map(|deleted_at| deleted_at.to_rfc3339()),
+    })
+}
+
+fn revision_line(revision: &Revision) -> ArchiveLine {
+    ArchiveLine::Revision(RevisionLine {
+        id: revision.get_id(),
+        key: revision.get_key(),
+        kind: revision.get_kind().as_str().to_string(),
+        recorded_at: revision.get_recorded_at().to_rfc3339(),
+        before: revision.get_before().map(Snapshot::from),
+        after: revision.get_after().map(Snapshot::from),
+    })
+}
+
+/// Reads an archive back into a backup, refusing archives of an unknown format or newer
+/// version and archives that hold fewer or more lines than their metadata announces.
+pub fn read_archive(archive: &str) -> Result<Backup, String> {
+    let mut lines = archive
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let metadata = match lines.next() {
+        Some((number, line)) => match parse_line(number, line)? {
+            ArchiveLine::Metadata(metadata) => metadata,
+            _ => return Err(format!("line {}: expected backup metadata", number + 1)),
+        },
+        None => return Err("archive is empty".to_string()),
+    };
+    if metadata.format != ARCHIVE_FORMAT {
+        return Err(format!("unknown archive format '{}'", metadata.format));
+    }
+    if metadata.version > BACKUP_VERSION {
+        return Err(format!(
+            "archive version {} is newer than supported version {}",
+            metadata.version, BACKUP_VERSION
+        ));
+    }
+
+    let mut datapoints = Vec::new();
+    let mut trash = Vec::new();
+    let mut revisions = Vec::new();
+    for (number, line) in lines {
+        let at_line = |error: String| format!("line {}: {}", number + 1, error);
+        match parse_line(number, line)? {
+            ArchiveLine::Metadata(_) => return Err(at_line("duplicate metadata".to_string())),
+            ArchiveLine::Datapoint(line) => {
+                let datapoint = line.snapshot.into_datapoint(line.key).map_err(at_line)?;
+                match line.deleted_at {
+                    Some(deleted_at) => {
+                        let deleted_at = parse_timestamp(&deleted_at).map_err(at_line)?;
+                        trash.push(TrashedDatapoint::new(
+                            datapoint,
+                            deleted_at.with_timezone(&Utc),
+                        ))
+                    }
+                    None => datapoints.push(datapoint),
+                }
+            }
+            ArchiveLine::Revision(line) => revisions.push(into_revision(line).map_err(at_line)?),
+        }
+    }
+    if datapoints.len() != metadata.datapoints
+        || trash.len() != metadata.trashed
+        || revisions.len() != metadata.revisions
+    {
+        return Err("archive contents do not match its metadata".to_string());
+    }
+    Ok(Backup::new(datapoints, trash, revisions))
+}
+
+fn parse_line(number: usize, line: &str) -> Result<ArchiveLine, String> {
+    json::from_str(line).map_err(|error| format!("line {}: {}", number + 1, error))
+}
+
+fn into_revision(line: RevisionLine) -> Result<Revision, String> {
+    let kind = RevisionKind::parse(&line.kind)
+        .ok_or_else(|| format!("unknown revision kind '{}'", line.kind))?;
+    let before = match line.before {
+        Some(snapshot) => Some(snapshot.into_datapoint(line.key)?),
+        None => None,
+    };
+    let after = match line.after {
+        Some(snapshot) => Some(snapshot.into_datapoint(line.key)?),
+        None => None,
+    };
+    Ok(Revision::new(
+        line.id,
+        line.key,
+        kind,
+        parse_timestamp(&line.recorded_at)?.with_timezone(&Utc),
+        before,
+        after,
+    ))
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|_| format!("could not parse timestamp '{}'", timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn datapoint(data: &str, key: u64) -> Datapoint {
+        Datapoint::new(
+            FixedOffset::east_opt(3600)
+                .unwrap()
+                .with_ymd_and_hms(2023, 1, 2, 7, 30, 0)
+                .unwrap(),
+            data.to_string(),
+            vec!["weight".to_string()],
+            key,
+        )
+    }
+
+    fn backup() -> Backup {
+        let recorded_at = Utc.with_ymd_and_hms(2023, 1, 2, 6, 30, 0).unwrap();
+        Backup::new(
+            vec![datapoint("80kg", 1)],
+            vec![TrashedDatapoint::new(datapoint("81kg", 2), recorded_at)],
+            vec![
+                Revision::new(
+                    1,
+                    1,
+                    RevisionKind::Create,
+                    recorded_at,
+                    None,
+                    Some(datapoint("80kg", 1)),
+                ),
+                Revision::new(
+                    2,
+                    2,
+                    RevisionKind::Delete,
+                    recorded_at,
+                    Some(datapoint("81kg", 2)),
+                    None,
+                ),
+            ],
+        )
+    }
+
+    fn archive() -> String {
+        write_archive(backup(), Utc::now()).collect()
+    }
+
+    #[test]
+    fn archive_starts_with_metadata() {
+        let archive = archive();
+        let first: json::Value = json::from_str(archive.lines().next().unwrap()).unwrap();
+
+        assert_eq!(first["type"], "metadata");
+        assert_eq!(first["format"], "tapas-backup");
+        assert_eq!(first["version"], 1);
+        assert_eq!(first["datapoints"], 1);
+        assert_eq!(archive.lines().count(), 5);
+    }
+
+    #[test]
+    fn archive_survives_a_round_trip() {
+        assert_eq!(read_archive(&archive()), Ok(backup()));
+    }
+
+    #[test]
+    fn newer_archive_versions_are_refused() {
+        let archive = archive().replacen("\"version\":1", "\"version\":2", 1);
+
+        assert!(read_archive(&archive).unwrap_err().contains("newer"));
+    }
+
+    #[test]
+    fn truncated_archives_are_refused() {
+        let archive = archive();
+        let truncated: Vec<&str> = archive.lines().take(3).collect();
+
+        assert_eq!(
+            read_archive(&truncated.join("\n")),
+            Err("archive contents do not match its metadata".to_string())
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_by_number() {
+        let archive = format!("{}{{not json}}\n", archive());
+
+        assert!(read_archive(&archive).unwrap_err().starts_with("line 6:"));
+    }
+}
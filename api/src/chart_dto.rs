@@ -0,0 +1,107 @@
+use chrono::{DateTime, Local};
+use domain::plotter::scatterplot::ChartData;
+use rocket::serde::Serialize;
+use utoipa::ToSchema;
+
+/// The series and axes a plot draws, for charts rendered by the client.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ChartDTO {
+    title: String,
+    #[serde(rename = "xAxis")]
+    x_axis: TimeAxisDTO,
+    #[serde(rename = "yAxis")]
+    y_axis: ValueAxisDTO,
+    points: Vec<PointDTO>,
+    /// The regression line, when one was asked for or a goal is predicted.
+    #[serde(rename = "fittedLine")]
+    fitted_line: Option<Vec<PointDTO>>,
+    /// The goal drawn across the chart, when one is predicted.
+    #[serde(rename = "targetLine")]
+    target_line: Option<Vec<PointDTO>>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TimeAxisDTO {
+    min: String,
+    max: String,
+    /// `date` when the chart spans more than two days and is labelled with dates, `time`
+    /// when it is labelled with times of day.
+    labels: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ValueAxisDTO {
+    min: f64,
+    max: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PointDTO {
+    x: String,
+    y: f64,
+}
+
+impl From<ChartData> for ChartDTO {
+    fn from(chart: ChartData) -> ChartDTO {
+        let (x_min, x_max) = chart.get_x_range();
+        let (y_min, y_max) = chart.get_y_range();
+        ChartDTO {
+            title: chart.get_title().to_string(),
+            x_axis: TimeAxisDTO {
+                min: x_min.to_rfc3339(),
+                max: x_max.to_rfc3339(),
+                labels: if chart.labels_dates() { "date" } else { "time" }.to_string(),
+            },
+            y_axis: ValueAxisDTO {
+                min: y_min,
+                max: y_max,
+            },
+            points: points_from(chart.get_points()),
+            fitted_line: chart.get_fitted_line().map(|line| points_from(line)),
+            target_line: chart.get_target_line().map(|line| points_from(line)),
+        }
+    }
+}
+
+fn points_from(series: &[(DateTime<Local>, f64)]) -> Vec<PointDTO> {
+    series
+        .iter()
+        .map(|(datetime, value)| PointDTO {
+            x: datetime.to_rfc3339(),
+            y: *value,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::datapoint::create_datapoint;
+    use domain::parsedquery::ParsedQuery;
+    use domain::plotter::scatterplot::scatter_chart;
+    use domain::queryresult::QueryResult;
+    use rocket::serde::json;
+
+    #[test]
+    fn chart_body_holds_the_axes_and_every_series() {
+        let datapoints = vec![
+            create_datapoint("80 +weight +DATE:2023-10-01"),
+            create_datapoint("79 +weight +DATE:2023-10-08"),
+        ];
+        let data = QueryResult::from(datapoints, ParsedQuery::from("weight"));
+        let chart = scatter_chart(&data, true).unwrap();
+
+        let body = json::to_value(ChartDTO::from(chart)).unwrap();
+
+        assert_eq!(body["xAxis"]["labels"], "date");
+        assert_eq!(body["xAxis"]["min"], body["points"][0]["x"]);
+        assert!(body["yAxis"]["max"].as_f64().unwrap() > 80.0);
+        assert_eq!(body["points"][1]["y"], 79.0);
+        assert_eq!(body["fittedLine"].as_array().unwrap().len(), 2);
+        assert_eq!(body["targetLine"], json::Value::Null);
+    }
+}
@@ -1,7 +1,9 @@
 use domain::datapoint::Datapoint;
+use domain::trasheddatapoint::TrashedDatapoint;
 use rocket::serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct DatapointDTO {
     timestamp: String,
@@ -22,6 +24,24 @@ impl From<Datapoint> for DatapointDTO {
     }
 }
 
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TrashedDatapointDTO {
+    #[serde(flatten)]
+    datapoint: DatapointDTO,
+    #[serde(rename = "deletedAt")]
+    deleted_at: String,
+}
+
+impl From<TrashedDatapoint> for TrashedDatapointDTO {
+    fn from(trashed: TrashedDatapoint) -> TrashedDatapointDTO {
+        TrashedDatapointDTO {
+            deleted_at: trashed.get_deleted_at().to_rfc3339(),
+            datapoint: DatapointDTO::from(trashed.into_datapoint()),
+        }
+    }
+}
+
 pub fn dto_vec_from(datapoints: Vec<Datapoint>) -> Vec<DatapointDTO> {
     let mut collector = Vec::new();
     for datapoint in datapoints {
@@ -29,3 +49,28 @@ pub fn dto_vec_from(datapoints: Vec<Datapoint>) -> Vec<DatapointDTO> {
     }
     collector
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    /// Two datapoints logged an hour east of UTC, the first with a comma in its data.
+    pub fn datapoints() -> Vec<Datapoint> {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        vec![
+            Datapoint::new(
+                offset.with_ymd_and_hms(2023, 1, 2, 7, 30, 0).unwrap(),
+                "80kg, after run".to_string(),
+                vec!["weight".to_string(), "morning".to_string()],
+                1,
+            ),
+            Datapoint::new(
+                offset.with_ymd_and_hms(2023, 1, 3, 7, 45, 0).unwrap(),
+                "79kg".to_string(),
+                vec!["weight".to_string()],
+                2,
+            ),
+        ]
+    }
+}
@@ -0,0 +1,62 @@
+use domain::datapointevent::DatapointEvent;
+use rocket::tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// How many changes a slow subscriber may fall behind before it misses some.
+const CAPACITY: usize = 1024;
+
+/// Hands the changes the repository reports to every open `/api/events` stream. Each
+/// change carries its owner, so streams only pass on the changes of their own user.
+pub struct EventBus {
+    sender: Sender<(u64, DatapointEvent)>,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventBus { sender }
+    }
+
+    /// A listener for `Repository::with_listener` publishing on this bus.
+    pub fn listener(&self) -> impl Fn(u64, DatapointEvent) + Send + Sync + 'static {
+        let sender = self.sender.clone();
+        move |owner, event| {
+            // Sending only fails while no stream is open, when nobody misses the change.
+            let _ = sender.send((owner, event));
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<(u64, DatapointEvent)> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::datapoint::create_datapoint;
+    use domain::datapointevent::ChangeKind;
+
+    #[test]
+    fn published_changes_reach_every_subscriber() {
+        let bus = EventBus::new();
+        let listener = bus.listener();
+        listener(
+            1,
+            DatapointEvent::new(ChangeKind::Created, create_datapoint("lost")),
+        );
+        let mut first = bus.subscribe();
+        let mut second = bus.subscribe();
+
+        listener(
+            2,
+            DatapointEvent::new(ChangeKind::Deleted, create_datapoint("one")),
+        );
+
+        for receiver in [&mut first, &mut second] {
+            let (owner, event) = receiver.try_recv().unwrap();
+            assert_eq!(owner, 2);
+            assert_eq!(event.get_kind(), ChangeKind::Deleted);
+            assert!(receiver.try_recv().is_err());
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use domain::datapoint::Datapoint;
+use rocket::http::ContentType;
+use rocket::serde::{json, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Option<ExportFormat> {
+        match format {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            "jsonl" => Some(ExportFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            ExportFormat::Csv => ContentType::CSV,
+            ExportFormat::Json => ContentType::JSON,
+            ExportFormat::Jsonl => ContentType::new("application", "jsonl"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ExportRecord {
+    key: u64,
+    timestamp: String,
+    data: String,
+    tags: Vec<String>,
+}
+
+impl From<Datapoint> for ExportRecord {
+    fn from(datapoint: Datapoint) -> ExportRecord {
+        ExportRecord {
+            key: datapoint.get_key(),
+            timestamp: datapoint.get_datetime().to_rfc3339(),
+            data: datapoint.get_data().to_owned(),
+            tags: datapoint.get_tags().to_owned(),
+        }
+    }
+}
+
+/// Lazily renders the datapoints in the export format, one chunk per datapoint plus any
+/// header or closing chunk the format needs, so the export can be streamed.
+pub fn export_chunks(
+    datapoints: Vec<Datapoint>,
+    format: ExportFormat,
+) -> impl Iterator<Item = String> + Send {
+    let header = match format {
+        ExportFormat::Csv => Some("key,timestamp,data,tags\n".to_string()),
+        ExportFormat::Json => Some("[".to_string()),
+        ExportFormat::Jsonl => None,
+    };
+    let footer = match format {
+        ExportFormat::Json => Some("]\n".to_string()),
+        _ => None,
+    };
+    let records = datapoints
+        .into_iter()
+        .enumerate()
+        .map(move |(i, datapoint)| render(ExportRecord::from(datapoint), format, i == 0));
+    header.into_iter().chain(records).chain(footer)
+}
+
+fn render(record: ExportRecord, format: ExportFormat, first: bool) -> String {
+    match format {
+        ExportFormat::Csv => csv_line(record),
+        ExportFormat::Json => {
+            let separator = if first { "" } else { "," };
+            format!("{}{}", separator, json_line(&record))
+        }
+        ExportFormat::Jsonl => format!("{}\n", json_line(&record)),
+    }
+}
+
+fn json_line(record: &ExportRecord) -> String {
+    json::to_string(record).expect("export records always serialize")
+}
+
+fn csv_line(record: ExportRecord) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            record.key.to_string(),
+            record.timestamp,
+            record.data,
+            record.tags.join(" "),
+        ])
+        .expect("writing to memory cannot fail");
+    let bytes = writer.into_inner().expect("writing to memory cannot fail");
+    String::from_utf8(bytes).expect("csv records of strings are valid utf-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint_dto::tests::datapoints;
+
+    #[test]
+    fn csv_export_quotes_fields_and_uses_iso_timestamps() {
+        let exported: String = export_chunks(datapoints(), ExportFormat::Csv).collect();
+
+        assert_eq!(
+            exported,
+            "key,timestamp,data,tags\n\
+             1,2023-01-02T07:30:00+01:00,\"80kg, after run\",weight morning\n\
+             2,2023-01-03T07:45:00+01:00,79kg,weight\n"
+        );
+    }
+
+    #[test]
+    fn json_export_is_a_single_array() {
+        let exported: String = export_chunks(datapoints(), ExportFormat::Json).collect();
+
+        let parsed: json::Value = json::from_str(&exported).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+        assert_eq!(parsed[1]["timestamp"], "2023-01-03T07:45:00+01:00");
+        assert_eq!(parsed[0]["tags"][1], "morning");
+    }
+
+    #[test]
+    fn empty_json_export_is_an_empty_array() {
+        let exported: String = export_chunks(Vec::new(), ExportFormat::Json).collect();
+
+        assert_eq!(exported, "[]\n");
+    }
+
+    #[test]
+    fn jsonl_export_has_one_object_per_line() {
+        let exported: Vec<String> = export_chunks(datapoints(), ExportFormat::Jsonl).collect();
+
+        assert_eq!(exported.len(), 2);
+        assert!(exported.iter().all(|line| line.ends_with('\n')));
+        let parsed: json::Value = json::from_str(&exported[0]).unwrap();
+        assert_eq!(parsed["key"], 1);
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        assert_eq!(ExportFormat::parse("jsonl"), Some(ExportFormat::Jsonl));
+        assert_eq!(ExportFormat::parse("xml"), None);
+    }
+}
@@ -0,0 +1,37 @@
+use domain::csvimport::RowError;
+use rocket::serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct RowErrorDTO {
+    row: u64,
+    message: String,
+}
+
+impl From<&RowError> for RowErrorDTO {
+    fn from(error: &RowError) -> RowErrorDTO {
+        RowErrorDTO {
+            row: error.get_row(),
+            message: error.get_message().to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportReport {
+    parsed: usize,
+    imported: usize,
+    errors: Vec<RowErrorDTO>,
+}
+
+impl ImportReport {
+    pub fn new(parsed: usize, imported: usize, errors: &[RowError]) -> ImportReport {
+        ImportReport {
+            parsed,
+            imported,
+            errors: errors.iter().map(RowErrorDTO::from).collect(),
+        }
+    }
+}
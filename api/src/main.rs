@@ -1,64 +1,511 @@
+mod api_doc;
+mod api_error;
+mod api_token_dto;
+mod auth;
+mod backup_archive;
+mod chart_dto;
 mod datapoint_dto;
+mod event_bus;
+mod export_format;
+mod import_dto;
+mod pagination;
+mod plot_cache;
+mod plot_options_form;
+mod revision_dto;
 mod summary_dto;
+mod webhook_dispatch;
+mod webhook_dto;
 
-use crate::datapoint_dto::{dto_vec_from, DatapointDTO};
+use crate::api_doc::ApiDoc;
+use crate::api_error::{default_catcher, ApiError, ApiResult};
+use crate::api_token_dto::{ApiTokenDTO, CreatedApiTokenDTO};
+use crate::auth::{session_cookie, AdminAccess, AuthenticatedUser, WriteAccess, SESSION_COOKIE};
+use crate::backup_archive::{read_archive, write_archive};
+use crate::chart_dto::ChartDTO;
+use crate::datapoint_dto::{dto_vec_from, DatapointDTO, TrashedDatapointDTO};
+use crate::event_bus::EventBus;
+use crate::export_format::{export_chunks, ExportFormat};
+use crate::import_dto::ImportReport;
+use crate::pagination::{DatapointPage, PagedDatapoints, Pagination, MAX_PER_PAGE};
+use crate::plot_cache::PlotCache;
+use crate::plot_options_form::{plot_options, PlotOptionsForm};
+use crate::revision_dto::RevisionDTO;
 use crate::summary_dto::SummaryDTO;
-use chrono::NaiveDateTime;
-use domain::datastore::Datastore;
+use crate::webhook_dispatch::dispatch_webhooks;
+use crate::webhook_dto::{DeliveryDTO, WebhookDTO, WebhookPayload};
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use domain::apitoken::{check_token_name, TokenScope};
+use domain::backup::RestoreMode;
+use domain::csvimport::{parse_csv, ColumnMapping};
+use domain::datapoint::{parse_lines, Datapoint, EntryTimezone};
+use domain::healthimport::{parse_health_export, HealthSource, RecordType, TagMapping};
+use domain::linearfunction::LinearFunction;
 use domain::plotter::categorical::categorical_plot;
-use domain::plotter::scatterplot::{predictionplot, scatterplot};
+use domain::plotter::scatterplot::{prediction_chart, predictionplot, scatter_chart, scatterplot};
+use domain::plotter::util::PlotError;
+use domain::querypage::{Cursor, PageRequest, SortOrder};
+use domain::queryresult::QueryResult;
 use domain::stats::model_fit::linear_regression;
 use domain::stats::stats::compare;
+use domain::user::RegistrationError;
+use domain::webhook::{check_webhook_url, WebhookTrigger};
 use persistence::dbmanager::DBManager;
-use rocket::fs::{relative, FileServer};
-use rocket::http::Status;
+use persistence::repository::Repository;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Cookie, CookieJar, Status};
 use rocket::response::status;
+use rocket::response::stream::{Event, EventStream, TextStream};
 use rocket::serde::{json::Json, Deserialize, Serialize};
-use rocket::State;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Shutdown, State};
+use std::env;
+use std::sync::Arc;
+use utoipa::{OpenApi, ToSchema};
 
 #[macro_use]
 extern crate rocket;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct Form<'a> {
     #[serde(rename = "fieldInput")]
     value: &'a str,
+    /// The timezone the entry was logged in, as an IANA name such as `Europe/Amsterdam` or
+    /// a UTC offset such as `-05:00`. Defaults to the timezone of the server.
+    timezone: Option<&'a str>,
 }
 
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct TagEdit<'a> {
+    tags: &'a str,
+    add: bool,
+}
+
+/// Lists the datapoints matching the query `q`, one page at a time. `sort` is `date`
+/// (the default) or `key`, prefixed with `-` for descending order. Pages are counted
+/// from `cursor` when one is given.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "One page of matching datapoints", body = DatapointPage),
+        (status = 400, description = "A parameter is invalid", body = ApiError)
+    )
+)]
+#[get("/datapoints?<q>&<page>&<per_page>&<sort>&<cursor>")]
+fn list_datapoints(
+    q: Option<&str>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    sort: Option<&str>,
+    cursor: Option<&str>,
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointPage> {
+    let pagination = Pagination::new(page, per_page).ok_or_else(|| {
+        ApiError::bad_request(
+            "invalid_pagination",
+            format!(
+                "page must be at least 1 and per_page between 1 and {}",
+                MAX_PER_PAGE
+            ),
+        )
+    })?;
+    let request = pagination.to_request(parse_sort(sort, "date")?, parse_cursor(cursor)?);
+    let query_page = repository.query_page(user.id(), q.unwrap_or(""), &request);
+    Ok(Json(DatapointPage::new(query_page, pagination)))
+}
+
+#[utoipa::path(
+    request_body = Form,
+    responses(
+        (status = 201, description = "The stored datapoint", body = DatapointDTO),
+        (status = 400, description = "The timezone is invalid", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[post("/datapoints", format = "application/json", data = "<form_input>")]
+async fn create_datapoint(
+    form_input: Json<Form<'_>>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<status::Created<Json<DatapointDTO>>, ApiError> {
+    let timezone = entry_timezone(form_input.timezone)?;
+    let created = add_entry(repository, user.id(), form_input.value, timezone).await?;
+    let location = format!("/api/datapoints/{}", created.get_key());
+    Ok(status::Created::new(location).body(Json(DatapointDTO::from(created))))
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "The datapoint", body = DatapointDTO),
+        (status = 404, description = "No datapoint has the key", body = ApiError)
+    )
+)]
+#[get("/datapoints/<key>")]
+fn get_datapoint(
+    key: u64,
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointDTO> {
+    let datapoint = repository
+        .get_by_key(user.id(), vec![key])
+        .into_iter()
+        .next()
+        .ok_or_else(|| datapoint_not_found(key))?;
+    Ok(Json(DatapointDTO::from(datapoint)))
+}
+
+/// Replaces the datapoint with the entry parsed from `fieldInput`.
+#[utoipa::path(
+    request_body = Form,
+    responses(
+        (status = 200, description = "The replaced datapoint", body = DatapointDTO),
+        (status = 400, description = "The timezone is invalid", body = ApiError),
+        (status = 404, description = "No datapoint has the key", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[put(
+    "/datapoints/<key>",
+    format = "application/json",
+    data = "<form_input>"
+)]
+async fn replace_datapoint(
+    key: u64,
+    form_input: Json<Form<'_>>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointDTO> {
+    let timezone = entry_timezone(form_input.timezone)?;
+    let updated = update_entry(repository, user.id(), form_input.value, key, timezone).await?;
+    Ok(Json(DatapointDTO::from(updated)))
+}
+
+/// Adds or removes tags on a single datapoint, like `/batchedit` does for several.
+#[utoipa::path(
+    request_body = TagEdit,
+    responses(
+        (status = 200, description = "The edited datapoint", body = DatapointDTO),
+        (status = 404, description = "No datapoint has the key", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[patch("/datapoints/<key>", format = "application/json", data = "<tag_edit>")]
+async fn patch_datapoint(
+    key: u64,
+    tag_edit: Json<TagEdit<'_>>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointDTO> {
+    ensure_exists(repository, user.id(), key)?;
+    let edited = edit_tags(
+        repository,
+        user.id(),
+        tag_edit.tags,
+        vec![key],
+        tag_edit.add,
+    )
+    .await?;
+    Ok(Json(DatapointDTO::from(edited[0].clone())))
+}
+
+/// Moves the datapoint to the trash.
+#[utoipa::path(
+    responses(
+        (status = 204, description = "The datapoint was moved to the trash"),
+        (status = 404, description = "No datapoint has the key", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[delete("/datapoints/<key>")]
+async fn delete_datapoint(
+    key: u64,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<Status, ApiError> {
+    trash_entry(repository, user.id(), key).await?;
+    Ok(Status::NoContent)
+}
+
+/// Alias of `POST /api/datapoints`, kept for existing clients.
+#[utoipa::path(
+    request_body = Form,
+    responses(
+        (status = 200, description = "The entry was stored"),
+        (status = 400, description = "The timezone is invalid", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
 #[post("/input", format = "application/json", data = "<form_input>")]
 async fn input(
     form_input: Json<Form<'_>>,
-    datastorage: &State<Datastore>,
-    dbmanager: &State<DBManager>,
-) -> Status {
-    let new_datapoint = datastorage.add_datapoint(form_input.value);
-    match dbmanager.insert_datapoint(new_datapoint).await {
-        true => Status::Ok,
-        false => Status::InternalServerError,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<Status, ApiError> {
+    let timezone = entry_timezone(form_input.timezone)?;
+    add_entry(repository, user.id(), form_input.value, timezone).await?;
+    Ok(Status::Ok)
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct LineResult {
+    line: usize,
+    key: Option<u64>,
+    error: Option<String>,
+}
+
+/// Adds one entry per line of the input. Entries are only stored when every line is valid,
+/// and then all in one transaction.
+#[utoipa::path(
+    request_body = Form,
+    responses(
+        (status = 200, description = "The key stored for every line", body = [LineResult]),
+        (status = 400, description = "The timezone is invalid", body = ApiError),
+        (status = 422, description = "Some lines could not be parsed; details holds every line", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[post("/bulkinput", format = "application/json", data = "<form_input>")]
+async fn bulkinput(
+    form_input: Json<Form<'_>>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<LineResult>> {
+    let parsed = parse_lines(form_input.value, entry_timezone(form_input.timezone)?);
+    let lines: Vec<usize> = parsed.iter().map(|(line, _)| *line).collect();
+    if parsed.iter().any(|(_, result)| result.is_err()) {
+        let results = parsed
+            .into_iter()
+            .map(|(line, result)| LineResult {
+                line,
+                key: None,
+                error: result.err(),
+            })
+            .collect::<Vec<LineResult>>();
+        return Err(
+            ApiError::unprocessable("invalid_lines", "some lines could not be parsed")
+                .with_details(results),
+        );
+    }
+    let datapoints = parsed
+        .into_iter()
+        .filter_map(|(_, result)| result.ok())
+        .collect();
+    match repository.import_datapoints(user.id(), datapoints).await {
+        Some(imported) => {
+            let results = lines
+                .into_iter()
+                .zip(imported)
+                .map(|(line, datapoint)| LineResult {
+                    line,
+                    key: Some(datapoint.get_key()),
+                    error: None,
+                })
+                .collect();
+            Ok(Json(results))
+        }
+        None => Err(ApiError::storage_failure("storing the entries failed")),
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct UpdateForm<'a> {
     #[serde(rename = "fieldInput")]
     value: &'a str,
     key: u64,
+    /// The timezone the entry was logged in, see `Form`.
+    timezone: Option<&'a str>,
 }
 
+/// Alias of `PUT /api/datapoints/<key>`, kept for existing clients.
+#[utoipa::path(
+    request_body = UpdateForm,
+    responses(
+        (status = 200, description = "The replaced datapoint", body = DatapointDTO),
+        (status = 400, description = "The timezone is invalid", body = ApiError),
+        (status = 404, description = "No datapoint has the key", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
 #[post("/update", format = "application/json", data = "<form_input>")]
 async fn update(
     form_input: Json<UpdateForm<'_>>,
-    datastorage: &State<Datastore>,
-    dbmanager: &State<DBManager>,
-) -> status::Custom<Json<DatapointDTO>> {
-    let updated_datapoint = datastorage.update_datapoint(form_input.value, form_input.key);
-    dbmanager.update_datapoint(updated_datapoint.clone()).await;
-    status::Custom(Status::Ok, Json(DatapointDTO::from(updated_datapoint)))
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointDTO> {
+    let timezone = entry_timezone(form_input.timezone)?;
+    let updated = update_entry(
+        repository,
+        user.id(),
+        form_input.value,
+        form_input.key,
+        timezone,
+    )
+    .await?;
+    Ok(Json(DatapointDTO::from(updated)))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct MappingForm<'a> {
+    date: &'a str,
+    time: Option<&'a str>,
+    value: Option<&'a str>,
+    text: Option<&'a str>,
+    tags: Option<&'a str>,
+}
+
+impl MappingForm<'_> {
+    fn to_mapping(&self) -> ColumnMapping {
+        let mut mapping = ColumnMapping::new(self.date);
+        if let Some(time) = self.time {
+            mapping = mapping.with_time(time);
+        }
+        if let Some(value) = self.value {
+            mapping = mapping.with_value(value);
+        }
+        if let Some(text) = self.text {
+            mapping = mapping.with_text(text);
+        }
+        if let Some(tags) = self.tags {
+            mapping = mapping.with_tags(tags);
+        }
+        mapping
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct ImportRequest<'a> {
+    csv: String,
+    #[serde(borrow)]
+    mapping: MappingForm<'a>,
+    #[serde(rename = "dryRun")]
+    dry_run: bool,
+}
+
+/// Imports CSV rows as datapoints. A dry run only reports what would be imported; a real
+/// import refuses the whole file while any row fails to parse.
+#[utoipa::path(
+    request_body = ImportRequest,
+    responses(
+        (status = 200, description = "What was, or for a dry run would be, imported", body = ImportReport),
+        (status = 422, description = "Some rows could not be parsed; details holds the report", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[post("/import", format = "application/json", data = "<import_request>")]
+async fn import(
+    import_request: Json<ImportRequest<'_>>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<ImportReport> {
+    let import = parse_csv(&import_request.csv, &import_request.mapping.to_mapping());
+    let parsed = import.get_datapoints().len();
+    if import_request.dry_run {
+        return Ok(Json(ImportReport::new(parsed, 0, import.get_errors())));
+    }
+    if !import.is_clean() {
+        return Err(
+            ApiError::unprocessable("invalid_rows", "some rows could not be parsed")
+                .with_details(ImportReport::new(parsed, 0, import.get_errors())),
+        );
+    }
+    match repository
+        .import_datapoints(user.id(), import.into_datapoints())
+        .await
+    {
+        Some(imported) => Ok(Json(ImportReport::new(parsed, imported.len(), &[]))),
+        None => Err(ApiError::storage_failure(
+            "storing the imported rows failed",
+        )),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct HealthImportReport {
+    parsed: usize,
+    imported: usize,
+    duplicates: usize,
+}
+
+/// Imports an Apple Health `export.xml` or a Google Fit Takeout JSON file. The tags given
+/// to each record type can be overridden with the `weight`, `steps`, `heartrate` and
+/// `sleep` parameters.
+#[utoipa::path(
+    request_body(content = String, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "What was imported and what was skipped as a duplicate", body = HealthImportReport),
+        (status = 400, description = "A parameter is invalid", body = ApiError),
+        (status = 413, description = "The export is too large", body = ApiError),
+        (status = 422, description = "The export could not be parsed", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+#[post(
+    "/import/health?<source>&<weight>&<steps>&<heartrate>&<sleep>",
+    data = "<export>"
+)]
+async fn import_health(
+    source: &str,
+    weight: Option<&str>,
+    steps: Option<&str>,
+    heartrate: Option<&str>,
+    sleep: Option<&str>,
+    export: Data<'_>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<HealthImportReport> {
+    let source = HealthSource::parse(source).ok_or_else(|| {
+        ApiError::bad_request("invalid_source", "source must be 'apple' or 'googlefit'")
+    })?;
+    let mut mapping = TagMapping::new();
+    for (record_type, tags) in [
+        (RecordType::Weight, weight),
+        (RecordType::Steps, steps),
+        (RecordType::HeartRate, heartrate),
+        (RecordType::Sleep, sleep),
+    ] {
+        if let Some(tags) = tags {
+            mapping = mapping.with_tags(record_type, tags);
+        }
+    }
+    let export = export
+        .open(512.mebibytes())
+        .into_string()
+        .await
+        .map_err(|error| ApiError::bad_request("unreadable_body", error.to_string()))?;
+    if !export.is_complete() {
+        return Err(ApiError::new(
+            Status::PayloadTooLarge,
+            "payload_too_large",
+            "export is too large",
+        ));
+    }
+    let records = parse_health_export(source, &export)
+        .map_err(|message| ApiError::unprocessable("invalid_export", message))?;
+    let parsed = records.len();
+    match repository
+        .import_health_records(user.id(), records, &mapping)
+        .await
+    {
+        Some(imported) => Ok(Json(HealthImportReport {
+            parsed,
+            imported: imported.len(),
+            duplicates: parsed - imported.len(),
+        })),
+        None => Err(ApiError::storage_failure(
+            "storing the imported records failed",
+        )),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct EditRequest<'a> {
     tags: &'a str,
@@ -66,33 +513,81 @@ struct EditRequest<'a> {
     keys: Vec<u64>,
 }
 
+#[utoipa::path(
+    request_body = EditRequest,
+    responses(
+        (status = 200, description = "The edited datapoints", body = [DatapointDTO]),
+        (status = 500, description = "Storing the edit failed; details holds the unchanged datapoints", body = ApiError)
+    )
+)]
 #[post("/batchedit", format = "application/json", data = "<edit_request>")]
 async fn batchedit(
     edit_request: Json<EditRequest<'_>>,
-    datastorage: &State<Datastore>,
-    dbmanager: &State<DBManager>,
-) -> status::Custom<Json<Vec<DatapointDTO>>> {
-    let datapoints = datastorage.batch_operation(
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<DatapointDTO>> {
+    let edited = edit_tags(
+        repository,
+        user.id(),
         edit_request.tags,
         edit_request.keys.clone(),
         edit_request.add,
-    );
-    for datapoint in datapoints.clone() {
-        if !dbmanager.update_datapoint(datapoint).await {
-            return status::Custom(Status::InternalServerError, Json(dto_vec_from(datapoints)));
-        }
+    )
+    .await?;
+    Ok(Json(dto_vec_from(edited)))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct StepsRequest {
+    steps: usize,
+}
+
+#[utoipa::path(
+    request_body = StepsRequest,
+    responses(
+        (status = 200, description = "The datapoints the undone edits touched", body = [DatapointDTO]),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[post("/undo", format = "application/json", data = "<steps_request>")]
+async fn undo(
+    steps_request: Json<StepsRequest>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<DatapointDTO>> {
+    match repository.undo(user.id(), steps_request.steps).await {
+        Some(datapoints) => Ok(Json(dto_vec_from(datapoints))),
+        None => Err(ApiError::storage_failure("storing the undo failed")),
     }
+}
 
-    return status::Custom(Status::Ok, Json(dto_vec_from(datapoints)));
+#[utoipa::path(
+    request_body = StepsRequest,
+    responses(
+        (status = 200, description = "The datapoints the redone edits touched", body = [DatapointDTO]),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
+#[post("/redo", format = "application/json", data = "<steps_request>")]
+async fn redo(
+    steps_request: Json<StepsRequest>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<DatapointDTO>> {
+    match repository.redo(user.id(), steps_request.steps).await {
+        Some(datapoints) => Ok(Json(dto_vec_from(datapoints))),
+        None => Err(ApiError::storage_failure("storing the redo failed")),
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct DeleteKey {
     value: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct DeleteConfirmation {
     #[serde(rename = "datastoreDeleted")]
@@ -101,124 +596,474 @@ struct DeleteConfirmation {
     database_deleted: bool,
 }
 
+/// Alias of `DELETE /api/datapoints/<key>`, kept for existing clients.
+#[utoipa::path(
+    request_body = DeleteKey,
+    responses(
+        (status = 200, description = "The datapoint was moved to the trash", body = DeleteConfirmation),
+        (status = 404, description = "No datapoint has the key", body = ApiError),
+        (status = 500, description = "Storing the change failed", body = ApiError)
+    )
+)]
 #[post("/delete", format = "application/json", data = "<key>")]
 async fn delete(
     key: Json<DeleteKey>,
-    datastorage: &State<Datastore>,
-    dbmanager: &State<DBManager>,
-) -> status::Custom<Json<DeleteConfirmation>> {
-    let datastore_deleted = match datastorage.delete_datapoint(key.value) {
-        Some(_) => true,
-        None => false,
-    };
-    let database_deleted = dbmanager.delete_datapoint(key.value).await;
-    if database_deleted && database_deleted {
-        return status::Custom(
-            Status::Ok,
-            Json(DeleteConfirmation {
-                datastore_deleted,
-                database_deleted,
-            }),
-        );
-    } else {
-        return status::Custom(
-            Status::InternalServerError,
-            Json(DeleteConfirmation {
-                datastore_deleted,
-                database_deleted,
-            }),
-        );
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DeleteConfirmation> {
+    trash_entry(repository, user.id(), key.value).await?;
+    Ok(Json(DeleteConfirmation {
+        datastore_deleted: true,
+        database_deleted: true,
+    }))
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every datapoint in the trash", body = [TrashedDatapointDTO])
+    )
+)]
+#[get("/trash")]
+async fn trash(
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> Json<Vec<TrashedDatapointDTO>> {
+    repository.purge_expired_trash(user.id()).await;
+    let trashed = repository
+        .retrieve_trash(user.id())
+        .into_iter()
+        .map(TrashedDatapointDTO::from)
+        .collect();
+    Json(trashed)
+}
+
+#[utoipa::path(
+    request_body = DeleteKey,
+    responses(
+        (status = 200, description = "The restored datapoint", body = DatapointDTO),
+        (status = 404, description = "No datapoint in the trash has the key", body = ApiError)
+    )
+)]
+#[post("/restore", format = "application/json", data = "<key>")]
+async fn restore(
+    key: Json<DeleteKey>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointDTO> {
+    match repository.restore_datapoint(user.id(), key.value).await {
+        Some(restored) => Ok(Json(DatapointDTO::from(restored))),
+        None => Err(ApiError::not_found(
+            "not_in_trash",
+            format!("no datapoint with key {} could be restored", key.value),
+        )),
     }
 }
 
-#[post("/query", format = "application/json", data = "<form_input>")]
-fn query(form_input: Json<Form<'_>>, datastorage: &State<Datastore>) -> Json<Vec<DatapointDTO>> {
-    let queryresult = datastorage.query(form_input.value);
-    Json(dto_vec_from(queryresult.get_datapoints()))
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Groups of datapoints sharing data and tags", body = Vec<Vec<DatapointDTO>>),
+        (status = 400, description = "A parameter is invalid", body = ApiError)
+    )
+)]
+#[get("/duplicates?<window>")]
+fn duplicates(
+    window: Option<i64>,
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<Vec<DatapointDTO>>> {
+    let window = duplicate_window(window)?;
+    let groups = repository
+        .find_duplicates(user.id(), window)
+        .into_iter()
+        .map(dto_vec_from)
+        .collect();
+    Ok(Json(groups))
 }
 
-#[derive(Deserialize)]
+/// The widest window, in seconds, within which datapoints count as duplicates: 30 days.
+const MAX_DUPLICATE_WINDOW: i64 = 30 * 24 * 60 * 60;
+
+/// The window `/duplicates` looks within, five minutes unless given.
+fn duplicate_window(window: Option<i64>) -> Result<Duration, ApiError> {
+    let window = window.unwrap_or(300);
+    if !(0..=MAX_DUPLICATE_WINDOW).contains(&window) {
+        return Err(ApiError::bad_request(
+            "invalid_window",
+            format!("window must be from 0 to {} seconds", MAX_DUPLICATE_WINDOW),
+        ));
+    }
+    Ok(Duration::seconds(window))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct MergeRequest {
+    keep: u64,
+    remove: Vec<u64>,
+}
+
+#[utoipa::path(
+    request_body = MergeRequest,
+    responses(
+        (status = 200, description = "The kept datapoint", body = DatapointDTO),
+        (status = 422, description = "The datapoints are not duplicates", body = ApiError)
+    )
+)]
+#[post("/merge", format = "application/json", data = "<merge_request>")]
+async fn merge(
+    merge_request: Json<MergeRequest>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointDTO> {
+    match repository
+        .merge_duplicates(user.id(), merge_request.keep, merge_request.remove.clone())
+        .await
+    {
+        Some(kept) => Ok(Json(DatapointDTO::from(kept))),
+        None => Err(ApiError::unprocessable(
+            "not_duplicates",
+            "the removed datapoints must exist and share data and tags with the kept one",
+        )),
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every revision of the datapoint, oldest first", body = [RevisionDTO]),
+        (status = 500, description = "Loading the history failed", body = ApiError)
+    )
+)]
+#[get("/history/<key>")]
+async fn history(
+    key: u64,
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<RevisionDTO>> {
+    match repository.history(user.id(), key).await {
+        Some(revisions) => Ok(Json(revisions.into_iter().map(RevisionDTO::from).collect())),
+        None => Err(ApiError::storage_failure("loading the history failed")),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct RevertRequest {
+    key: u64,
+    revision: u64,
+}
+
+#[utoipa::path(
+    request_body = RevertRequest,
+    responses(
+        (status = 200, description = "The reverted datapoint", body = DatapointDTO),
+        (status = 404, description = "The datapoint has no such revision", body = ApiError)
+    )
+)]
+#[post("/revert", format = "application/json", data = "<revert_request>")]
+async fn revert(
+    revert_request: Json<RevertRequest>,
+    user: WriteAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<DatapointDTO> {
+    match repository
+        .revert_datapoint(user.id(), revert_request.key, revert_request.revision)
+        .await
+    {
+        Some(reverted) => Ok(Json(DatapointDTO::from(reverted))),
+        None => Err(ApiError::not_found(
+            "revision_not_found",
+            format!(
+                "datapoint {} has no revision {}",
+                revert_request.key, revert_request.revision
+            ),
+        )),
+    }
+}
+
+/// Returns every matching datapoint oldest first unless `limit`, `offset`, `cursor` or
+/// `sort` ask for less; use `sort=-date` for newest first.
+#[utoipa::path(
+    request_body = Form,
+    responses(
+        (status = 200, description = "The matching datapoints", body = [DatapointDTO], headers(("X-Total-Count" = usize, description = "How many datapoints match"), ("X-Next-Cursor" = String, description = "The cursor of the next page, while one follows"))),
+        (status = 400, description = "A parameter is invalid", body = ApiError)
+    )
+)]
+#[post(
+    "/query?<limit>&<offset>&<cursor>&<sort>",
+    format = "application/json",
+    data = "<form_input>"
+)]
+fn query(
+    form_input: Json<Form<'_>>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    cursor: Option<&str>,
+    sort: Option<&str>,
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> Result<PagedDatapoints, ApiError> {
+    let request = query_request(limit, offset, cursor, sort)?;
+    Ok(PagedDatapoints(repository.query_page(
+        user.id(),
+        form_input.value,
+        &request,
+    )))
+}
+
+/// The page `/query` is asked for, in date order unless `sort` says otherwise.
+fn query_request(
+    limit: Option<usize>,
+    offset: Option<usize>,
+    cursor: Option<&str>,
+    sort: Option<&str>,
+) -> Result<PageRequest, ApiError> {
+    let mut request = PageRequest::new(parse_sort(sort, "date")?).with_offset(offset.unwrap_or(0));
+    if let Some(limit) = limit {
+        if limit == 0 || limit > MAX_PER_PAGE {
+            return Err(ApiError::bad_request(
+                "invalid_limit",
+                format!("limit must be between 1 and {}", MAX_PER_PAGE),
+            ));
+        }
+        request = request.with_limit(limit);
+    }
+    if let Some(cursor) = parse_cursor(cursor)? {
+        request = request.with_cursor(cursor);
+    }
+    Ok(request)
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "The matching datapoints in the requested format", body = String, content_type = ["text/csv", "application/json", "application/jsonl"]),
+        (status = 400, description = "A parameter is invalid", body = ApiError)
+    )
+)]
+#[get("/export?<query>&<format>")]
+fn export(
+    query: Option<&str>,
+    format: &str,
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> Result<(ContentType, TextStream![String]), ApiError> {
+    let format = ExportFormat::parse(format).ok_or_else(|| {
+        ApiError::bad_request("invalid_format", "format must be 'csv', 'json' or 'jsonl'")
+    })?;
+    let datapoints = repository
+        .query(user.id(), query.unwrap_or(""))
+        .get_datapoints();
+    let chunks = export_chunks(datapoints, format);
+    Ok((
+        format.content_type(),
+        TextStream! {
+            for chunk in chunks {
+                yield chunk;
+            }
+        },
+    ))
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "A JSON-lines backup archive", body = String, content_type = "application/jsonl"),
+        (status = 500, description = "Loading the backup failed", body = ApiError)
+    )
+)]
+#[get("/backup")]
+async fn backup(
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<(ContentType, TextStream![String]), ApiError> {
+    let backup = repository
+        .backup(user.id())
+        .await
+        .ok_or_else(|| ApiError::storage_failure("loading the backup failed"))?;
+    let lines = write_archive(backup, Utc::now());
+    Ok((
+        ContentType::new("application", "jsonl"),
+        TextStream! {
+            for line in lines {
+                yield line;
+            }
+        },
+    ))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct RestoreReport {
+    datapoints: usize,
+    trashed: usize,
+    revisions: usize,
+}
+
+#[utoipa::path(
+    request_body(content = String, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "How much was restored", body = RestoreReport),
+        (status = 400, description = "A parameter is invalid", body = ApiError),
+        (status = 413, description = "The archive is too large", body = ApiError),
+        (status = 422, description = "The archive could not be read", body = ApiError),
+        (status = 500, description = "Restoring the backup failed", body = ApiError)
+    )
+)]
+#[post("/backup/restore?<mode>", data = "<archive>")]
+async fn restore_backup(
+    mode: &str,
+    archive: Data<'_>,
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<RestoreReport> {
+    let mode = RestoreMode::parse(mode).ok_or_else(|| {
+        ApiError::bad_request("invalid_mode", "mode must be 'replace' or 'merge'")
+    })?;
+    let archive = archive
+        .open(64.mebibytes())
+        .into_string()
+        .await
+        .map_err(|error| ApiError::bad_request("unreadable_body", error.to_string()))?;
+    if !archive.is_complete() {
+        return Err(ApiError::new(
+            Status::PayloadTooLarge,
+            "payload_too_large",
+            "archive is too large",
+        ));
+    }
+    let backup = read_archive(&archive)
+        .map_err(|message| ApiError::unprocessable("invalid_archive", message))?;
+    match repository.restore_backup(user.id(), backup, mode).await {
+        Some(restored) => Ok(Json(RestoreReport {
+            datapoints: restored.get_datapoints().len(),
+            trashed: restored.get_trash().len(),
+            revisions: restored.get_revisions().len(),
+        })),
+        None => Err(ApiError::storage_failure("restoring the backup failed")),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct PlotRequest<'a> {
     #[serde(rename = "fieldInput")]
     value: &'a str,
     #[serde(rename = "withRegression")]
     with_regression: bool,
+    /// `png`, the default, or `svg`.
+    #[serde(borrow)]
+    format: Option<&'a str>,
+    options: Option<PlotOptionsForm>,
 }
 
-#[derive(Serialize)]
+/// A rendered plot, fetched from `/plot/<filename>` by the user it was rendered for.
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct Image {
     filename: String,
 }
 
+#[utoipa::path(
+    request_body = PlotRequest,
+    responses(
+        (status = 200, description = "The generated plot", body = Image),
+        (status = 400, description = "The format or plot options are invalid", body = ApiError),
+        (status = 422, description = "The query has no numeric data", body = ApiError),
+        (status = 500, description = "Rendering the plot failed", body = ApiError)
+    )
+)]
 #[post("/plot", format = "application/json", data = "<form_input>")]
-fn plot(
+async fn plot(
     form_input: Json<PlotRequest<'_>>,
-    datastorage: &State<Datastore>,
-) -> status::Custom<Json<Image>> {
-    let queryresult = datastorage.query(form_input.value);
-    match scatterplot(&queryresult, form_input.with_regression) {
-        Ok(filename) => status::Custom(Status::Ok, Json(Image { filename })),
-        Err(_) => status::Custom(
-            Status::InternalServerError,
-            Json(Image {
-                filename: "nodice".to_string(),
-            }),
-        ),
-    }
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+    plot_cache: &State<PlotCache>,
+) -> ApiResult<Image> {
+    let options = plot_options(form_input.format, form_input.options.as_ref())?;
+    let extension = options.get_format().extension();
+    let queryresult = repository.query(user.id(), form_input.value);
+    let with_regression = form_input.with_regression;
+    let image = render(move || scatterplot(&queryresult, with_regression, &options))
+        .await
+        .map_err(plot_failure)?;
+    Ok(Json(Image {
+        filename: plot_cache.store(user.id(), image, extension),
+    }))
+}
+
+/// Serves a plot rendered by `/api/plot`, `/api/comparison` or `/api/predict` to the user it
+/// was rendered for, for as long as it is cached.
+#[get("/<filename>")]
+fn plot_image(
+    filename: &str,
+    user: AuthenticatedUser,
+    plot_cache: &State<PlotCache>,
+) -> Option<(ContentType, Vec<u8>)> {
+    let (_, extension) = filename.rsplit_once('.')?;
+    let content_type = ContentType::from_extension(extension)?;
+    Some((content_type, plot_cache.get(user.id(), filename)?))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct CompareForm<'a> {
     #[serde(borrow)]
     #[serde(rename = "fieldInputs")]
     queries: Vec<&'a str>,
+    /// `png`, the default, or `svg`.
+    #[serde(borrow)]
+    format: Option<&'a str>,
+    options: Option<PlotOptionsForm>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct ComparisonResults {
     filename: String,
     summaries: Vec<SummaryDTO>,
 }
 
+#[utoipa::path(
+    request_body = CompareForm,
+    responses(
+        (status = 200, description = "The generated plot and a summary per query", body = ComparisonResults),
+        (status = 400, description = "The format or plot options are invalid", body = ApiError),
+        (status = 422, description = "A query has no numeric data", body = ApiError),
+        (status = 500, description = "Rendering the plot failed", body = ApiError)
+    )
+)]
 #[post("/comparison", format = "application/json", data = "<form_input>")]
-fn comparison(
+async fn comparison(
     form_input: Json<CompareForm<'_>>,
-    datastorage: &State<Datastore>,
-) -> status::Custom<Json<ComparisonResults>> {
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+    plot_cache: &State<PlotCache>,
+) -> ApiResult<ComparisonResults> {
+    let options = plot_options(form_input.format, form_input.options.as_ref())?;
+    let extension = options.get_format().extension();
     let mut collector = Vec::new();
     for query in form_input.queries.clone() {
-        collector.push(datastorage.query(query));
+        collector.push(repository.query(user.id(), query));
     }
-    let filename = match categorical_plot(&collector) {
-        Some(filename) => filename,
-        None => {
-            return status::Custom(
-                Status::Ok,
-                Json(ComparisonResults {
-                    filename: "none".to_string(),
-                    summaries: Vec::new(),
-                }),
-            )
-        }
-    };
     let summaries = compare(&collector)
         .into_iter()
         .map(|summary| SummaryDTO::from(summary))
         .collect();
-    status::Custom(
-        Status::Ok,
-        Json(ComparisonResults {
-            filename,
-            summaries,
-        }),
-    )
+    let image = render(move || categorical_plot(&collector, &options))
+        .await
+        .map_err(|error| match error {
+            PlotError::NoNumericData => ApiError::unprocessable(
+                "no_numeric_data",
+                "every query must match datapoints with numeric values",
+            ),
+            error => plot_failure(error),
+        })?;
+    Ok(Json(ComparisonResults {
+        filename: plot_cache.store(user.id(), image, extension),
+        summaries,
+    }))
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct Prediction {
     filename: String,
@@ -227,77 +1072,157 @@ struct Prediction {
     will_intercept: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct PredictionForm<'a> {
     #[serde(rename = "fieldInput")]
     query: &'a str,
     #[serde(rename = "targetGoal")]
     goal: f64,
+    /// `png`, the default, or `svg`.
+    #[serde(borrow)]
+    format: Option<&'a str>,
+    options: Option<PlotOptionsForm>,
 }
 
+#[utoipa::path(
+    request_body = PredictionForm,
+    responses(
+        (status = 200, description = "When the goal will be reached", body = Prediction),
+        (status = 400, description = "The format or plot options are invalid", body = ApiError),
+        (status = 422, description = "The query has no numeric data or the goal can't be reached", body = ApiError),
+        (status = 500, description = "Rendering the plot failed", body = ApiError)
+    )
+)]
 #[post("/predict", format = "application/json", data = "<form_input>")]
-fn predict(
+async fn predict(
     form_input: Json<PredictionForm<'_>>,
-    datastorage: &State<Datastore>,
-) -> status::Custom<Json<Prediction>> {
-    let queryresult = datastorage.query(form_input.query);
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+    plot_cache: &State<PlotCache>,
+) -> ApiResult<Prediction> {
+    let options = plot_options(form_input.format, form_input.options.as_ref())?;
+    let queryresult = repository.query(user.id(), form_input.query);
+    let (linear_function, predicted_datetime, will_intercept) =
+        predict_goal(&queryresult, form_input.goal)?;
 
-    let data = match queryresult.get_date_numeric_data() {
-        Some(data) => data,
-        None => {
-            return status::Custom(
-                Status::InternalServerError,
-                Json(Prediction {
-                    filename: "".to_string(),
-                    prediction: "float parsing failure".to_string(),
-                    will_intercept: false,
-                }),
+    let mut filename = "".to_string();
+    if will_intercept {
+        let extension = options.get_format().extension();
+        let goal = form_input.goal;
+        let image = render(move || {
+            predictionplot(
+                &queryresult,
+                linear_function,
+                goal,
+                predicted_datetime,
+                &options,
             )
-        }
-    };
-    let latest_date = data[data.len() - 1].0;
-    let linear_function = linear_regression(data, 50);
-    let prediction: i64 = linear_function.apply_inverse(form_input.goal) as i64;
-    let predicted_datetime = NaiveDateTime::from_timestamp_opt(prediction, 0).unwrap();
+        })
+        .await
+        .map_err(plot_failure)?;
+        filename = plot_cache.store(user.id(), image, extension);
+    }
 
-    let mut will_intercept = true;
+    Ok(Json(Prediction {
+        filename,
+        prediction: predicted_datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        will_intercept,
+    }))
+}
 
-    if predicted_datetime < latest_date.naive_local() {
-        will_intercept = false;
-    }
+/// Fits a line through the numeric datapoints of the query and returns it with the date
+/// it reaches the goal, and whether that date lies after the latest datapoint.
+fn predict_goal(
+    queryresult: &QueryResult,
+    goal: f64,
+) -> Result<(LinearFunction, DateTime<Local>, bool), ApiError> {
+    let data = queryresult.get_date_numeric_data().ok_or_else(|| {
+        ApiError::unprocessable(
+            "no_numeric_data",
+            "the query must match datapoints with numeric values",
+        )
+    })?;
+    let latest_date = data[data.len() - 1].0;
+    let linear_function = linear_regression(data, 50);
+    let prediction: i64 = linear_function.apply_inverse(goal) as i64;
+    let predicted_datetime = match Utc.timestamp_opt(prediction, 0).single() {
+        Some(datetime) => datetime.with_timezone(&Local),
+        None => {
+            return Err(ApiError::unprocessable(
+                "prediction_out_of_range",
+                "the goal is not reached at a representable date",
+            ))
+        }
+    };
+    let will_intercept = predicted_datetime >= latest_date;
+    Ok((linear_function, predicted_datetime, will_intercept))
+}
 
-    let filename: String;
-    if will_intercept {
-        filename = predictionplot(
-            &queryresult,
-            linear_function,
-            form_input.goal,
-            predicted_datetime,
-        );
-    } else {
-        filename = "".to_string();
-    }
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct ChartRequest<'a> {
+    #[serde(rename = "fieldInput")]
+    query: &'a str,
+    #[serde(default)]
+    #[serde(rename = "withRegression")]
+    with_regression: bool,
+    /// Extends the fitted line to the date this goal is predicted to be reached.
+    #[serde(rename = "targetGoal")]
+    goal: Option<f64>,
+}
 
-    status::Custom(
-        Status::Ok,
-        Json(Prediction {
-            filename,
-            prediction: predicted_datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
-            will_intercept,
-        }),
+#[utoipa::path(
+    request_body = ChartRequest,
+    responses(
+        (status = 200, description = "The series and axes the plot of the query draws", body = ChartDTO),
+        (status = 422, description = "The query has no numeric data or the goal won't be reached", body = ApiError)
     )
+)]
+#[post("/chart", format = "application/json", data = "<form_input>")]
+fn chart(
+    form_input: Json<ChartRequest<'_>>,
+    user: AuthenticatedUser,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<ChartDTO> {
+    let queryresult = repository.query(user.id(), form_input.query);
+    let chart = match form_input.goal {
+        Some(goal) => {
+            let (linear_function, predicted_datetime, will_intercept) =
+                predict_goal(&queryresult, goal)?;
+            if !will_intercept {
+                return Err(ApiError::unprocessable(
+                    "goal_not_reached",
+                    "the goal is not reached after the latest datapoint",
+                ));
+            }
+            prediction_chart(&queryresult, linear_function, goal, predicted_datetime)
+        }
+        None => scatter_chart(&queryresult, form_input.with_regression),
+    };
+    let chart = chart.ok_or_else(|| {
+        ApiError::unprocessable(
+            "no_numeric_data",
+            "the query must match datapoints with numeric values",
+        )
+    })?;
+    Ok(Json(ChartDTO::from(chart)))
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct Tag {
     tag: String,
 }
 
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every tag in use", body = [Tag])
+    )
+)]
 #[get("/tags")]
-fn tags(datastorage: &State<Datastore>) -> Json<Vec<Tag>> {
-    let tags = datastorage.retrieve_taglist();
+fn tags(user: AuthenticatedUser, repository: &State<Arc<Repository>>) -> Json<Vec<Tag>> {
+    let tags = repository.retrieve_taglist(user.id());
     let mut tag_objects: Vec<Tag> = Vec::new();
     for tag in tags {
         tag_objects.push(Tag { tag });
@@ -305,17 +1230,660 @@ fn tags(datastorage: &State<Datastore>) -> Json<Vec<Tag>> {
     Json(tag_objects)
 }
 
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct Credentials<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct Account {
+    id: u64,
+    username: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct SessionToken {
+    token: String,
+    #[serde(rename = "expiresAt")]
+    expires_at: String,
+}
+
+/// Creates an account. The first account created takes over the datapoints stored
+/// before there were accounts.
+#[utoipa::path(
+    request_body = Credentials,
+    security(()),
+    responses(
+        (status = 201, description = "The new account", body = Account),
+        (status = 400, description = "The username or password can't be used", body = ApiError),
+        (status = 409, description = "The username is taken", body = ApiError),
+        (status = 500, description = "Storing the account failed", body = ApiError)
+    )
+)]
+#[post("/users", format = "application/json", data = "<credentials>")]
+async fn register(
+    credentials: Json<Credentials<'_>>,
+    repository: &State<Arc<Repository>>,
+) -> Result<status::Custom<Json<Account>>, ApiError> {
+    let user = repository
+        .register(credentials.username, credentials.password)
+        .await
+        .map_err(|error| match error {
+            RegistrationError::Invalid(message) => {
+                ApiError::bad_request("invalid_account", message)
+            }
+            RegistrationError::UsernameTaken => ApiError::new(
+                Status::Conflict,
+                "username_taken",
+                format!("username {} is taken", credentials.username),
+            ),
+            RegistrationError::Failed => ApiError::storage_failure("storing the account failed"),
+        })?;
+    Ok(status::Custom(
+        Status::Created,
+        Json(Account {
+            id: user.get_id(),
+            username: user.get_username().to_string(),
+        }),
+    ))
+}
+
+/// Logs in, returning a token to send as `Authorization: Bearer <token>`. The token is
+/// also set as an HttpOnly `session` cookie for the web client.
+#[utoipa::path(
+    request_body = Credentials,
+    security(()),
+    responses(
+        (status = 200, description = "The token of the new session", body = SessionToken),
+        (status = 401, description = "The username or password is wrong", body = ApiError)
+    )
+)]
+#[post("/sessions", format = "application/json", data = "<credentials>")]
+async fn login(
+    credentials: Json<Credentials<'_>>,
+    cookies: &CookieJar<'_>,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<SessionToken> {
+    let (_, token, session) = repository
+        .login(credentials.username, credentials.password)
+        .await
+        .ok_or_else(|| {
+            ApiError::new(
+                Status::Unauthorized,
+                "invalid_credentials",
+                "the username or password is wrong",
+            )
+        })?;
+    cookies.add(session_cookie(token.clone()));
+    Ok(Json(SessionToken {
+        token,
+        expires_at: session.get_expires_at().to_rfc3339(),
+    }))
+}
+
+/// Ends the session the request is made with.
+#[utoipa::path(
+    responses(
+        (status = 204, description = "The session has ended"),
+        (status = 401, description = "The request carries no valid session", body = ApiError)
+    )
+)]
+#[delete("/sessions")]
+async fn logout(
+    user: AuthenticatedUser,
+    cookies: &CookieJar<'_>,
+    repository: &State<Arc<Repository>>,
+) -> Status {
+    repository.logout(user.token()).await;
+    cookies.remove(Cookie::named(SESSION_COOKIE));
+    Status::NoContent
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct TokenRequest<'a> {
+    name: &'a str,
+    scope: &'a str,
+}
+
+/// Creates an API token for scripts and integrations. `scope` is `read`, `write` or
+/// `admin`. The token is only returned here; store it, as it can't be retrieved later.
+#[utoipa::path(
+    request_body = TokenRequest,
+    responses(
+        (status = 201, description = "The new token", body = CreatedApiTokenDTO),
+        (status = 400, description = "The name or scope is invalid", body = ApiError),
+        (status = 403, description = "The request is not made with admin scope", body = ApiError),
+        (status = 500, description = "Storing the token failed", body = ApiError)
+    )
+)]
+#[post("/tokens", format = "application/json", data = "<token_request>")]
+async fn create_token(
+    token_request: Json<TokenRequest<'_>>,
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<status::Custom<Json<CreatedApiTokenDTO>>, ApiError> {
+    check_token_name(token_request.name)
+        .map_err(|message| ApiError::bad_request("invalid_name", message))?;
+    let scope = TokenScope::parse(token_request.scope).ok_or_else(|| {
+        ApiError::bad_request("invalid_scope", "scope must be 'read', 'write' or 'admin'")
+    })?;
+    let (api_token, token) = repository
+        .create_api_token(user.id(), token_request.name, scope)
+        .await
+        .ok_or_else(|| ApiError::storage_failure("storing the token failed"))?;
+    Ok(status::Custom(
+        Status::Created,
+        Json(CreatedApiTokenDTO::new(api_token, token)),
+    ))
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every API token of the user, without the tokens themselves", body = [ApiTokenDTO]),
+        (status = 403, description = "The request is not made with admin scope", body = ApiError),
+        (status = 500, description = "Loading the tokens failed", body = ApiError)
+    )
+)]
+#[get("/tokens")]
+async fn tokens(
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<ApiTokenDTO>> {
+    match repository.api_tokens(user.id()).await {
+        Some(api_tokens) => Ok(Json(
+            api_tokens.into_iter().map(ApiTokenDTO::from).collect(),
+        )),
+        None => Err(ApiError::storage_failure("loading the tokens failed")),
+    }
+}
+
+/// Revokes the API token, which fails with 401 Unauthorized from then on.
+#[utoipa::path(
+    responses(
+        (status = 204, description = "The token was revoked"),
+        (status = 403, description = "The request is not made with admin scope", body = ApiError),
+        (status = 404, description = "The user has no token with the id", body = ApiError)
+    )
+)]
+#[delete("/tokens/<id>")]
+async fn revoke_token(
+    id: u64,
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<Status, ApiError> {
+    if !repository.revoke_api_token(user.id(), id).await {
+        return Err(ApiError::not_found(
+            "token_not_found",
+            format!("no token with id {}", id),
+        ));
+    }
+    Ok(Status::NoContent)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct WebhookRequest<'a> {
+    url: &'a str,
+    #[serde(default)]
+    query: &'a str,
+    trigger: &'a str,
+    threshold: Option<f64>,
+}
+
+/// Registers a URL to be POSTed a JSON payload when the user's datapoints matching `query`
+/// change. With `trigger` `created` it fires for every new matching datapoint; with
+/// `threshold` it fires when a new or edited matching datapoint's value is on the other
+/// side of `threshold` than the matching datapoint before it. The url must not point to a
+/// loopback, private or link-local address. Failed deliveries are retried with growing
+/// delays.
+#[utoipa::path(
+    request_body = WebhookRequest,
+    responses(
+        (status = 201, description = "The new webhook", body = WebhookDTO),
+        (status = 400, description = "The url or trigger is invalid", body = ApiError),
+        (status = 403, description = "The request is not made with admin scope", body = ApiError),
+        (status = 500, description = "Storing the webhook failed", body = ApiError)
+    )
+)]
+#[post("/webhooks", format = "application/json", data = "<webhook_request>")]
+async fn create_webhook(
+    webhook_request: Json<WebhookRequest<'_>>,
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<status::Custom<Json<WebhookDTO>>, ApiError> {
+    check_webhook_url(webhook_request.url)
+        .map_err(|message| ApiError::bad_request("invalid_url", message))?;
+    let trigger = WebhookTrigger::parse(webhook_request.trigger, webhook_request.threshold)
+        .ok_or_else(|| {
+            ApiError::bad_request(
+                "invalid_trigger",
+                "trigger must be 'created', or 'threshold' with a numeric threshold",
+            )
+        })?;
+    let webhook = repository
+        .create_webhook(
+            user.id(),
+            webhook_request.url,
+            webhook_request.query,
+            trigger,
+        )
+        .await
+        .ok_or_else(|| ApiError::storage_failure("storing the webhook failed"))?;
+    Ok(status::Custom(
+        Status::Created,
+        Json(WebhookDTO::from(webhook)),
+    ))
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every webhook of the user", body = [WebhookDTO]),
+        (status = 403, description = "The request is not made with admin scope", body = ApiError),
+        (status = 500, description = "Loading the webhooks failed", body = ApiError)
+    )
+)]
+#[get("/webhooks")]
+async fn webhooks(
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<WebhookDTO>> {
+    match repository.webhooks(user.id()).await {
+        Some(webhooks) => Ok(Json(webhooks.into_iter().map(WebhookDTO::from).collect())),
+        None => Err(ApiError::storage_failure("loading the webhooks failed")),
+    }
+}
+
+/// Deletes the webhook along with its delivery log. Deliveries still pending are dropped.
+#[utoipa::path(
+    responses(
+        (status = 204, description = "The webhook was deleted"),
+        (status = 403, description = "The request is not made with admin scope", body = ApiError),
+        (status = 404, description = "The user has no webhook with the id", body = ApiError)
+    )
+)]
+#[delete("/webhooks/<id>")]
+async fn delete_webhook(
+    id: u64,
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> Result<Status, ApiError> {
+    if !repository.delete_webhook(user.id(), id).await {
+        return Err(webhook_not_found(id));
+    }
+    Ok(Status::NoContent)
+}
+
+/// The delivery log of the webhook, newest first.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every delivery queued for the webhook", body = [DeliveryDTO]),
+        (status = 403, description = "The request is not made with admin scope", body = ApiError),
+        (status = 404, description = "The user has no webhook with the id", body = ApiError)
+    )
+)]
+#[get("/webhooks/<id>/deliveries")]
+async fn deliveries(
+    id: u64,
+    user: AdminAccess,
+    repository: &State<Arc<Repository>>,
+) -> ApiResult<Vec<DeliveryDTO>> {
+    match repository.deliveries(user.id(), id).await {
+        Some(deliveries) => Ok(Json(
+            deliveries.into_iter().map(DeliveryDTO::from).collect(),
+        )),
+        None => Err(webhook_not_found(id)),
+    }
+}
+
+fn webhook_not_found(id: u64) -> ApiError {
+    ApiError::not_found("webhook_not_found", format!("no webhook with id {}", id))
+}
+
+/// Streams the changes to the user's datapoints as server-sent events named `created`,
+/// `updated` or `deleted`, each holding the datapoint. A `resync` event means changes were
+/// missed and the client should query again, as it should after restoring a backup, which
+/// sends no events.
+#[utoipa::path(
+    responses(
+        (status = 200, description = "A stream of datapoint changes", body = String, content_type = "text/event-stream")
+    )
+)]
+#[get("/events")]
+fn events(
+    user: AuthenticatedUser,
+    event_bus: &State<EventBus>,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let owner = user.id();
+    let mut receiver = event_bus.subscribe();
+    EventStream! {
+        loop {
+            let (event_owner, event) = select! {
+                received = receiver.recv() => match received {
+                    Ok(received) => received,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => {
+                        yield Event::data("").event("resync");
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+            if event_owner == owner {
+                let kind = event.get_kind().as_str();
+                yield Event::json(&DatapointDTO::from(event.into_datapoint())).event(kind);
+            }
+        }
+    }
+}
+
+/// The OpenAPI 3 document describing every route under `/api`.
+#[utoipa::path(
+    security(()),
+    responses((status = 200, description = "The OpenAPI document", body = Object))
+)]
+#[get("/openapi.json")]
+fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Renders a plot on a blocking thread, as large images take long enough to hold up every
+/// other request otherwise.
+async fn render(
+    plot: impl FnOnce() -> Result<Vec<u8>, PlotError> + Send + 'static,
+) -> Result<Vec<u8>, PlotError> {
+    rocket::tokio::task::spawn_blocking(plot)
+        .await
+        .unwrap_or_else(|error| Err(PlotError::Render(error.to_string())))
+}
+
+/// A query without numbers to plot is the client's to fix. Any other failure is the
+/// server's, and its reason is not passed on.
+fn plot_failure(error: PlotError) -> ApiError {
+    match error {
+        PlotError::NoNumericData => ApiError::unprocessable("no_numeric_data", error.to_string()),
+        PlotError::Render(_) => ApiError::render_failure("rendering the plot failed"),
+    }
+}
+
+fn parse_sort(sort: Option<&str>, default: &str) -> Result<SortOrder, ApiError> {
+    SortOrder::parse(sort.unwrap_or(default)).ok_or_else(|| {
+        ApiError::bad_request(
+            "invalid_sort",
+            "sort must be 'date', '-date', 'key' or '-key'",
+        )
+    })
+}
+
+fn parse_cursor(cursor: Option<&str>) -> Result<Option<Cursor>, ApiError> {
+    match cursor {
+        Some(cursor) => Cursor::decode(cursor)
+            .map(Some)
+            .ok_or_else(|| ApiError::bad_request("invalid_cursor", "cursor is not valid")),
+        None => Ok(None),
+    }
+}
+
+async fn add_entry(
+    repository: &Repository,
+    owner: u64,
+    input: &str,
+    timezone: EntryTimezone,
+) -> Result<Datapoint, ApiError> {
+    repository
+        .add_datapoint(owner, input, timezone)
+        .await
+        .ok_or_else(|| ApiError::storage_failure("storing the entry failed"))
+}
+
+async fn update_entry(
+    repository: &Repository,
+    owner: u64,
+    input: &str,
+    key: u64,
+    timezone: EntryTimezone,
+) -> Result<Datapoint, ApiError> {
+    ensure_exists(repository, owner, key)?;
+    repository
+        .update_datapoint(owner, input, key, timezone)
+        .await
+        .ok_or_else(|| ApiError::storage_failure("storing the update failed"))
+}
+
+async fn edit_tags(
+    repository: &Repository,
+    owner: u64,
+    tags: &str,
+    keys: Vec<u64>,
+    add: bool,
+) -> Result<Vec<Datapoint>, ApiError> {
+    match repository
+        .batch_operation(owner, tags, keys.clone(), add)
+        .await
+    {
+        Some(edited) => Ok(edited),
+        None => {
+            let unchanged = repository.get_by_key(owner, keys);
+            Err(ApiError::storage_failure("storing the edit failed")
+                .with_details(dto_vec_from(unchanged)))
+        }
+    }
+}
+
+async fn trash_entry(repository: &Repository, owner: u64, key: u64) -> Result<(), ApiError> {
+    repository.purge_expired_trash(owner).await;
+    ensure_exists(repository, owner, key)?;
+    repository
+        .delete_datapoint(owner, key)
+        .await
+        .map(|_| ())
+        .ok_or_else(|| ApiError::storage_failure("deleting the datapoint failed"))
+}
+
+fn entry_timezone(timezone: Option<&str>) -> Result<EntryTimezone, ApiError> {
+    match timezone {
+        Some(timezone) => EntryTimezone::parse(timezone).ok_or_else(|| {
+            ApiError::bad_request(
+                "invalid_timezone",
+                format!("'{}' is neither a timezone nor a UTC offset", timezone),
+            )
+        }),
+        None => Ok(EntryTimezone::default()),
+    }
+}
+
+fn ensure_exists(repository: &Repository, owner: u64, key: u64) -> Result<(), ApiError> {
+    if repository.get_by_key(owner, vec![key]).is_empty() {
+        return Err(datapoint_not_found(key));
+    }
+    Ok(())
+}
+
+fn datapoint_not_found(key: u64) -> ApiError {
+    ApiError::not_found(
+        "datapoint_not_found",
+        format!("no datapoint with key {}", key),
+    )
+}
+
+/// How often datapoints kept in the trash past the retention period are purged.
+const TRASH_PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// The longest retention period `TRASH_RETENTION_DAYS` may set, about a hundred years.
+const MAX_TRASH_RETENTION_DAYS: i64 = 36500;
+
+/// The retention period set by `TRASH_RETENTION_DAYS`, 30 days when it is not set.
+fn trash_retention(days: Option<&str>) -> Result<Duration, String> {
+    let days = match days {
+        Some(days) => days
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|days| (0..=MAX_TRASH_RETENTION_DAYS).contains(days)),
+        None => Some(30),
+    };
+    days.map(Duration::days).ok_or_else(|| {
+        format!(
+            "TRASH_RETENTION_DAYS must be a whole number of days from 0 to {}",
+            MAX_TRASH_RETENTION_DAYS
+        )
+    })
+}
+
+/// Purges the expired trash of every user now and then every `TRASH_PURGE_INTERVAL`, so
+/// it is purged whether or not anyone looks at it.
+async fn purge_trash_periodically(repository: Arc<Repository>) {
+    let mut purge = rocket::tokio::time::interval(TRASH_PURGE_INTERVAL);
+    loop {
+        purge.tick().await;
+        repository.purge_all_expired_trash().await;
+    }
+}
+
+fn api_routes() -> Vec<rocket::Route> {
+    routes![
+        list_datapoints,
+        create_datapoint,
+        get_datapoint,
+        replace_datapoint,
+        patch_datapoint,
+        delete_datapoint,
+        input,
+        bulkinput,
+        import,
+        import_health,
+        query,
+        export,
+        backup,
+        restore_backup,
+        plot,
+        tags,
+        predict,
+        chart,
+        update,
+        delete,
+        trash,
+        restore,
+        duplicates,
+        merge,
+        history,
+        revert,
+        batchedit,
+        undo,
+        redo,
+        comparison,
+        register,
+        login,
+        logout,
+        create_token,
+        tokens,
+        revoke_token,
+        create_webhook,
+        webhooks,
+        delete_webhook,
+        deliveries,
+        events,
+        openapi
+    ]
+}
+
 #[launch]
 async fn rocket() -> _ {
-    let dbmanager = DBManager::new().await;
-    let datapoints = dbmanager.load_datapoints().await;
-    let datastore = Datastore::from(datapoints);
+    let trash_retention = trash_retention(env::var("TRASH_RETENTION_DAYS").ok().as_deref())
+        .unwrap_or_else(|message| {
+            eprintln!("{}", message);
+            std::process::exit(1)
+        });
+    let event_bus = EventBus::new();
+    let repository = Repository::load(DBManager::new().await)
+        .await
+        .with_trash_retention(trash_retention)
+        .with_listener(event_bus.listener())
+        .with_webhook_payloads(WebhookPayload::render);
+    let repository = Arc::new(repository);
+    rocket::tokio::spawn(dispatch_webhooks(repository.clone()));
+    rocket::tokio::spawn(purge_trash_periodically(repository.clone()));
     rocket::build()
-        .mount(
-            "/api",
-            routes![input, query, plot, tags, predict, update, delete, batchedit, comparison],
-        )
-        .mount("/plot", FileServer::from(relative!("../generated")))
-        .manage(datastore)
-        .manage(dbmanager)
+        .mount("/api", api_routes())
+        .register("/api", catchers![default_catcher])
+        .mount("/plot", routes![plot_image])
+        .manage(repository)
+        .manage(PlotCache::new())
+        .manage(event_bus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::datastore::Datastore;
+    use rocket::serde::json;
+
+    #[test]
+    fn trash_retention_defaults_to_30_days_and_refuses_negative_huge_or_garbled_values() {
+        assert_eq!(trash_retention(None), Ok(Duration::days(30)));
+        assert_eq!(trash_retention(Some("7")), Ok(Duration::days(7)));
+        assert_eq!(trash_retention(Some("0")), Ok(Duration::days(0)));
+        assert!(trash_retention(Some("-1")).is_err());
+        assert!(trash_retention(Some("a week")).is_err());
+        assert_eq!(trash_retention(Some("36500")), Ok(Duration::days(36500)));
+        assert!(trash_retention(Some("36501")).is_err());
+        assert!(trash_retention(Some("9223372036854775807")).is_err());
+    }
+
+    #[test]
+    fn duplicate_windows_beyond_30_days_or_below_zero_are_refused() {
+        assert_eq!(duplicate_window(None).unwrap(), Duration::minutes(5));
+        assert_eq!(
+            duplicate_window(Some(MAX_DUPLICATE_WINDOW)).unwrap(),
+            Duration::days(30)
+        );
+        for window in [-1, MAX_DUPLICATE_WINDOW + 1, i64::MAX] {
+            let error = json::to_value(duplicate_window(Some(window)).unwrap_err()).unwrap();
+            assert_eq!(error["code"], "invalid_window");
+        }
+    }
+
+    #[test]
+    fn query_without_sort_returns_datapoints_oldest_first() {
+        let datastore = Datastore::new();
+        for date in ["2023-01-03", "2023-01-01", "2023-01-02"] {
+            datastore.add_datapoint(&format!("80 +weight +DATE:{} +TIME:12-00-00", date));
+        }
+        let request = query_request(None, None, None, None).unwrap();
+
+        let keys: Vec<u64> = datastore
+            .query_page("weight", &request)
+            .into_datapoints()
+            .iter()
+            .map(|datapoint| datapoint.get_key())
+            .collect();
+
+        assert_eq!(keys, vec![2, 3, 1]);
+    }
+
+    #[rocket::async_test]
+    async fn rendered_plots_come_back_and_renderer_crashes_fail_as_render_errors() {
+        assert_eq!(render(|| Ok(vec![1, 2, 3])).await.unwrap(), vec![1, 2, 3]);
+        let crashed = render(|| panic!("renderer crashed")).await;
+        assert!(matches!(crashed, Err(PlotError::Render(_))));
+    }
+
+    #[test]
+    fn only_plots_without_numeric_data_are_the_clients_to_fix() {
+        let no_data = json::to_value(plot_failure(PlotError::NoNumericData)).unwrap();
+        let render = json::to_value(plot_failure(PlotError::Render(
+            "font not found".to_string(),
+        )))
+        .unwrap();
+
+        assert_eq!(no_data["code"], "no_numeric_data");
+        assert_eq!(no_data["message"], "no numeric datapoints match the query");
+        assert_eq!(render["code"], "render_failed");
+        assert!(!render["message"].as_str().unwrap().contains("font"));
+    }
 }
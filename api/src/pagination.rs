@@ -0,0 +1,121 @@
+use crate::datapoint_dto::{dto_vec_from, DatapointDTO};
+use domain::querypage::{Cursor, PageRequest, QueryPage, SortOrder};
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::{json::Json, Serialize};
+use utoipa::ToSchema;
+
+pub const DEFAULT_PER_PAGE: usize = 50;
+pub const MAX_PER_PAGE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pagination {
+    page: usize,
+    per_page: usize,
+}
+
+impl Pagination {
+    /// Pages are counted from 1. Returns `None` for page 0 or a page size outside
+    /// `1..=MAX_PER_PAGE`.
+    pub fn new(page: Option<usize>, per_page: Option<usize>) -> Option<Pagination> {
+        let page = page.unwrap_or(1);
+        let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if page == 0 || per_page == 0 || per_page > MAX_PER_PAGE {
+            return None;
+        }
+        Some(Pagination { page, per_page })
+    }
+
+    /// Pages are counted from the cursor when one is given.
+    pub fn to_request(self, order: SortOrder, cursor: Option<Cursor>) -> PageRequest {
+        let request = PageRequest::new(order)
+            .with_offset((self.page - 1).saturating_mul(self.per_page))
+            .with_limit(self.per_page);
+        match cursor {
+            Some(cursor) => request.with_cursor(cursor),
+            None => request,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DatapointPage {
+    datapoints: Vec<DatapointDTO>,
+    page: usize,
+    #[serde(rename = "perPage")]
+    per_page: usize,
+    total: usize,
+    #[serde(rename = "nextCursor")]
+    next_cursor: Option<String>,
+}
+
+impl DatapointPage {
+    pub fn new(query_page: QueryPage, pagination: Pagination) -> DatapointPage {
+        let total = query_page.get_total();
+        let next_cursor = query_page.get_next_cursor().map(Cursor::encode);
+        DatapointPage {
+            datapoints: dto_vec_from(query_page.into_datapoints()),
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+/// One page of a query as a plain array, with the total in `X-Total-Count` and, while
+/// more datapoints follow, the cursor for the next page in `X-Next-Cursor`.
+pub struct PagedDatapoints(pub QueryPage);
+
+impl<'r> Responder<'r, 'static> for PagedDatapoints {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let total = self.0.get_total();
+        let next_cursor = self.0.get_next_cursor().map(Cursor::encode);
+        let mut response = Json(dto_vec_from(self.0.into_datapoints())).respond_to(request)?;
+        response.set_raw_header("X-Total-Count", total.to_string());
+        if let Some(next_cursor) = next_cursor {
+            response.set_raw_header("X-Next-Cursor", next_cursor);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint_dto::tests::datapoints;
+    use rocket::serde::json;
+
+    #[test]
+    fn pagination_rejects_page_zero_and_oversized_pages() {
+        assert_eq!(Pagination::new(Some(0), None), None);
+        assert_eq!(Pagination::new(None, Some(0)), None);
+        assert_eq!(Pagination::new(None, Some(MAX_PER_PAGE + 1)), None);
+        assert!(Pagination::new(None, None).is_some());
+    }
+
+    #[test]
+    fn page_body_holds_the_requested_slice_the_total_and_a_cursor() {
+        let pagination = Pagination::new(Some(1), Some(1)).unwrap();
+        let query_page = pagination
+            .to_request(SortOrder::DateDescending, None)
+            .apply(datapoints());
+        let body = json::to_value(DatapointPage::new(query_page, pagination)).unwrap();
+
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["perPage"], 1);
+        assert_eq!(body["datapoints"].as_array().unwrap().len(), 1);
+        assert_eq!(body["datapoints"][0]["key"], 2);
+        let cursor = Cursor::decode(body["nextCursor"].as_str().unwrap()).unwrap();
+
+        let query_page = pagination
+            .to_request(SortOrder::DateDescending, Some(cursor))
+            .apply(datapoints());
+        let body = json::to_value(DatapointPage::new(query_page, pagination)).unwrap();
+
+        assert_eq!(body["datapoints"].as_array().unwrap().len(), 1);
+        assert_eq!(body["datapoints"][0]["key"], 1);
+        assert_eq!(body["nextCursor"], json::Value::Null);
+    }
+}
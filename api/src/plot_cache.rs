@@ -0,0 +1,112 @@
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// How many bytes of images are kept before the least recently used ones are evicted.
+const CAPACITY: usize = 32 * 1024 * 1024;
+
+/// Rendered plots waiting to be fetched from `/plot/<filename>`. Images are named by the
+/// SHA-256 of their contents, so identical plots share one entry and names never collide,
+/// and are only served to the user they were rendered for.
+pub struct PlotCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+type Key = (u64, String);
+
+struct Entries {
+    images: HashMap<Key, Vec<u8>>,
+    recency: VecDeque<Key>,
+    size: usize,
+}
+
+impl Entries {
+    fn touch(&mut self, key: &Key) {
+        if let Some(position) = self.recency.iter().position(|used| used == key) {
+            let key = self.recency.remove(position).unwrap();
+            self.recency.push_back(key);
+        }
+    }
+}
+
+impl PlotCache {
+    pub fn new() -> PlotCache {
+        PlotCache::with_capacity(CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> PlotCache {
+        PlotCache {
+            capacity,
+            entries: Mutex::new(Entries {
+                images: HashMap::new(),
+                recency: VecDeque::new(),
+                size: 0,
+            }),
+        }
+    }
+
+    /// Keeps the image for the owner, returning the filename it is served under. The most
+    /// recent image is always kept, even when it alone exceeds the capacity.
+    pub fn store(&self, owner: u64, image: Vec<u8>, extension: &str) -> String {
+        let filename = format!("{:x}.{}", Sha256::digest(&image), extension);
+        let key = (owner, filename.clone());
+        let mut entries = self.entries.lock().unwrap();
+        if entries.images.contains_key(&key) {
+            entries.touch(&key);
+            return filename;
+        }
+        entries.size += image.len();
+        entries.images.insert(key.clone(), image);
+        entries.recency.push_back(key);
+        while entries.size > self.capacity && entries.recency.len() > 1 {
+            let evicted = entries.recency.pop_front().unwrap();
+            if let Some(image) = entries.images.remove(&evicted) {
+                entries.size -= image.len();
+            }
+        }
+        filename
+    }
+
+    pub fn get(&self, owner: u64, filename: &str) -> Option<Vec<u8>> {
+        let key = (owner, filename.to_string());
+        let mut entries = self.entries.lock().unwrap();
+        let image = entries.images.get(&key)?.clone();
+        entries.touch(&key);
+        Some(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_named_by_content_and_kept_per_owner() {
+        let cache = PlotCache::new();
+
+        let first = cache.store(1, vec![1, 2, 3], "png");
+        let again = cache.store(1, vec![1, 2, 3], "png");
+        let other = cache.store(1, vec![4, 5, 6], "png");
+
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        assert!(first.ends_with(".png") && first.len() == 64 + 4);
+        assert_eq!(cache.get(1, &first), Some(vec![1, 2, 3]));
+        assert_eq!(cache.get(2, &first), None);
+    }
+
+    #[test]
+    fn least_recently_used_images_are_evicted_beyond_the_capacity() {
+        let cache = PlotCache::with_capacity(6);
+        let first = cache.store(1, vec![1, 1, 1], "png");
+        let second = cache.store(1, vec![2, 2, 2], "png");
+        cache.get(1, &first);
+
+        let third = cache.store(1, vec![3, 3, 3], "png");
+
+        assert!(cache.get(1, &first).is_some());
+        assert_eq!(cache.get(1, &second), None);
+        assert!(cache.get(1, &third).is_some());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::api_error::ApiError;
+use domain::plotter::plotoptions::{PlotOptions, Theme};
+use domain::plotter::util::PlotFormat;
+use rocket::serde::Deserialize;
+use utoipa::ToSchema;
+
+/// How a plot is drawn. Anything left out keeps the look of the default plot: 640x480
+/// pixels in the dark theme.
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PlotOptionsForm {
+    width: Option<u32>,
+    height: Option<u32>,
+    /// `dark`, `light` or `colorblind`.
+    theme: Option<String>,
+    #[serde(rename = "markerSize")]
+    marker_size: Option<u32>,
+    #[serde(rename = "titleFontSize")]
+    title_font_size: Option<u32>,
+    #[serde(rename = "labelFontSize")]
+    label_font_size: Option<u32>,
+    /// Replaces the title generated from the query.
+    title: Option<String>,
+}
+
+impl PlotOptionsForm {
+    fn apply_to(&self, options: PlotOptions) -> Result<PlotOptions, ApiError> {
+        let (width, height) = options.get_size();
+        let mut options =
+            options.with_size(self.width.unwrap_or(width), self.height.unwrap_or(height));
+        if let Some(theme) = &self.theme {
+            let theme = Theme::parse(theme).ok_or_else(|| {
+                ApiError::bad_request(
+                    "invalid_theme",
+                    "theme must be 'dark', 'light' or 'colorblind'",
+                )
+            })?;
+            options = options.with_theme(theme);
+        }
+        if let Some(marker_size) = self.marker_size {
+            options = options.with_marker_size(marker_size);
+        }
+        if let Some(title_font_size) = self.title_font_size {
+            options = options.with_title_font_size(title_font_size);
+        }
+        if let Some(label_font_size) = self.label_font_size {
+            options = options.with_label_font_size(label_font_size);
+        }
+        if let Some(title) = &self.title {
+            options = options.with_title(title);
+        }
+        Ok(options)
+    }
+}
+
+/// The options a plot is asked for with: `format` is `png`, the default, or `svg`.
+pub fn plot_options(
+    format: Option<&str>,
+    form: Option<&PlotOptionsForm>,
+) -> Result<PlotOptions, ApiError> {
+    let format = match format {
+        Some(format) => PlotFormat::parse(format).ok_or_else(|| {
+            ApiError::bad_request("invalid_format", "format must be 'png' or 'svg'")
+        })?,
+        None => PlotFormat::default(),
+    };
+    let options = PlotOptions::default().with_format(format);
+    let options = match form {
+        Some(form) => form.apply_to(options)?,
+        None => options,
+    };
+    options
+        .check()
+        .map_err(|message| ApiError::bad_request("invalid_options", message))?;
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::serde_json;
+
+    fn form(json: &str) -> PlotOptionsForm {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn given_options_replace_the_defaults() {
+        let form =
+            form(r#"{"width": 1200, "theme": "colorblind", "markerSize": 3, "title": "Mine"}"#);
+
+        let options = plot_options(Some("svg"), Some(&form)).unwrap();
+
+        assert_eq!(options.get_format(), PlotFormat::Svg);
+        assert_eq!(options.get_size(), (1200, 480));
+        assert_eq!(options.get_theme(), Theme::ColorBlind);
+        assert_eq!(options.get_marker_size(), 3);
+        assert_eq!(options.get_title(), Some("Mine"));
+        assert_eq!(plot_options(None, None).unwrap(), PlotOptions::default());
+    }
+
+    #[test]
+    fn unknown_themes_and_out_of_bounds_options_are_bad_requests() {
+        for (format, json) in [
+            (None, r#"{"theme": "neon"}"#),
+            (None, r#"{"height": 10}"#),
+            (Some("gif"), "{}"),
+        ] {
+            let form = form(json);
+            assert!(plot_options(format, Some(&form)).is_err());
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use crate::datapoint_dto::DatapointDTO;
+use domain::revision::Revision;
+use rocket::serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct RevisionDTO {
+    id: u64,
+    key: u64,
+    kind: String,
+    #[serde(rename = "recordedAt")]
+    recorded_at: String,
+    before: Option<DatapointDTO>,
+    after: Option<DatapointDTO>,
+}
+
+impl From<Revision> for RevisionDTO {
+    fn from(revision: Revision) -> RevisionDTO {
+        RevisionDTO {
+            id: revision.get_id(),
+            key: revision.get_key(),
+            kind: revision.get_kind().as_str().to_string(),
+            recorded_at: revision.get_recorded_at().to_rfc3339(),
+            before: revision.get_before().cloned().map(DatapointDTO::from),
+            after: revision.get_after().cloned().map(DatapointDTO::from),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use domain::stats::summary::Summary;
 use rocket::serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct SummaryDTO {
     name: String,
@@ -0,0 +1,162 @@
+use domain::webhook::{is_public_address, Delivery};
+use futures::stream::{self, StreamExt};
+use persistence::repository::Repository;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use rocket::tokio::net::lookup_host;
+use rocket::tokio::select;
+use rocket::tokio::time::{interval, Duration};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// How often deliveries waiting for a retry are looked for.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a webhook may take to answer before the attempt counts as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many deliveries are attempted at the same time, so one slow webhook doesn't hold up
+/// the others.
+const CONCURRENT_DELIVERIES: usize = 16;
+
+/// Delivers the payloads the repository queues as changes set off webhooks, retrying
+/// failed ones as its retry policy says. Runs for as long as the server does.
+pub async fn dispatch_webhooks(repository: Arc<Repository>) {
+    let mut poll = interval(POLL_INTERVAL);
+    loop {
+        select! {
+            _ = repository.delivery_queued() => {}
+            _ = poll.tick() => {}
+        }
+        stream::iter(repository.due_deliveries().await)
+            .for_each_concurrent(CONCURRENT_DELIVERIES, |delivery| {
+                let repository = &repository;
+                async move {
+                    let result = deliver_to_public_host(&delivery).await;
+                    repository.record_delivery_attempt(delivery, result).await;
+                }
+            })
+            .await;
+    }
+}
+
+/// Delivers the payload unless the host of the URL resolves to an address on the server's
+/// own network. The request goes to the address that was checked, and redirects are not
+/// followed, so the host can't be pointed elsewhere after the check.
+async fn deliver_to_public_host(delivery: &Delivery) -> Result<u16, String> {
+    let client = public_client(delivery.get_url()).await?;
+    deliver(&client, delivery).await
+}
+
+async fn public_client(url: &str) -> Result<Client, String> {
+    let url = Url::parse(url).map_err(|error| error.to_string())?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| "url has no host".to_string())?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let client = Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(Policy::none());
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = match literal.parse::<IpAddr>() {
+        Ok(address) => vec![SocketAddr::new(address, port)],
+        Err(_) => lookup_host((host, port))
+            .await
+            .map_err(|error| error.to_string())?
+            .collect(),
+    };
+    let address = *addresses
+        .first()
+        .ok_or_else(|| format!("{} resolves to no address", host))?;
+    if addresses
+        .iter()
+        .any(|address| !is_public_address(address.ip()))
+    {
+        return Err(format!("{} resolves to a non-public address", host));
+    }
+    client
+        .resolve(host, address)
+        .build()
+        .map_err(|error| error.to_string())
+}
+
+/// POSTs the payload of the delivery, returning the HTTP status it was answered with, or
+/// why no answer came.
+pub async fn deliver(client: &Client, delivery: &Delivery) -> Result<u16, String> {
+    client
+        .post(delivery.get_url())
+        .header(CONTENT_TYPE, "application/json")
+        .body(delivery.get_payload().to_string())
+        .send()
+        .await
+        .map(|response| response.status().as_u16())
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use domain::webhook::{Webhook, WebhookTrigger};
+    use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use rocket::tokio::net::TcpListener;
+    use rocket::tokio::spawn;
+    use rocket::tokio::task::JoinHandle;
+
+    /// A local HTTP server answering one request with the status, handing back the request.
+    async fn stand_in(status: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !String::from_utf8_lossy(&request).contains("\"webhook\":true") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (url, server)
+    }
+
+    fn delivery_to(url: &str) -> Delivery {
+        let webhook = Webhook::new(1, 1, url, "", WebhookTrigger::Created, Utc::now());
+        Delivery::queue(&webhook, "{\"webhook\":true}", Utc::now())
+    }
+
+    #[rocket::async_test]
+    async fn payloads_are_posted_as_json_and_answered_with_a_status() {
+        let (url, server) = stand_in("200 OK").await;
+
+        let result = deliver(&Client::new(), &delivery_to(&url)).await;
+
+        assert_eq!(result, Ok(200));
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1"));
+        assert!(request.contains("content-type: application/json"));
+    }
+
+    #[rocket::async_test]
+    async fn error_statuses_and_unreachable_hosts_are_reported() {
+        let (url, server) = stand_in("500 Internal Server Error").await;
+
+        assert_eq!(deliver(&Client::new(), &delivery_to(&url)).await, Ok(500));
+        server.await.unwrap();
+        assert!(deliver(&Client::new(), &delivery_to(&url)).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn hosts_resolving_to_the_servers_own_network_are_not_delivered_to() {
+        let (url, _server) = stand_in("200 OK").await;
+        let on_localhost = url.replace("127.0.0.1", "localhost");
+
+        for url in [url.as_str(), on_localhost.as_str(), "http://[::1]/hook"] {
+            let result = deliver_to_public_host(&delivery_to(url)).await;
+            assert!(result.unwrap_err().contains("non-public address"));
+        }
+    }
+}
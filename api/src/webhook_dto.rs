@@ -0,0 +1,96 @@
+use crate::datapoint_dto::DatapointDTO;
+use domain::datapointevent::DatapointEvent;
+use domain::webhook::{Delivery, DeliveryStatus, Webhook};
+use rocket::serde::json;
+use rocket::serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookDTO {
+    id: u64,
+    url: String,
+    query: String,
+    trigger: String,
+    threshold: Option<f64>,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+}
+
+impl From<Webhook> for WebhookDTO {
+    fn from(webhook: Webhook) -> WebhookDTO {
+        WebhookDTO {
+            id: webhook.get_id(),
+            url: webhook.get_url().to_string(),
+            query: webhook.get_query().to_string(),
+            trigger: webhook.get_trigger().as_str().to_string(),
+            threshold: webhook.get_trigger().get_threshold(),
+            created_at: webhook.get_created_at().to_rfc3339(),
+        }
+    }
+}
+
+/// One entry of the delivery log of a webhook.
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DeliveryDTO {
+    id: u64,
+    status: String,
+    attempts: u32,
+    #[serde(rename = "nextAttemptAt")]
+    next_attempt_at: Option<String>,
+    #[serde(rename = "lastResult")]
+    last_result: Option<String>,
+    payload: String,
+}
+
+impl From<Delivery> for DeliveryDTO {
+    fn from(delivery: Delivery) -> DeliveryDTO {
+        let pending = delivery.get_status() == DeliveryStatus::Pending;
+        DeliveryDTO {
+            id: delivery.get_id(),
+            status: delivery.get_status().as_str().to_string(),
+            attempts: delivery.get_attempts(),
+            next_attempt_at: pending.then(|| delivery.get_next_attempt_at().to_rfc3339()),
+            last_result: delivery.get_last_result().map(|result| result.to_string()),
+            payload: delivery.get_payload().to_string(),
+        }
+    }
+}
+
+/// The JSON body POSTed to a webhook. `previousValue` is the value a threshold was crossed
+/// from.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookPayload {
+    #[serde(rename = "webhookId")]
+    webhook_id: u64,
+    event: String,
+    trigger: String,
+    threshold: Option<f64>,
+    #[serde(rename = "previousValue")]
+    previous_value: Option<f64>,
+    datapoint: DatapointDTO,
+}
+
+impl WebhookPayload {
+    pub fn new(webhook: &Webhook, event: &DatapointEvent, previous: Option<f64>) -> WebhookPayload {
+        WebhookPayload {
+            webhook_id: webhook.get_id(),
+            event: event.get_kind().as_str().to_string(),
+            trigger: webhook.get_trigger().as_str().to_string(),
+            threshold: webhook.get_trigger().get_threshold(),
+            previous_value: webhook.get_trigger().get_threshold().and(previous),
+            datapoint: DatapointDTO::from(event.get_datapoint().clone()),
+        }
+    }
+
+    /// The JSON of the payload, in the shape `Repository::with_webhook_payloads` asks for.
+    pub fn render(
+        webhook: &Webhook,
+        event: &DatapointEvent,
+        previous: Option<f64>,
+    ) -> Option<String> {
+        json::to_string(&WebhookPayload::new(webhook, event, previous)).ok()
+    }
+}
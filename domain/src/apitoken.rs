@@ -0,0 +1,185 @@
+use crate::user::{generate_token, hash_token};
+use chrono::{DateTime, Duration, Utc};
+
+/// Marks API tokens apart from session tokens, so they are looked up in the right place.
+pub const API_TOKEN_PREFIX: &str = "tapas_";
+
+/// What a token may do. Every scope also allows what the scopes before it allow.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum TokenScope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl TokenScope {
+    pub fn parse(scope: &str) -> Option<TokenScope> {
+        match scope {
+            "read" => Some(TokenScope::Read),
+            "write" => Some(TokenScope::Write),
+            "admin" => Some(TokenScope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+            TokenScope::Admin => "admin",
+        }
+    }
+
+    pub fn allows(&self, required: TokenScope) -> bool {
+        *self >= required
+    }
+}
+
+/// A long-lived token for scripts and integrations, acting for its user within its scope.
+/// Like session tokens, only the SHA-256 hash of the token itself is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiToken {
+    id: u64,
+    user_id: u64,
+    name: String,
+    scope: TokenScope,
+    token_hash: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn new(
+        id: u64,
+        user_id: u64,
+        name: &str,
+        scope: TokenScope,
+        token_hash: &str,
+        created_at: DateTime<Utc>,
+        last_used_at: Option<DateTime<Utc>>,
+    ) -> ApiToken {
+        ApiToken {
+            id,
+            user_id,
+            name: name.to_string(),
+            scope,
+            token_hash: token_hash.to_string(),
+            created_at,
+            last_used_at,
+        }
+    }
+
+    /// Creates a token that has not been used yet, returned with the token itself.
+    pub fn create(
+        user_id: u64,
+        name: &str,
+        scope: TokenScope,
+        now: DateTime<Utc>,
+    ) -> (ApiToken, String) {
+        let token = format!("{}{}", API_TOKEN_PREFIX, generate_token());
+        let api_token = ApiToken::new(0, user_id, name, scope, &hash_token(&token), now, None);
+        (api_token, token)
+    }
+
+    pub fn with_id(self, id: u64) -> ApiToken {
+        ApiToken { id, ..self }
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_user_id(&self) -> u64 {
+        self.user_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_scope(&self) -> TokenScope {
+        self.scope
+    }
+
+    pub fn get_token_hash(&self) -> &str {
+        &self.token_hash
+    }
+
+    pub fn get_created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn get_last_used_at(&self) -> Option<&DateTime<Utc>> {
+        self.last_used_at.as_ref()
+    }
+
+    /// Whether a use at `now` is worth recording. Uses closer together than `interval`
+    /// are not, which spares a write on every request.
+    pub fn needs_use_recorded(&self, now: &DateTime<Utc>, interval: Duration) -> bool {
+        match self.last_used_at {
+            Some(last_used_at) => *now - last_used_at >= interval,
+            None => true,
+        }
+    }
+
+    pub fn used_at(self, now: DateTime<Utc>) -> ApiToken {
+        ApiToken {
+            last_used_at: Some(now),
+            ..self
+        }
+    }
+}
+
+pub const MAX_TOKEN_NAME_LENGTH: usize = 64;
+
+/// Returns why the name can't be given to a token.
+pub fn check_token_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+        return Err(format!(
+            "name must be 1 to {} characters long",
+            MAX_TOKEN_NAME_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn higher_scopes_allow_everything_lower_ones_do() {
+        assert!(TokenScope::Admin.allows(TokenScope::Write));
+        assert!(TokenScope::Write.allows(TokenScope::Read));
+        assert!(TokenScope::Read.allows(TokenScope::Read));
+        assert!(!TokenScope::Read.allows(TokenScope::Write));
+        assert!(!TokenScope::Write.allows(TokenScope::Admin));
+        assert_eq!(TokenScope::parse("write"), Some(TokenScope::Write));
+        assert_eq!(TokenScope::parse("owner"), None);
+    }
+
+    #[test]
+    fn created_tokens_are_prefixed_and_stored_as_hashes() {
+        let (api_token, token) = ApiToken::create(3, "cron", TokenScope::Write, Utc::now());
+
+        assert!(is_api_token(&token));
+        assert_eq!(api_token.get_token_hash(), hash_token(&token));
+        assert_eq!(api_token.get_last_used_at(), None);
+    }
+
+    #[test]
+    fn uses_are_recorded_at_most_once_per_interval() {
+        let now = Utc::now();
+        let (api_token, _) = ApiToken::create(3, "cron", TokenScope::Read, now);
+        assert!(api_token.needs_use_recorded(&now, Duration::minutes(1)));
+
+        let api_token = api_token.used_at(now);
+
+        assert!(!api_token.needs_use_recorded(&(now + Duration::seconds(30)), Duration::minutes(1)));
+        assert!(api_token.needs_use_recorded(&(now + Duration::minutes(1)), Duration::minutes(1)));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::datapoint::Datapoint;
+use crate::revision::Revision;
+use crate::trasheddatapoint::TrashedDatapoint;
+
+pub const BACKUP_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestoreMode {
+    /// Everything in the store is removed before the backup is restored.
+    Replace,
+    /// The backup is added next to what is already in the store.
+    Merge,
+}
+
+impl RestoreMode {
+    pub fn parse(mode: &str) -> Option<RestoreMode> {
+        match mode {
+            "replace" => Some(RestoreMode::Replace),
+            "merge" => Some(RestoreMode::Merge),
+            _ => None,
+        }
+    }
+}
+
+/// Everything needed to rebuild a store: live datapoints, the trash and the revision log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    datapoints: Vec<Datapoint>,
+    trash: Vec<TrashedDatapoint>,
+    revisions: Vec<Revision>,
+}
+
+impl Backup {
+    pub fn new(
+        datapoints: Vec<Datapoint>,
+        trash: Vec<TrashedDatapoint>,
+        revisions: Vec<Revision>,
+    ) -> Backup {
+        Backup {
+            datapoints,
+            trash,
+            revisions,
+        }
+    }
+
+    pub fn get_datapoints(&self) -> &Vec<Datapoint> {
+        &self.datapoints
+    }
+
+    pub fn get_trash(&self) -> &Vec<TrashedDatapoint> {
+        &self.trash
+    }
+
+    pub fn get_revisions(&self) -> &Vec<Revision> {
+        &self.revisions
+    }
+
+    /// Distinct tags of the live datapoints, in order of first appearance.
+    pub fn get_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        for datapoint in &self.datapoints {
+            for tag in datapoint.get_tags() {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+        tags
+    }
+
+    /// Rewrites all keys from their backed up value to the newly allocated one, given as
+    /// `(old, new)` pairs. Revisions of datapoints that are not part of the backup, such as
+    /// purged ones, have no new key and are left out.
+    pub fn remap_keys(self, keys: &[(u64, u64)]) -> Backup {
+        let new_key = |old: u64| {
+            keys.iter()
+                .find(|(backed_up, _)| *backed_up == old)
+                .map(|(_, new)| *new)
+        };
+        let datapoints = self
+            .datapoints
+            .into_iter()
+            .filter_map(|mut datapoint| {
+                datapoint.set_key(new_key(datapoint.get_key())?);
+                Some(datapoint)
+            })
+            .collect();
+        let trash = self
+            .trash
+            .into_iter()
+            .filter_map(|trashed| {
+                let key = new_key(trashed.get_key())?;
+                let deleted_at = *trashed.get_deleted_at();
+                let mut datapoint = trashed.into_datapoint();
+                datapoint.set_key(key);
+                Some(TrashedDatapoint::new(datapoint, deleted_at))
+            })
+            .collect();
+        let revisions = self
+            .revisions
+            .into_iter()
+            .filter_map(|revision| {
+                let key = new_key(revision.get_key())?;
+                Some(revision.with_key(key))
+            })
+            .collect();
+        Backup {
+            datapoints,
+            trash,
+            revisions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint::tests::keyed;
+    use crate::revision::RevisionKind;
+    use chrono::prelude::*;
+
+    fn created(id: u64, datapoint: &Datapoint) -> Revision {
+        Revision::new(
+            id,
+            datapoint.get_key(),
+            RevisionKind::Create,
+            Utc::now(),
+            None,
+            Some(datapoint.clone()),
+        )
+    }
+
+    fn backup() -> Backup {
+        let one = keyed("one +tag", 1);
+        let two = keyed("two +tag +other", 2);
+        let three = keyed("three", 3);
+        Backup::new(
+            vec![one.clone(), two.clone()],
+            vec![TrashedDatapoint::new(three.clone(), Utc::now())],
+            vec![
+                created(1, &one),
+                created(2, &two),
+                created(3, &three),
+                created(4, &keyed("purged", 4)),
+            ],
+        )
+    }
+
+    #[test]
+    fn restore_modes_parse_from_their_names() {
+        assert_eq!(RestoreMode::parse("replace"), Some(RestoreMode::Replace));
+        assert_eq!(RestoreMode::parse("merge"), Some(RestoreMode::Merge));
+        assert_eq!(RestoreMode::parse("append"), None);
+    }
+
+    #[test]
+    fn backup_lists_tags_of_live_datapoints() {
+        assert_eq!(
+            backup().get_tags(),
+            vec!["tag".to_string(), "other".to_string()]
+        );
+    }
+
+    #[test]
+    fn remapping_rewrites_keys_of_datapoints_trash_and_revisions() {
+        let remapped = backup().remap_keys(&[(1, 11), (2, 12), (3, 13)]);
+
+        let keys: Vec<u64> = remapped
+            .get_datapoints()
+            .iter()
+            .map(|datapoint| datapoint.get_key())
+            .collect();
+        assert_eq!(keys, vec![11, 12]);
+        assert_eq!(remapped.get_trash()[0].get_key(), 13);
+        let revision_keys: Vec<u64> = remapped
+            .get_revisions()
+            .iter()
+            .map(|revision| revision.get_key())
+            .collect();
+        assert_eq!(revision_keys, vec![11, 12, 13]);
+        assert_eq!(
+            remapped.get_revisions()[1].get_after().unwrap().get_key(),
+            12
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use crate::datapoint::Datapoint;
+use std::sync::Mutex;
+
+const HISTORY_LIMIT: usize = 100;
+
+/// A batch tag operation, recorded as the tags it added to and removed from every
+/// affected datapoint, so that undoing and redoing it leaves alone any changes made to the
+/// datapoints since.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchCommand {
+    changes: Vec<TagChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TagChange {
+    key: u64,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+impl BatchCommand {
+    pub fn new(before: &[Datapoint], after: &[Datapoint]) -> BatchCommand {
+        let changes = after
+            .iter()
+            .map(|changed| {
+                let old_tags = before
+                    .iter()
+                    .find(|datapoint| datapoint.get_key() == changed.get_key())
+                    .map(|datapoint| datapoint.get_tags().clone())
+                    .unwrap_or_default();
+                TagChange {
+                    key: changed.get_key(),
+                    added: missing_from(changed.get_tags(), &old_tags),
+                    removed: missing_from(&old_tags, changed.get_tags()),
+                }
+            })
+            .collect();
+        BatchCommand { changes }
+    }
+
+    pub fn get_keys(&self) -> Vec<u64> {
+        self.changes.iter().map(|change| change.key).collect()
+    }
+
+    /// Takes back the tags the operation added to the datapoint and puts back the ones it
+    /// removed, if the datapoint was part of it.
+    pub fn undo(&self, datapoint: &mut Datapoint) {
+        if let Some(change) = self.change_of(datapoint) {
+            apply_tags(datapoint, &change.removed, &change.added);
+        }
+    }
+
+    /// Adds and removes the tags again as the operation did, if the datapoint was part of it.
+    pub fn redo(&self, datapoint: &mut Datapoint) {
+        if let Some(change) = self.change_of(datapoint) {
+            apply_tags(datapoint, &change.added, &change.removed);
+        }
+    }
+
+    fn change_of(&self, datapoint: &Datapoint) -> Option<&TagChange> {
+        self.changes
+            .iter()
+            .find(|change| change.key == datapoint.get_key())
+    }
+}
+
+/// The tags of `tags` that are not in `other`.
+fn missing_from(tags: &[String], other: &[String]) -> Vec<String> {
+    tags.iter()
+        .filter(|tag| !other.contains(tag))
+        .cloned()
+        .collect()
+}
+
+fn apply_tags(datapoint: &mut Datapoint, added: &[String], removed: &[String]) {
+    for tag in removed {
+        datapoint.remove_tag(tag);
+    }
+    for tag in added {
+        if !datapoint.get_tags().contains(tag) {
+            datapoint.add_tag(tag);
+        }
+    }
+}
+
+/// Undo and redo stacks of batch commands. Commands taken from a stack are handed back with
+/// `finish_undo`/`finish_redo` once applied, or with `cancel_undo`/`cancel_redo` when
+/// applying them failed.
+pub struct CommandHistory {
+    done: Mutex<Vec<BatchCommand>>,
+    undone: Mutex<Vec<BatchCommand>>,
+}
+
+impl CommandHistory {
+    pub fn new() -> CommandHistory {
+        CommandHistory {
+            done: Mutex::new(Vec::new()),
+            undone: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Records a newly applied command, which makes the undone commands unavailable for redo.
+    pub fn record(&self, command: BatchCommand) {
+        let mut done = self.done.lock().expect("mutex holder crashed");
+        done.push(command);
+        if done.len() > HISTORY_LIMIT {
+            done.remove(0);
+        }
+        self.undone.lock().expect("mutex holder crashed").clear();
+    }
+
+    /// Takes up to `steps` commands to undo, most recent first.
+    pub fn take_undo(&self, steps: usize) -> Vec<BatchCommand> {
+        take_from(&self.done, steps)
+    }
+
+    /// Takes up to `steps` commands to redo, most recently undone first.
+    pub fn take_redo(&self, steps: usize) -> Vec<BatchCommand> {
+        take_from(&self.undone, steps)
+    }
+
+    pub fn finish_undo(&self, commands: Vec<BatchCommand>) {
+        give_back(&self.undone, commands);
+    }
+
+    pub fn finish_redo(&self, commands: Vec<BatchCommand>) {
+        give_back(&self.done, commands);
+    }
+
+    pub fn cancel_undo(&self, commands: Vec<BatchCommand>) {
+        put_back(&self.done, commands);
+    }
+
+    pub fn cancel_redo(&self, commands: Vec<BatchCommand>) {
+        put_back(&self.undone, commands);
+    }
+
+    /// Forgets all commands, for when the datapoints they refer to have been replaced.
+    pub fn clear(&self) {
+        self.done.lock().expect("mutex holder crashed").clear();
+        self.undone.lock().expect("mutex holder crashed").clear();
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.done.lock().expect("mutex holder crashed").len()
+    }
+
+    pub fn redo_count(&self) -> usize {
+        self.undone.lock().expect("mutex holder crashed").len()
+    }
+}
+
+impl Default for CommandHistory {
+    fn default() -> Self {
+        CommandHistory::new()
+    }
+}
+
+fn take_from(stack: &Mutex<Vec<BatchCommand>>, steps: usize) -> Vec<BatchCommand> {
+    let mut stack = stack.lock().expect("mutex holder crashed");
+    let split = stack.len().saturating_sub(steps);
+    let mut taken = stack.split_off(split);
+    taken.reverse();
+    taken
+}
+
+fn give_back(stack: &Mutex<Vec<BatchCommand>>, commands: Vec<BatchCommand>) {
+    stack.lock().expect("mutex holder crashed").extend(commands);
+}
+
+fn put_back(stack: &Mutex<Vec<BatchCommand>>, commands: Vec<BatchCommand>) {
+    stack
+        .lock()
+        .expect("mutex holder crashed")
+        .extend(commands.into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint::tests::keyed;
+
+    fn command(name: &str) -> BatchCommand {
+        BatchCommand::new(
+            &[keyed(name, 1)],
+            &[keyed(&format!("{} +{}", name, name), 1)],
+        )
+    }
+
+    #[test]
+    fn batch_command_undo_and_redo_swap_tags_of_affected_datapoints() {
+        let before = vec![keyed("80kg +weight", 1)];
+        let after = vec![keyed("80kg +weight +morning", 1)];
+        let command = BatchCommand::new(&before, &after);
+        let mut datapoint = after[0].clone();
+
+        command.undo(&mut datapoint);
+        assert_eq!(datapoint.get_tags(), &vec!["weight".to_string()]);
+
+        command.redo(&mut datapoint);
+        assert_eq!(
+            datapoint.get_tags(),
+            &vec!["weight".to_string(), "morning".to_string()]
+        );
+    }
+
+    #[test]
+    fn batch_command_undo_keeps_tag_changes_made_since() {
+        let command = BatchCommand::new(&[keyed("one +a +b", 1)], &[keyed("one +a +c", 1)]);
+        let mut edited = keyed("one, edited +a +c +d", 1);
+
+        command.undo(&mut edited);
+        assert_eq!(edited.get_tags(), &vec!["a", "d", "b"]);
+
+        command.redo(&mut edited);
+        assert_eq!(edited.get_tags(), &vec!["a", "d", "c"]);
+    }
+
+    #[test]
+    fn batch_command_leaves_unaffected_datapoints_alone() {
+        let command = BatchCommand::new(&[keyed("one +a", 1)], &[keyed("one +a +b", 1)]);
+        let mut other = keyed("two +c", 2);
+
+        command.undo(&mut other);
+
+        assert_eq!(other.get_tags(), &vec!["c".to_string()]);
+        assert_eq!(command.get_keys(), vec![1]);
+    }
+
+    #[test]
+    fn command_history_undoes_most_recent_first() {
+        let history = CommandHistory::new();
+        history.record(command("first"));
+        history.record(command("second"));
+        history.record(command("third"));
+
+        let taken = history.take_undo(2);
+
+        assert_eq!(taken, vec![command("third"), command("second")]);
+        assert_eq!(history.undo_count(), 1);
+    }
+
+    #[test]
+    fn command_history_redoes_in_original_order() {
+        let history = CommandHistory::new();
+        history.record(command("first"));
+        history.record(command("second"));
+        let undone = history.take_undo(2);
+        history.finish_undo(undone);
+
+        let redone = history.take_redo(5);
+
+        assert_eq!(redone, vec![command("first"), command("second")]);
+        history.finish_redo(redone);
+        assert_eq!(history.take_undo(1), vec![command("second")]);
+    }
+
+    #[test]
+    fn cancelled_undo_restores_history() {
+        let history = CommandHistory::new();
+        history.record(command("first"));
+        history.record(command("second"));
+
+        let taken = history.take_undo(2);
+        history.cancel_undo(taken);
+
+        assert_eq!(history.undo_count(), 2);
+        assert_eq!(history.redo_count(), 0);
+        assert_eq!(history.take_undo(1), vec![command("second")]);
+    }
+
+    #[test]
+    fn recording_a_command_clears_redo() {
+        let history = CommandHistory::new();
+        history.record(command("first"));
+        let undone = history.take_undo(1);
+        history.finish_undo(undone);
+
+        history.record(command("second"));
+
+        assert_eq!(history.redo_count(), 0);
+        assert!(history.take_redo(1).is_empty());
+    }
+}
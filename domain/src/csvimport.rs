@@ -0,0 +1,328 @@
+use crate::datapoint::{resolve_wall_clock, Datapoint};
+use chrono::prelude::*;
+
+/// Names the CSV columns that hold each part of a datapoint. Only the date column is
+/// required; a missing time column imports the rows at midnight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMapping {
+    date: String,
+    time: Option<String>,
+    value: Option<String>,
+    text: Option<String>,
+    tags: Option<String>,
+}
+
+impl ColumnMapping {
+    pub fn new(date: &str) -> ColumnMapping {
+        ColumnMapping {
+            date: date.to_string(),
+            time: None,
+            value: None,
+            text: None,
+            tags: None,
+        }
+    }
+
+    pub fn with_time(self, time: &str) -> ColumnMapping {
+        ColumnMapping {
+            time: Some(time.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_value(self, value: &str) -> ColumnMapping {
+        ColumnMapping {
+            value: Some(value.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_text(self, text: &str) -> ColumnMapping {
+        ColumnMapping {
+            text: Some(text.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_tags(self, tags: &str) -> ColumnMapping {
+        ColumnMapping {
+            tags: Some(tags.to_string()),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    row: u64,
+    message: String,
+}
+
+impl RowError {
+    pub fn new(row: u64, message: String) -> RowError {
+        RowError { row, message }
+    }
+
+    /// The line of the CSV file the error was found on, the header being line 1.
+    pub fn get_row(&self) -> u64 {
+        self.row
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+}
+
+/// The datapoints parsed from a CSV file together with the rows that could not be parsed.
+/// The datapoints are unkeyed until storage allocates their keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvImport {
+    datapoints: Vec<Datapoint>,
+    errors: Vec<RowError>,
+}
+
+impl CsvImport {
+    pub fn get_datapoints(&self) -> &Vec<Datapoint> {
+        &self.datapoints
+    }
+
+    pub fn get_errors(&self) -> &Vec<RowError> {
+        &self.errors
+    }
+
+    pub fn into_datapoints(self) -> Vec<Datapoint> {
+        self.datapoints
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+struct ColumnIndices {
+    date: usize,
+    time: Option<usize>,
+    value: Option<usize>,
+    text: Option<usize>,
+    tags: Option<usize>,
+}
+
+pub fn parse_csv(content: &str, mapping: &ColumnMapping) -> CsvImport {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(error) => return failed_import(RowError::new(1, error.to_string())),
+    };
+    let indices = match column_indices(&headers, mapping) {
+        Ok(indices) => indices,
+        Err(error) => return failed_import(error),
+    };
+
+    let mut datapoints = Vec::new();
+    let mut errors = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                let row = error.position().map_or(0, |position| position.line());
+                errors.push(RowError::new(row, error.to_string()));
+                continue;
+            }
+        };
+        let row = record.position().map_or(0, |position| position.line());
+        match parse_record(&record, &indices) {
+            Ok(datapoint) => datapoints.push(datapoint),
+            Err(message) => errors.push(RowError::new(row, message)),
+        }
+    }
+    CsvImport { datapoints, errors }
+}
+
+fn failed_import(error: RowError) -> CsvImport {
+    CsvImport {
+        datapoints: Vec::new(),
+        errors: vec![error],
+    }
+}
+
+fn column_indices(
+    headers: &csv::StringRecord,
+    mapping: &ColumnMapping,
+) -> Result<ColumnIndices, RowError> {
+    let find = |name: &String| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| RowError::new(1, format!("no column named '{}'", name)))
+    };
+    let find_optional = |name: &Option<String>| match name {
+        Some(name) => find(name).map(Some),
+        None => Ok(None),
+    };
+    Ok(ColumnIndices {
+        date: find(&mapping.date)?,
+        time: find_optional(&mapping.time)?,
+        value: find_optional(&mapping.value)?,
+        text: find_optional(&mapping.text)?,
+        tags: find_optional(&mapping.tags)?,
+    })
+}
+
+fn parse_record(record: &csv::StringRecord, indices: &ColumnIndices) -> Result<Datapoint, String> {
+    let field = |index: Option<usize>| index.and_then(|index| record.get(index)).unwrap_or("");
+
+    let datetime = parse_datetime(
+        field(Some(indices.date)),
+        indices.time.map(|_| field(indices.time)),
+    )?;
+    let value = field(indices.value);
+    if indices.value.is_some() && value.is_empty() {
+        return Err("value is empty".to_string());
+    }
+    let data = [value, field(indices.text)]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .cloned()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let tags = field(indices.tags)
+        .replace('+', " ")
+        .split_whitespace()
+        .map(|tag| tag.to_string())
+        .collect();
+    Ok(Datapoint::new(datetime, data, tags, 0))
+}
+
+fn parse_datetime(date: &str, time: Option<&str>) -> Result<DateTime<FixedOffset>, String> {
+    if time.is_none() {
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+            return Ok(datetime);
+        }
+        for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+            if let Ok(datetime) = NaiveDateTime::parse_from_str(date, format) {
+                return Ok(resolve_wall_clock(&Local, datetime));
+            }
+        }
+    }
+    let parsed_date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("could not parse date '{}'", date))?;
+    let parsed_time = match time {
+        Some(time) => ["%H:%M:%S", "%H:%M", "%H-%M-%S"]
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(time, format).ok())
+            .ok_or_else(|| format!("could not parse time '{}'", time))?,
+        None => NaiveTime::MIN,
+    };
+    Ok(resolve_wall_clock(
+        &Local,
+        parsed_date.and_time(parsed_time),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_mapping() -> ColumnMapping {
+        ColumnMapping::new("date")
+            .with_time("time")
+            .with_value("weight")
+            .with_text("note")
+            .with_tags("tags")
+    }
+
+    #[test]
+    fn rows_are_parsed_into_unkeyed_datapoints() {
+        let content = "date,time,weight,note,tags\n\
+                       2023-01-02,07:30,80kg,after run,weight morning\n\
+                       2023-01-03,07:45:10,79.5kg,,+weight +morning\n";
+
+        let import = parse_csv(content, &full_mapping());
+
+        assert!(import.is_clean());
+        let datapoints = import.get_datapoints();
+        assert_eq!(datapoints.len(), 2);
+        assert_eq!(datapoints[0].get_data(), "80kg after run");
+        assert_eq!(
+            datapoints[0].get_tags(),
+            &vec!["weight".to_string(), "morning".to_string()]
+        );
+        assert_eq!(
+            datapoints[0].get_datetime().naive_local(),
+            NaiveDate::from_ymd_opt(2023, 1, 2)
+                .unwrap()
+                .and_hms_opt(7, 30, 0)
+                .unwrap()
+        );
+        assert_eq!(datapoints[1].get_data(), "79.5kg");
+        assert_eq!(datapoints[1].get_key(), 0);
+    }
+
+    #[test]
+    fn date_column_can_hold_full_timestamps_when_time_is_not_mapped() {
+        let content = "when,value\n2023-01-02T07:30:00+02:00,80\n2023-01-03 08:00:00,81\n";
+
+        let import = parse_csv(content, &ColumnMapping::new("when").with_value("value"));
+
+        let datapoints = import.get_datapoints();
+        assert_eq!(datapoints[0].get_utc_offset_seconds(), 2 * 3600);
+        assert_eq!(
+            datapoints[0].get_utc_datetime(),
+            Utc.with_ymd_and_hms(2023, 1, 2, 5, 30, 0).unwrap()
+        );
+        assert_eq!(
+            datapoints[1].get_datetime().time(),
+            NaiveTime::from_hms_opt(8, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn date_without_time_is_imported_at_midnight() {
+        let content = "date,value\n2023-01-02,80\n";
+
+        let import = parse_csv(content, &ColumnMapping::new("date").with_value("value"));
+
+        assert_eq!(
+            import.get_datapoints()[0].get_datetime().time(),
+            NaiveTime::MIN
+        );
+    }
+
+    #[test]
+    fn unparseable_rows_are_reported_with_their_line() {
+        let content = "date,time,weight,note,tags\n\
+                       2023-01-02,07:30,80kg,,weight\n\
+                       yesterday,07:30,80kg,,weight\n\
+                       2023-01-04,late,80kg,,weight\n\
+                       2023-01-05,07:30,,,weight\n";
+
+        let import = parse_csv(content, &full_mapping());
+
+        assert_eq!(import.get_datapoints().len(), 1);
+        let rows: Vec<u64> = import
+            .get_errors()
+            .iter()
+            .map(|error| error.get_row())
+            .collect();
+        assert_eq!(rows, vec![3, 4, 5]);
+        assert_eq!(
+            import.get_errors()[0].get_message(),
+            "could not parse date 'yesterday'"
+        );
+    }
+
+    #[test]
+    fn mapping_to_unknown_column_is_reported_on_header_row() {
+        let content = "date,value\n2023-01-02,80\n";
+
+        let import = parse_csv(content, &ColumnMapping::new("date").with_value("weight"));
+
+        assert!(import.get_datapoints().is_empty());
+        assert_eq!(
+            import.get_errors(),
+            &vec![RowError::new(1, "no column named 'weight'".to_string())]
+        );
+    }
+}
@@ -1,17 +1,24 @@
 use std::num::ParseFloatError;
 
 use chrono::prelude::*;
+use chrono::LocalResult;
+use chrono_tz::Tz;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Datapoint {
-    datetime: DateTime<Local>,
+    datetime: DateTime<FixedOffset>,
     data: String,
     tags: Vec<String>,
     key: u64,
 }
 
 impl Datapoint {
-    pub fn new(datetime: DateTime<Local>, data: String, tags: Vec<String>, key: u64) -> Datapoint {
+    pub fn new(
+        datetime: DateTime<FixedOffset>,
+        data: String,
+        tags: Vec<String>,
+        key: u64,
+    ) -> Datapoint {
         Datapoint {
             datetime,
             data,
@@ -32,10 +39,19 @@ impl Datapoint {
         &self.data
     }
 
-    pub fn get_datetime(&self) -> &DateTime<Local> {
+    /// The moment the datapoint was logged, expressed in the UTC offset it was logged in.
+    pub fn get_datetime(&self) -> &DateTime<FixedOffset> {
         &self.datetime
     }
 
+    pub fn get_utc_datetime(&self) -> DateTime<Utc> {
+        self.datetime.with_timezone(&Utc)
+    }
+
+    pub fn get_utc_offset_seconds(&self) -> i32 {
+        self.datetime.offset().local_minus_utc()
+    }
+
     pub fn get_tags(&self) -> &Vec<String> {
         &self.tags
     }
@@ -62,6 +78,10 @@ impl Datapoint {
         self.key
     }
 
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+    }
+
     pub fn add_tag(&mut self, tag: &String) -> () {
         self.tags.push(tag.clone());
     }
@@ -85,22 +105,115 @@ impl Datapoint {
     }
 }
 
+/// The timezone the date and time of an entry are read in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EntryTimezone {
+    /// The timezone of the server, for entries that don't say where they were logged.
+    #[default]
+    ServerLocal,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl EntryTimezone {
+    /// Parses an IANA name such as `Europe/Amsterdam` or a UTC offset such as `-05:00`.
+    /// Offsets without a sign are east of UTC, so they can be written in a `+TZ:` command.
+    pub fn parse(text: &str) -> Option<EntryTimezone> {
+        if let Ok(timezone) = text.parse::<Tz>() {
+            return Some(EntryTimezone::Named(timezone));
+        }
+        let offset = if text.starts_with(['+', '-']) {
+            text.to_string()
+        } else {
+            format!("+{}", text)
+        };
+        offset.parse::<FixedOffset>().ok().map(EntryTimezone::Fixed)
+    }
+
+    fn now(&self) -> NaiveDateTime {
+        match self {
+            EntryTimezone::ServerLocal => Local::now().naive_local(),
+            EntryTimezone::Named(timezone) => Utc::now().with_timezone(timezone).naive_local(),
+            EntryTimezone::Fixed(offset) => Utc::now().with_timezone(offset).naive_local(),
+        }
+    }
+
+    fn resolve(&self, wall_clock: NaiveDateTime) -> DateTime<FixedOffset> {
+        match self {
+            EntryTimezone::ServerLocal => resolve_wall_clock(&Local, wall_clock),
+            EntryTimezone::Named(timezone) => resolve_wall_clock(timezone, wall_clock),
+            EntryTimezone::Fixed(offset) => resolve_wall_clock(offset, wall_clock),
+        }
+    }
+}
+
 pub fn create_datapoint(text: &str) -> Datapoint {
+    create_datapoint_in(text, EntryTimezone::default())
+}
+
+/// Creates a datapoint logged in the given timezone, unless the entry names its own with a
+/// `+TZ:` command.
+pub fn create_datapoint_in(text: &str, timezone: EntryTimezone) -> Datapoint {
     let tags = get_tags_from(text);
     let data = get_data_from(text);
-    handle_tags_and_create_datapoint(data, tags)
+    handle_tags_and_create_datapoint(data, tags, timezone)
 }
 
-fn handle_tags_and_create_datapoint(data: String, tags: Vec<String>) -> Datapoint {
-    let mut datetime: DateTime<Local> = Local::now();
-    let mut date = datetime.date_naive();
-    let mut time = datetime.time();
+/// Like `create_datapoint_in`, but refuses input without data and date, time or timezone
+/// commands that cannot be parsed instead of falling back on the current date and time.
+pub fn try_create_datapoint(text: &str, timezone: EntryTimezone) -> Result<Datapoint, String> {
+    if get_data_from(text).is_empty() {
+        return Err("entry has no data".to_string());
+    }
+    for tag in get_tags_from(text) {
+        let command: Vec<&str> = tag.split(':').collect();
+        let valid = match command[0] {
+            "D" | "DATE" => {
+                command.len() > 1 && NaiveDate::parse_from_str(command[1], "%Y-%m-%d").is_ok()
+            }
+            "T" | "TIME" => {
+                command.len() > 1 && NaiveTime::parse_from_str(command[1], "%H-%M-%S").is_ok()
+            }
+            "TZ" => parse_timezone(&tag).is_some(),
+            _ => true,
+        };
+        if !valid {
+            return Err(format!("could not parse '+{}'", tag));
+        }
+    }
+    Ok(create_datapoint_in(text, timezone))
+}
+
+/// Parses multi-line input with one entry per line, skipping blank lines. Every entry is
+/// returned with its 1-based line number.
+pub fn parse_lines(text: &str, timezone: EntryTimezone) -> Vec<(usize, Result<Datapoint, String>)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, try_create_datapoint(line, timezone)))
+        .collect()
+}
+
+fn handle_tags_and_create_datapoint(
+    data: String,
+    tags: Vec<String>,
+    timezone: EntryTimezone,
+) -> Datapoint {
+    let timezone = tags
+        .iter()
+        .rev()
+        .find_map(|tag| parse_timezone(tag))
+        .unwrap_or(timezone);
+    let now = timezone.now();
+    let mut date = now.date();
+    let mut time = now.time();
 
     let mut tag_collector = Vec::new();
 
     for tag in &tags {
         let command: Vec<&str> = tag.split(':').collect();
         match command[0] {
+            "TZ" => (),
             "D" => date = parse_date(command, date),
             "DATE" => date = parse_date(command, date),
             "T" => time = parse_time(command, time),
@@ -109,16 +222,38 @@ fn handle_tags_and_create_datapoint(data: String, tags: Vec<String>) -> Datapoin
         }
     }
 
-    datetime = Local.from_local_datetime(&date.and_time(time)).unwrap();
-
     Datapoint {
         data,
         tags: tag_collector,
-        datetime,
+        datetime: timezone.resolve(date.and_time(time)),
         key: 0,
     }
 }
 
+/// Pins a wall-clock time in the given timezone to an instant. Times repeated by a DST
+/// transition resolve to their first occurrence, times skipped by one keep their wall-clock
+/// reading with the offset in effect around the transition.
+pub(crate) fn resolve_wall_clock<Tz: TimeZone>(
+    timezone: &Tz,
+    wall_clock: NaiveDateTime,
+) -> DateTime<FixedOffset> {
+    match timezone.from_local_datetime(&wall_clock) {
+        LocalResult::Single(datetime) => datetime.fixed_offset(),
+        LocalResult::Ambiguous(earliest, _) => earliest.fixed_offset(),
+        LocalResult::None => {
+            let offset = timezone.offset_from_utc_datetime(&wall_clock).fix();
+            wall_clock
+                .and_local_timezone(offset)
+                .single()
+                .expect("fixed offsets have no gaps")
+        }
+    }
+}
+
+fn parse_timezone(tag: &str) -> Option<EntryTimezone> {
+    EntryTimezone::parse(tag.strip_prefix("TZ:")?)
+}
+
 fn parse_date(command: Vec<&str>, fallback: NaiveDate) -> NaiveDate {
     if command.len() < 2 {
         return fallback;
@@ -171,9 +306,16 @@ fn collect_tags(tag_iterator: Vec<&str>) -> Vec<String> {
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
 
+    /// A datapoint created from the input, under the key storage would have given it.
+    pub fn keyed(input: &str, key: u64) -> Datapoint {
+        let mut datapoint = create_datapoint(input);
+        datapoint.set_key(key);
+        datapoint
+    }
+
     #[test]
     fn get_data_from_text_will_return_empty_string_if_nothing_entered() {
         let data = get_data_from("+");
@@ -217,6 +359,122 @@ mod tests {
         assert_eq!(datapoint.get_datetime().time(), expected);
     }
 
+    #[test]
+    fn datetime_keeps_the_wall_clock_time_and_offset_it_was_logged_in() {
+        let wall_clock = NaiveDate::from_ymd_opt(2022, 2, 10)
+            .unwrap()
+            .and_hms_opt(12, 34, 56)
+            .unwrap();
+        let new_york = EntryTimezone::Named(chrono_tz::America::New_York);
+
+        let datapoint = create_datapoint_in("data +D:2022-02-10 +T:12-34-56", new_york);
+
+        assert_eq!(datapoint.get_datetime().naive_local(), wall_clock);
+        assert_eq!(datapoint.get_utc_offset_seconds(), -5 * 3600);
+        assert_eq!(
+            datapoint.get_utc_datetime().naive_utc(),
+            wall_clock + chrono::Duration::hours(5)
+        );
+    }
+
+    #[test]
+    fn timezone_command_overrides_the_timezone_of_the_client() {
+        let new_york = EntryTimezone::Named(chrono_tz::America::New_York);
+
+        let tokyo = create_datapoint_in("data +TZ:Asia/Tokyo +D:2022-02-10 +weight", new_york);
+        let offset = create_datapoint_in("data +TZ:-03:30 +D:2022-02-10", new_york);
+        let unsigned = create_datapoint_in("data +TZ:05:45 +D:2022-02-10", new_york);
+
+        assert_eq!(tokyo.get_utc_offset_seconds(), 9 * 3600);
+        assert_eq!(tokyo.get_tags(), &vec!["weight".to_string()]);
+        assert_eq!(offset.get_utc_offset_seconds(), -(3 * 3600 + 1800));
+        assert_eq!(unsigned.get_utc_offset_seconds(), 5 * 3600 + 45 * 60);
+    }
+
+    #[test]
+    fn timezones_are_parsed_by_name_or_offset() {
+        assert_eq!(
+            EntryTimezone::parse("Europe/Amsterdam"),
+            Some(EntryTimezone::Named(chrono_tz::Europe::Amsterdam))
+        );
+        assert_eq!(
+            EntryTimezone::parse("-05:00"),
+            Some(EntryTimezone::Fixed(
+                FixedOffset::west_opt(5 * 3600).unwrap()
+            ))
+        );
+        assert_eq!(EntryTimezone::parse("Mars/Olympus"), None);
+    }
+
+    #[test]
+    fn wall_clock_time_in_a_fixed_offset_keeps_that_offset() {
+        let wall_clock = NaiveDate::from_ymd_opt(2023, 6, 1)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        let offset = FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+
+        let datetime = resolve_wall_clock(&offset, wall_clock);
+
+        assert_eq!(datetime.naive_local(), wall_clock);
+        assert_eq!(
+            datetime.naive_utc(),
+            wall_clock - chrono::Duration::minutes(330)
+        );
+    }
+
+    #[test]
+    fn try_create_datapoint_accepts_valid_commands() {
+        let datapoint = try_create_datapoint(
+            "80kg +weight +D:2023-01-02 +T:07-30-00",
+            EntryTimezone::default(),
+        )
+        .unwrap();
+
+        assert_eq!(datapoint.get_data(), "80kg");
+        assert_eq!(
+            datapoint.get_datetime().naive_local(),
+            NaiveDate::from_ymd_opt(2023, 1, 2)
+                .unwrap()
+                .and_hms_opt(7, 30, 0)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn try_create_datapoint_refuses_unparseable_commands_and_missing_data() {
+        assert_eq!(
+            try_create_datapoint("80kg +D:yesterday", EntryTimezone::default()),
+            Err("could not parse '+D:yesterday'".to_string())
+        );
+        assert_eq!(
+            try_create_datapoint("80kg +TIME", EntryTimezone::default()),
+            Err("could not parse '+TIME'".to_string())
+        );
+        assert_eq!(
+            try_create_datapoint("80kg +TZ:Mars/Olympus", EntryTimezone::default()),
+            Err("could not parse '+TZ:Mars/Olympus'".to_string())
+        );
+        assert_eq!(
+            try_create_datapoint(" +weight", EntryTimezone::default()),
+            Err("entry has no data".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_lines_numbers_entries_and_skips_blank_lines() {
+        let parsed = parse_lines(
+            "80kg +weight\n\n81kg +weight +D:2023-13-01\n  \n79kg\n",
+            EntryTimezone::default(),
+        );
+
+        let lines: Vec<usize> = parsed.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![1, 3, 5]);
+        assert!(parsed[0].1.is_ok());
+        assert!(parsed[1].1.is_err());
+        assert_eq!(parsed[2].1.as_ref().unwrap().get_data(), "79kg");
+    }
+
     #[test]
     fn parse_date_falls_back_on_given_date_if_no_string_to_parse() {
         let command = vec!["DATE"];
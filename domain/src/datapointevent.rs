@@ -0,0 +1,47 @@
+use crate::datapoint::Datapoint;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// A change to the datapoints of a `Datastore`, holding the datapoint as it is after the
+/// change, or as it was before it was deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatapointEvent {
+    kind: ChangeKind,
+    datapoint: Datapoint,
+}
+
+impl DatapointEvent {
+    pub fn new(kind: ChangeKind, datapoint: Datapoint) -> DatapointEvent {
+        DatapointEvent { kind, datapoint }
+    }
+
+    pub fn get_kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    pub fn get_datapoint(&self) -> &Datapoint {
+        &self.datapoint
+    }
+
+    pub fn into_datapoint(self) -> Datapoint {
+        self.datapoint
+    }
+}
+
+/// Called with every change made to a `Datastore`, after the change has been made.
+pub type Listener = Box<dyn Fn(DatapointEvent) + Send + Sync>;
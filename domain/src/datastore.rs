@@ -1,34 +1,117 @@
-use crate::datapoint::{create_datapoint, Datapoint};
+use crate::backup::RestoreMode;
+use crate::batchcommand::BatchCommand;
+use crate::datapoint::{create_datapoint_in, Datapoint, EntryTimezone};
+use crate::datapointevent::{ChangeKind, DatapointEvent, Listener};
 use crate::parsedquery::ParsedQuery;
+use crate::querypage::{PageRequest, QueryPage};
 use crate::queryresult::QueryResult;
+use crate::trasheddatapoint::TrashedDatapoint;
+use chrono::{DateTime, Duration, Utc};
 use std::sync::{Mutex, MutexGuard};
 
 pub struct Datastore {
     datapoints: Mutex<Vec<Datapoint>>,
+    trash: Mutex<Vec<TrashedDatapoint>>,
     tags: Mutex<Vec<String>>,
     counter: Mutex<u64>,
+    listener: Mutex<Option<Listener>>,
 }
 
 impl Datastore {
     pub fn new() -> Datastore {
         Datastore {
             datapoints: Mutex::new(Vec::new()),
+            trash: Mutex::new(Vec::new()),
             tags: Mutex::new(Vec::new()),
             counter: Mutex::new(0),
+            listener: Mutex::new(None),
         }
     }
 
+    /// Has every later change to the datapoints reported to the listener, replacing the
+    /// listener set before.
+    pub fn set_listener(&self, listener: Listener) {
+        *self.listener.lock().expect("mutex holder crashed") = Some(listener);
+    }
+
+    pub fn with_trash(self, trashed: Vec<TrashedDatapoint>) -> Datastore {
+        {
+            let mut counter = self.counter.lock().expect("counter holder crashed");
+            for trashed_datapoint in &trashed {
+                if *counter < trashed_datapoint.get_key() {
+                    *counter = trashed_datapoint.get_key();
+                }
+            }
+        }
+        *self.trash.lock().expect("mutex holder crashed") = trashed;
+        self
+    }
+
+    /// Adds a datapoint keyed by the datastore's own counter, for use without a backing storage.
+    /// When a storage is present it allocates the key, see `prepare_datapoint`.
     pub fn add_datapoint(&self, input: &str) -> Datapoint {
-        let mut new_datapoint = create_datapoint(input);
-        self.append_tags(new_datapoint.get_tags());
-        let counter = self.increment_counter();
-        new_datapoint.set_key(counter);
+        let mut new_datapoint = self.prepare_datapoint(input, EntryTimezone::default());
+        new_datapoint.set_key(self.increment_counter());
+        self.insert_datapoint(new_datapoint.clone());
+        new_datapoint
+    }
+
+    /// Creates an unkeyed datapoint from the input, logged in the given timezone, without
+    /// storing it. Once a key has been allocated for it the datapoint can be stored with
+    /// `insert_datapoint`.
+    pub fn prepare_datapoint(&self, input: &str, timezone: EntryTimezone) -> Datapoint {
+        create_datapoint_in(input, timezone)
+    }
+
+    pub fn insert_datapoint(&self, datapoint: Datapoint) {
+        self.insert_unreported(datapoint.clone());
+        self.notify(ChangeKind::Created, vec![datapoint]);
+    }
+
+    /// Adds the datapoints and trash of a restored backup, emptying the datastore first
+    /// when replacing. None of it is reported to the listener: restoring a backup does not
+    /// create its datapoints anew.
+    pub fn restore(
+        &self,
+        datapoints: Vec<Datapoint>,
+        trash: Vec<TrashedDatapoint>,
+        mode: RestoreMode,
+    ) {
+        if mode == RestoreMode::Replace {
+            self.datapoints
+                .lock()
+                .expect("mutex holder crashed")
+                .clear();
+            self.trash.lock().expect("mutex holder crashed").clear();
+            self.tags.lock().expect("mutex holder crashed").clear();
+        }
+        for datapoint in datapoints {
+            self.insert_unreported(datapoint);
+        }
+        for trashed in trash {
+            self.insert_trashed(trashed);
+        }
+    }
+
+    fn insert_unreported(&self, datapoint: Datapoint) {
+        self.append_tags(datapoint.get_tags());
         let mut old_datapoints = self.datapoints.lock().expect("mutex holder crashed");
-        insert_sorted_by_time(new_datapoint.clone(), &mut old_datapoints);
-        return new_datapoint;
+        insert_sorted_by_time(datapoint, &mut old_datapoints);
+    }
+
+    pub fn insert_trashed(&self, trashed: TrashedDatapoint) {
+        let mut trash = self.trash.lock().expect("mutex holder crashed");
+        trash.push(trashed);
     }
 
-    fn get_by_key(&self, keys: Vec<u64>) -> Vec<Datapoint> {
+    pub fn contains_key(&self, key: u64) -> bool {
+        let datapoints = self.datapoints.lock().expect("mutex holder crashed");
+        datapoints
+            .iter()
+            .any(|datapoint| datapoint.get_key() == key)
+    }
+
+    pub fn get_by_key(&self, keys: Vec<u64>) -> Vec<Datapoint> {
         let mut collector: Vec<Datapoint> = Vec::new();
         let datapoints = self.retrieve_datapoints();
         for datapoint in datapoints {
@@ -40,20 +123,82 @@ impl Datastore {
     }
 
     pub fn batch_operation(&self, tags: &str, keys: Vec<u64>, add: bool) -> Vec<Datapoint> {
-        let tags: Vec<String> = tags
-            .trim()
-            .replace("+", " ")
-            .split_whitespace()
-            .map(|str| str.to_string())
-            .collect();
-        for tag in tags {
-            if add {
-                self.batch_add_tag(keys.clone(), tag);
-            } else {
-                self.batch_remove_tag(keys.clone(), tag);
+        let datapoints = self.prepare_batch_operation(tags, keys, add);
+        self.replace_datapoints(datapoints.clone());
+        datapoints
+    }
+
+    /// Computes the result of a batch tag operation without touching the stored datapoints,
+    /// so the changes can be persisted first and applied with `replace_datapoints` afterwards.
+    pub fn prepare_batch_operation(&self, tags: &str, keys: Vec<u64>, add: bool) -> Vec<Datapoint> {
+        let tags = parse_batch_tags(tags);
+        let mut datapoints = self.get_by_key(keys);
+        for datapoint in datapoints.iter_mut() {
+            for tag in &tags {
+                if add {
+                    datapoint.add_tag(tag);
+                } else {
+                    datapoint.remove_tag(tag);
+                }
+            }
+        }
+        datapoints
+    }
+
+    /// Computes the datapoints that result from undoing the commands in the given order,
+    /// without changing the datastore.
+    pub fn prepare_undo(&self, commands: &[BatchCommand]) -> Vec<Datapoint> {
+        self.prepare_commands(commands, BatchCommand::undo)
+    }
+
+    /// Computes the datapoints that result from redoing the commands in the given order,
+    /// without changing the datastore.
+    pub fn prepare_redo(&self, commands: &[BatchCommand]) -> Vec<Datapoint> {
+        self.prepare_commands(commands, BatchCommand::redo)
+    }
+
+    fn prepare_commands(
+        &self,
+        commands: &[BatchCommand],
+        apply: fn(&BatchCommand, &mut Datapoint),
+    ) -> Vec<Datapoint> {
+        let mut prepared: Vec<Datapoint> = Vec::new();
+        for command in commands {
+            for key in command.get_keys() {
+                if !prepared.iter().any(|datapoint| datapoint.get_key() == key) {
+                    prepared.extend(self.get_by_key(vec![key]));
+                }
+            }
+            for datapoint in prepared.iter_mut() {
+                apply(command, datapoint);
+            }
+        }
+        prepared
+    }
+
+    pub fn replace_datapoints(&self, replacements: Vec<Datapoint>) {
+        for replacement in &replacements {
+            self.append_tags(replacement.get_tags());
+        }
+        let mut replaced = Vec::new();
+        {
+            let mut datapoints = self.datapoints.lock().expect("mutex holder crashed");
+            for replacement in replacements {
+                let position = datapoints
+                    .iter()
+                    .position(|datapoint| datapoint.get_key() == replacement.get_key());
+                if let Some(i) = position {
+                    replaced.push(replacement.clone());
+                    if datapoints[i].get_datetime() == replacement.get_datetime() {
+                        datapoints[i] = replacement;
+                    } else {
+                        datapoints.remove(i);
+                        insert_sorted_by_time(replacement, &mut datapoints);
+                    }
+                }
             }
         }
-        return self.get_by_key(keys);
+        self.notify(ChangeKind::Updated, replaced);
     }
 
     pub fn batch_add_tag(&self, keys: Vec<u64>, tag: String) -> bool {
@@ -77,33 +222,72 @@ impl Datastore {
     }
 
     pub fn update_datapoint(&self, input: &str, key: u64) -> Datapoint {
-        let mut new_datapoint = create_datapoint(input);
-        self.append_tags(new_datapoint.get_tags());
+        let new_datapoint = self.prepare_update(input, key, EntryTimezone::default());
+        self.replace_datapoints(vec![new_datapoint.clone()]);
+        new_datapoint
+    }
+
+    /// Creates the replacement for the datapoint with the given key without storing it,
+    /// see `replace_datapoints`.
+    pub fn prepare_update(&self, input: &str, key: u64, timezone: EntryTimezone) -> Datapoint {
+        let mut new_datapoint = create_datapoint_in(input, timezone);
         new_datapoint.set_key(key);
-        let mut datapoint_vector = self.datapoints.lock().expect("mutex holder crashed");
-        for i in 0..datapoint_vector.len() {
-            if datapoint_vector[i].get_key() == key {
-                if datapoint_vector[i].get_datetime() == new_datapoint.get_datetime() {
-                    datapoint_vector[i] = new_datapoint.clone();
-                    break;
-                } else {
-                    datapoint_vector.remove(i);
-                    insert_sorted_by_time(new_datapoint.clone(), &mut datapoint_vector);
-                    break;
-                }
-            }
-        }
-        return new_datapoint;
+        new_datapoint
     }
 
     pub fn delete_datapoint(&self, key: u64) -> Option<Datapoint> {
-        let mut datapoints = self.datapoints.lock().expect("mutex holder crashed");
-        for (i, datapoint) in datapoints.clone().into_iter().enumerate() {
-            if datapoint.get_key() == key {
-                return Some(datapoints.remove(i));
-            }
-        }
-        return None;
+        let removed = {
+            let mut datapoints = self.datapoints.lock().expect("mutex holder crashed");
+            let position = datapoints
+                .iter()
+                .position(|datapoint| datapoint.get_key() == key)?;
+            datapoints.remove(position)
+        };
+        self.notify(ChangeKind::Deleted, vec![removed.clone()]);
+        Some(removed)
+    }
+
+    pub fn move_to_trash(&self, key: u64, deleted_at: DateTime<Utc>) -> Option<TrashedDatapoint> {
+        let datapoint = self.delete_datapoint(key)?;
+        let trashed = TrashedDatapoint::new(datapoint, deleted_at);
+        let mut trash = self.trash.lock().expect("mutex holder crashed");
+        trash.push(trashed.clone());
+        Some(trashed)
+    }
+
+    pub fn restore_datapoint(&self, key: u64) -> Option<Datapoint> {
+        let restored = {
+            let mut trash = self.trash.lock().expect("mutex holder crashed");
+            let position = trash.iter().position(|trashed| trashed.get_key() == key)?;
+            trash.remove(position).into_datapoint()
+        };
+        self.insert_datapoint(restored.clone());
+        Some(restored)
+    }
+
+    pub fn trash_contains_key(&self, key: u64) -> bool {
+        let trash = self.trash.lock().expect("mutex holder crashed");
+        trash.iter().any(|trashed| trashed.get_key() == key)
+    }
+
+    /// Keys of the trashed datapoints that were deleted before the cutoff.
+    pub fn expired_trash(&self, cutoff: &DateTime<Utc>) -> Vec<u64> {
+        let trash = self.trash.lock().expect("mutex holder crashed");
+        trash
+            .iter()
+            .filter(|trashed| trashed.deleted_before(cutoff))
+            .map(|trashed| trashed.get_key())
+            .collect()
+    }
+
+    pub fn remove_from_trash(&self, keys: &[u64]) {
+        let mut trash = self.trash.lock().expect("mutex holder crashed");
+        trash.retain(|trashed| !keys.contains(&trashed.get_key()));
+    }
+
+    pub fn retrieve_trash(&self) -> Vec<TrashedDatapoint> {
+        let lock = self.trash.lock().expect("mutex holder crashed");
+        lock.clone()
     }
 
     pub fn retrieve_datapoints(&self) -> Vec<Datapoint> {
@@ -116,6 +300,27 @@ impl Datastore {
         lock.clone()
     }
 
+    /// Groups datapoints with equal data and tags that were logged within `window` of the
+    /// previous member of their group. Only groups of two or more are returned, each in
+    /// chronological order.
+    pub fn find_duplicates(&self, window: Duration) -> Vec<Vec<Datapoint>> {
+        let mut groups: Vec<Vec<Datapoint>> = Vec::new();
+        for datapoint in self.retrieve_datapoints() {
+            let group = groups.iter_mut().find(|group| {
+                let last = &group[group.len() - 1];
+                last.data_same_as(&datapoint)
+                    && last.tags_same_as(&datapoint)
+                    && datapoint.get_utc_datetime() - last.get_utc_datetime() <= window
+            });
+            match group {
+                Some(group) => group.push(datapoint),
+                None => groups.push(vec![datapoint]),
+            }
+        }
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+
     pub fn query(&self, query: &str) -> QueryResult {
         let mut collector: Vec<Datapoint> = Vec::new();
         let parsed: ParsedQuery = ParsedQuery::from(query);
@@ -131,6 +336,11 @@ impl Datastore {
         QueryResult::from(collector, parsed).apply_query_commands()
     }
 
+    /// Runs the query and returns only the requested page of its datapoints.
+    pub fn query_page(&self, query: &str, request: &PageRequest) -> QueryPage {
+        request.apply(self.query(query).get_datapoints())
+    }
+
     fn append_tags(&self, tags: &Vec<String>) -> () {
         let mut lock = self.tags.lock().expect("Mutex holder crashed...");
         for tag in tags {
@@ -140,6 +350,17 @@ impl Datastore {
         }
     }
 
+    /// Reports the changes to the listener, if any. Called once the datapoints are unlocked
+    /// again, so the listener may read the datastore.
+    fn notify(&self, kind: ChangeKind, datapoints: Vec<Datapoint>) {
+        let listener = self.listener.lock().expect("mutex holder crashed");
+        if let Some(listener) = listener.as_ref() {
+            for datapoint in datapoints {
+                listener(DatapointEvent::new(kind, datapoint));
+            }
+        }
+    }
+
     fn increment_counter(&self) -> u64 {
         let mut counter = self.counter.lock().expect("counter holder crashed");
         *counter += 1;
@@ -158,8 +379,10 @@ impl From<Vec<Datapoint>> for Datastore {
         }
         let datastore = Datastore {
             datapoints: Mutex::new(datapoints.clone()),
+            trash: Mutex::new(Vec::new()),
             tags: Mutex::new(Vec::new()),
             counter: Mutex::new(max_key),
+            listener: Mutex::new(None),
         };
         for datapoint in datapoints {
             datastore.append_tags(datapoint.get_tags());
@@ -168,6 +391,14 @@ impl From<Vec<Datapoint>> for Datastore {
     }
 }
 
+fn parse_batch_tags(tags: &str) -> Vec<String> {
+    tags.trim()
+        .replace("+", " ")
+        .split_whitespace()
+        .map(|str| str.to_string())
+        .collect()
+}
+
 fn insert_sorted_by_time<'a>(datapoint: Datapoint, vector: &mut MutexGuard<'a, Vec<Datapoint>>) {
     let length = vector.len();
 
@@ -189,6 +420,9 @@ fn insert_sorted_by_time<'a>(datapoint: Datapoint, vector: &mut MutexGuard<'a, V
 #[cfg(test)]
 mod tests {
     use crate::datapoint;
+    use crate::querypage::SortOrder;
+    use chrono::{Duration, TimeZone};
+    use std::sync::Arc;
 
     use super::*;
 
@@ -246,6 +480,132 @@ mod tests {
         assert_eq!(datapoints[2].get_tags(), &Vec::<String>::new());
     }
 
+    #[test]
+    fn prepared_batch_operation_does_not_change_stored_datapoints() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one +tag");
+        datastore.add_datapoint("two +tag");
+
+        let prepared = datastore.prepare_batch_operation("tag", vec![1, 2], false);
+        let datapoints = datastore.retrieve_datapoints();
+
+        assert_eq!(prepared[0].get_tags(), &Vec::<String>::new());
+        assert_eq!(prepared[1].get_tags(), &Vec::<String>::new());
+        assert_eq!(datapoints[0].get_tags(), &vec!["tag".to_string()]);
+        assert_eq!(datapoints[1].get_tags(), &vec!["tag".to_string()]);
+    }
+
+    #[test]
+    fn replace_datapoints_applies_prepared_changes_and_registers_new_tags() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one");
+        datastore.add_datapoint("two");
+        datastore.add_datapoint("three");
+
+        let prepared = datastore.prepare_batch_operation("+new", vec![2], true);
+        datastore.replace_datapoints(prepared);
+        let datapoints = datastore.retrieve_datapoints();
+
+        assert_eq!(datapoints[0].get_tags(), &Vec::<String>::new());
+        assert_eq!(datapoints[1].get_tags(), &vec!["new".to_string()]);
+        assert_eq!(datapoints[2].get_tags(), &Vec::<String>::new());
+        assert_eq!(datastore.retrieve_taglist(), vec!["new".to_string()]);
+    }
+
+    #[test]
+    fn prepared_undo_reverts_commands_in_order() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one +tag");
+        datastore.add_datapoint("two +tag");
+        let before = datastore.get_by_key(vec![1, 2]);
+        let removed = datastore.prepare_batch_operation("tag", vec![1, 2], false);
+        datastore.replace_datapoints(removed.clone());
+        let first = BatchCommand::new(&before, &removed);
+        let added = datastore.prepare_batch_operation("new", vec![2], true);
+        datastore.replace_datapoints(added.clone());
+        let second = BatchCommand::new(&removed[1..], &added);
+
+        let undone = datastore.prepare_undo(&[second.clone(), first.clone()]);
+
+        assert_eq!(undone[0].get_key(), 2);
+        assert_eq!(undone[0].get_tags(), &vec!["tag".to_string()]);
+        assert_eq!(undone[1].get_key(), 1);
+        assert_eq!(undone[1].get_tags(), &vec!["tag".to_string()]);
+        assert_eq!(
+            datastore.get_by_key(vec![2])[0].get_tags(),
+            &vec!["new".to_string()]
+        );
+
+        datastore.replace_datapoints(undone);
+        let redone = datastore.prepare_redo(&[first]);
+
+        assert_eq!(redone.len(), 2);
+        assert!(redone
+            .iter()
+            .all(|datapoint| datapoint.get_tags().is_empty()));
+    }
+
+    #[test]
+    fn duplicates_within_window_are_grouped() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("80kg +weight +D:2023-01-02 +T:07-30-00");
+        datastore.add_datapoint("80kg +weight +D:2023-01-02 +T:07-31-00");
+        datastore.add_datapoint("80kg +weight +D:2023-01-02 +T:07-35-00");
+        datastore.add_datapoint("80kg +weight +D:2023-01-02 +T:09-00-00");
+        datastore.add_datapoint("80kg +other +D:2023-01-02 +T:07-30-30");
+        datastore.add_datapoint("81kg +weight +D:2023-01-02 +T:07-30-40");
+
+        let groups = datastore.find_duplicates(Duration::minutes(5));
+
+        let keys: Vec<Vec<u64>> = groups
+            .iter()
+            .map(|group| group.iter().map(|datapoint| datapoint.get_key()).collect())
+            .collect();
+        assert_eq!(keys, vec![vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn no_duplicates_without_repeated_entries() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one +tag");
+        datastore.add_datapoint("two +tag");
+
+        assert!(datastore.find_duplicates(Duration::hours(1)).is_empty());
+    }
+
+    #[test]
+    fn prepared_datapoint_is_only_stored_once_inserted() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one +tag");
+
+        let mut prepared = datastore.prepare_datapoint("two +other", EntryTimezone::default());
+
+        assert_eq!(prepared.get_key(), 0);
+        assert_eq!(datastore.retrieve_datapoints().len(), 1);
+
+        prepared.set_key(40);
+        datastore.insert_datapoint(prepared);
+
+        assert_eq!(datastore.retrieve_datapoints().len(), 2);
+        assert!(datastore.contains_key(40));
+        assert_eq!(
+            datastore.retrieve_taglist(),
+            vec!["tag".to_string(), "other".to_string()]
+        );
+    }
+
+    #[test]
+    fn prepared_update_does_not_change_stored_datapoint() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one +tag");
+
+        let prepared = datastore.prepare_update("changed +tag", 1, EntryTimezone::default());
+
+        assert_eq!(prepared.get_data(), "changed");
+        assert_eq!(prepared.get_key(), 1);
+        assert_eq!(datastore.retrieve_datapoints()[0].get_data(), "one");
+    }
+
     #[test]
     fn batch_add_tag_takes_selected_key_vector_and_adds_tags_to_each_selected_datapoint() {
         let datastore = Datastore::new();
@@ -323,6 +683,68 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    #[test]
+    fn trashed_datapoints_are_hidden_from_queries_until_restored() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("data +one +DATE:2023-10-10");
+        datastore.add_datapoint("more +one +DATE:2023-10-11");
+        let deleted_at = Utc::now();
+
+        let trashed = datastore.move_to_trash(1, deleted_at).unwrap();
+
+        assert_eq!(trashed.get_datapoint().get_data(), "data");
+        assert_eq!(trashed.get_deleted_at(), &deleted_at);
+        assert_eq!(datastore.query("one").get_datapoints().len(), 1);
+        assert!(datastore.trash_contains_key(1));
+
+        let restored = datastore.restore_datapoint(1).unwrap();
+        let datapoints = datastore.retrieve_datapoints();
+
+        assert_eq!(restored.get_data(), "data");
+        assert_eq!(datapoints[0].get_data(), "data");
+        assert_eq!(datapoints[1].get_data(), "more");
+        assert!(datastore.retrieve_trash().is_empty());
+    }
+
+    #[test]
+    fn restoring_a_key_not_in_the_trash_returns_none() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("data +one");
+
+        assert_eq!(datastore.restore_datapoint(1), None);
+        assert_eq!(datastore.move_to_trash(4, Utc::now()), None);
+    }
+
+    #[test]
+    fn expired_trash_lists_datapoints_deleted_before_the_cutoff() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("old");
+        datastore.add_datapoint("recent");
+        let cutoff = Utc.with_ymd_and_hms(2023, 11, 1, 0, 0, 0).unwrap();
+        datastore.move_to_trash(1, cutoff - Duration::days(1));
+        datastore.move_to_trash(2, cutoff + Duration::days(1));
+
+        let expired = datastore.expired_trash(&cutoff);
+        datastore.remove_from_trash(&expired);
+
+        assert_eq!(expired, vec![1]);
+        assert_eq!(datastore.retrieve_trash().len(), 1);
+        assert_eq!(datastore.retrieve_trash()[0].get_key(), 2);
+    }
+
+    #[test]
+    fn datastore_with_trash_keeps_counter_above_trashed_keys() {
+        let mut datapoint = datapoint::create_datapoint("trashed");
+        datapoint.set_key(7);
+        let datastore = Datastore::from(Vec::new())
+            .with_trash(vec![TrashedDatapoint::new(datapoint, Utc::now())]);
+
+        let added = datastore.add_datapoint("new");
+
+        assert_eq!(added.get_key(), 8);
+        assert!(datastore.trash_contains_key(7));
+    }
+
     #[test]
     fn query_can_exclude_certain_tags() {
         let datastore = Datastore::new();
@@ -508,6 +930,25 @@ mod tests {
         assert_eq!(retrieved[0].get_data(), "8");
     }
 
+    #[test]
+    fn query_page_counts_all_matches_but_returns_one_page_newest_first() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("1 +weight +DATE:2023-01-01");
+        datastore.add_datapoint("2 +weight +DATE:2023-01-03");
+        datastore.add_datapoint("3 +steps +DATE:2023-01-04");
+        datastore.add_datapoint("4 +weight +DATE:2023-01-02");
+
+        let page = datastore.query_page(
+            "+weight",
+            &PageRequest::new(SortOrder::DateDescending).with_limit(2),
+        );
+
+        assert_eq!(page.get_total(), 3);
+        assert_eq!(page.get_datapoints()[0].get_data(), "2");
+        assert_eq!(page.get_datapoints()[1].get_data(), "4");
+        assert!(page.get_next_cursor().is_some());
+    }
+
     #[test]
     fn query_with_tag_only_retrieves_tagged_datapoint() {
         let datastore = Datastore::new();
@@ -556,4 +997,56 @@ mod tests {
         assert!(actual_datapoint.data_same_as(&expected_datapoint));
         assert!(actual_datapoint.tags_same_as(&expected_datapoint));
     }
+
+    fn recording_listener(datastore: &Datastore) -> Arc<Mutex<Vec<(ChangeKind, u64)>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        datastore.set_listener(Box::new(move |event: DatapointEvent| {
+            let key = event.get_datapoint().get_key();
+            recorded.lock().unwrap().push((event.get_kind(), key));
+        }));
+        events
+    }
+
+    #[test]
+    fn listener_hears_of_every_created_updated_and_deleted_datapoint() {
+        let datastore = Datastore::new();
+        let events = recording_listener(&datastore);
+
+        datastore.add_datapoint("one");
+        datastore.update_datapoint("uno", 1);
+        datastore.replace_datapoints(vec![datastore.prepare_update(
+            "unknown",
+            9,
+            EntryTimezone::default(),
+        )]);
+        datastore.move_to_trash(1, Utc::now());
+        datastore.restore_datapoint(1);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (ChangeKind::Created, 1),
+                (ChangeKind::Updated, 1),
+                (ChangeKind::Deleted, 1),
+                (ChangeKind::Created, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn restoring_a_backup_is_not_reported() {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one +old");
+        let events = recording_listener(&datastore);
+        let mut restored = datapoint::create_datapoint("two +new");
+        restored.set_key(7);
+
+        datastore.restore(vec![restored], Vec::new(), RestoreMode::Replace);
+
+        assert!(events.lock().unwrap().is_empty());
+        assert_eq!(datastore.get_by_key(vec![7]).len(), 1);
+        assert!(!datastore.contains_key(1));
+        assert_eq!(datastore.retrieve_taglist(), vec!["new".to_string()]);
+    }
 }
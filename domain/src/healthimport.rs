@@ -0,0 +1,453 @@
+use crate::datapoint::Datapoint;
+use chrono::prelude::*;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordType {
+    Weight,
+    Steps,
+    HeartRate,
+    Sleep,
+}
+
+impl RecordType {
+    fn unit(&self) -> &'static str {
+        match self {
+            RecordType::Weight => "kg",
+            RecordType::Steps => "",
+            RecordType::HeartRate => "bpm",
+            RecordType::Sleep => "h",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HealthSource {
+    AppleHealth,
+    GoogleFit,
+}
+
+impl HealthSource {
+    pub fn parse(source: &str) -> Option<HealthSource> {
+        match source {
+            "apple" => Some(HealthSource::AppleHealth),
+            "googlefit" => Some(HealthSource::GoogleFit),
+            _ => None,
+        }
+    }
+}
+
+/// A single measurement read from a health export. Sleep is measured in hours asleep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthRecord {
+    record_type: RecordType,
+    start: DateTime<FixedOffset>,
+    value: f64,
+}
+
+impl HealthRecord {
+    pub fn new(record_type: RecordType, start: DateTime<FixedOffset>, value: f64) -> HealthRecord {
+        HealthRecord {
+            record_type,
+            start,
+            value,
+        }
+    }
+
+    pub fn get_record_type(&self) -> RecordType {
+        self.record_type
+    }
+
+    pub fn get_start(&self) -> &DateTime<FixedOffset> {
+        &self.start
+    }
+
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// The tags given to datapoints imported for each record type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagMapping {
+    weight: Vec<String>,
+    steps: Vec<String>,
+    heart_rate: Vec<String>,
+    sleep: Vec<String>,
+}
+
+impl TagMapping {
+    pub fn new() -> TagMapping {
+        TagMapping {
+            weight: vec!["weight".to_string()],
+            steps: vec!["steps".to_string()],
+            heart_rate: vec!["heartrate".to_string()],
+            sleep: vec!["sleep".to_string()],
+        }
+    }
+
+    /// Replaces the tags of a record type, given in the same `+tag` or space separated form
+    /// as batch operations.
+    pub fn with_tags(self, record_type: RecordType, tags: &str) -> TagMapping {
+        let tags: Vec<String> = tags
+            .replace('+', " ")
+            .split_whitespace()
+            .map(|tag| tag.to_string())
+            .collect();
+        match record_type {
+            RecordType::Weight => TagMapping {
+                weight: tags,
+                ..self
+            },
+            RecordType::Steps => TagMapping {
+                steps: tags,
+                ..self
+            },
+            RecordType::HeartRate => TagMapping {
+                heart_rate: tags,
+                ..self
+            },
+            RecordType::Sleep => TagMapping {
+                sleep: tags,
+                ..self
+            },
+        }
+    }
+
+    pub fn get_tags(&self, record_type: RecordType) -> &Vec<String> {
+        match record_type {
+            RecordType::Weight => &self.weight,
+            RecordType::Steps => &self.steps,
+            RecordType::HeartRate => &self.heart_rate,
+            RecordType::Sleep => &self.sleep,
+        }
+    }
+}
+
+impl Default for TagMapping {
+    fn default() -> Self {
+        TagMapping::new()
+    }
+}
+
+pub fn parse_health_export(
+    source: HealthSource,
+    content: &str,
+) -> Result<Vec<HealthRecord>, String> {
+    match source {
+        HealthSource::AppleHealth => parse_apple_health(content),
+        HealthSource::GoogleFit => parse_google_fit(content),
+    }
+}
+
+/// Reads the `Record` elements of an Apple Health `export.xml`. Records of other types
+/// and sleep records for time spent awake or in bed are skipped.
+pub fn parse_apple_health(xml: &str) -> Result<Vec<HealthRecord>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut records = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) | Ok(Event::Empty(element))
+                if element.name().as_ref() == b"Record" =>
+            {
+                if let Some(record) = apple_record(&element)? {
+                    records.push(record);
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(error) => {
+                return Err(format!(
+                    "invalid XML at position {}: {}",
+                    reader.buffer_position(),
+                    error
+                ))
+            }
+        }
+    }
+    Ok(records)
+}
+
+fn apple_record(element: &BytesStart) -> Result<Option<HealthRecord>, String> {
+    let attribute = |name: &str| -> Option<String> {
+        element
+            .attributes()
+            .filter_map(|attribute| attribute.ok())
+            .find(|attribute| attribute.key.as_ref() == name.as_bytes())
+            .and_then(|attribute| attribute.unescape_value().ok())
+            .map(|value| value.into_owned())
+    };
+    let record_type = match attribute("type").as_deref() {
+        Some("HKQuantityTypeIdentifierBodyMass") => RecordType::Weight,
+        Some("HKQuantityTypeIdentifierStepCount") => RecordType::Steps,
+        Some("HKQuantityTypeIdentifierHeartRate") => RecordType::HeartRate,
+        Some("HKCategoryTypeIdentifierSleepAnalysis") => RecordType::Sleep,
+        _ => return Ok(None),
+    };
+    let start = parse_apple_date(&attribute("startDate").unwrap_or_default())?;
+    let value = match record_type {
+        RecordType::Sleep => {
+            if !attribute("value").unwrap_or_default().contains("Asleep") {
+                return Ok(None);
+            }
+            let end = parse_apple_date(&attribute("endDate").unwrap_or_default())?;
+            hours_between(&start, &end)
+        }
+        _ => {
+            let value = attribute("value").unwrap_or_default();
+            let value: f64 = value
+                .parse()
+                .map_err(|_| format!("could not parse record value '{}'", value))?;
+            match (record_type, attribute("unit").as_deref()) {
+                (RecordType::Weight, Some("lb")) => value * 0.453_592_37,
+                _ => value,
+            }
+        }
+    };
+    Ok(Some(HealthRecord::new(record_type, start, value)))
+}
+
+fn parse_apple_date(date: &str) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z")
+        .map_err(|_| format!("could not parse record date '{}'", date))
+}
+
+/// Reads the data points of a Google Fit Takeout JSON file. Data types other than weight,
+/// step count, heart rate and sleep segments are skipped, as are awake sleep segments.
+pub fn parse_google_fit(json: &str) -> Result<Vec<HealthRecord>, String> {
+    let export: Value =
+        serde_json::from_str(json).map_err(|error| format!("invalid JSON: {}", error))?;
+    let points = export["Data Points"]
+        .as_array()
+        .ok_or_else(|| "no \"Data Points\" in export".to_string())?;
+    let mut records = Vec::new();
+    for point in points {
+        if let Some(record) = google_fit_record(point)? {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+fn google_fit_record(point: &Value) -> Result<Option<HealthRecord>, String> {
+    let record_type = match point["dataTypeName"].as_str() {
+        Some("com.google.weight") => RecordType::Weight,
+        Some("com.google.step_count.delta") => RecordType::Steps,
+        Some("com.google.heart_rate.bpm") => RecordType::HeartRate,
+        Some("com.google.sleep.segment") => RecordType::Sleep,
+        _ => return Ok(None),
+    };
+    let start = nanos_to_datetime(&point["startTimeNanos"])?;
+    let value = &point["fitValue"][0]["value"];
+    let value = match record_type {
+        RecordType::Sleep => {
+            // sleep stages 1 and 3 are awake and out of bed, the others are asleep
+            if matches!(value["intVal"].as_i64(), Some(1) | Some(3) | None) {
+                return Ok(None);
+            }
+            hours_between(&start, &nanos_to_datetime(&point["endTimeNanos"])?)
+        }
+        _ => match value["fpVal"].as_f64().or(value["intVal"].as_f64()) {
+            Some(value) => value,
+            None => return Err(format!("data point without value: {}", point)),
+        },
+    };
+    Ok(Some(HealthRecord::new(record_type, start, value)))
+}
+
+fn nanos_to_datetime(nanos: &Value) -> Result<DateTime<FixedOffset>, String> {
+    let nanos: i64 = match nanos {
+        Value::String(nanos) => nanos.parse().ok(),
+        Value::Number(nanos) => nanos.as_i64(),
+        _ => None,
+    }
+    .ok_or_else(|| format!("could not parse time '{}'", nanos))?;
+    let utc = Utc.timestamp_nanos(nanos);
+    let offset = Local.offset_from_utc_datetime(&utc.naive_utc()).fix();
+    Ok(utc.with_timezone(&offset))
+}
+
+fn hours_between(start: &DateTime<FixedOffset>, end: &DateTime<FixedOffset>) -> f64 {
+    let hours = (*end - *start).num_seconds() as f64 / 3600.0;
+    (hours * 100.0).round() / 100.0
+}
+
+/// Turns records into unkeyed datapoints holding the value with its unit, tagged as mapped.
+pub fn records_to_datapoints(records: Vec<HealthRecord>, mapping: &TagMapping) -> Vec<Datapoint> {
+    records
+        .into_iter()
+        .map(|record| {
+            let value = (record.value * 100.0).round() / 100.0;
+            Datapoint::new(
+                record.start,
+                format!("{}{}", value, record.record_type.unit()),
+                mapping.get_tags(record.record_type).clone(),
+                0,
+            )
+        })
+        .collect()
+}
+
+/// Drops candidates that have the same instant and numeric value as an existing datapoint,
+/// or as a candidate before them.
+pub fn deduplicate(candidates: Vec<Datapoint>, existing: &[Datapoint]) -> Vec<Datapoint> {
+    let mut kept: Vec<Datapoint> = Vec::new();
+    for candidate in candidates {
+        let duplicate = existing
+            .iter()
+            .chain(kept.iter())
+            .any(|datapoint| same_measurement(datapoint, &candidate));
+        if !duplicate {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+fn same_measurement(first: &Datapoint, second: &Datapoint) -> bool {
+    if first.get_utc_datetime() != second.get_utc_datetime() {
+        return false;
+    }
+    match (
+        first.clone().get_non_numeric_stripped().get_as_numeric(),
+        second.clone().get_non_numeric_stripped().get_as_numeric(),
+    ) {
+        (Ok(first), Ok(second)) => (first - second).abs() < 1e-9,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APPLE_EXPORT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<HealthData locale="en_NL">
+ <ExportDate value="2023-01-05 10:00:00 +0100"/>
+ <Record type="HKQuantityTypeIdentifierBodyMass" unit="kg" value="80.1" startDate="2023-01-02 07:30:00 +0100" endDate="2023-01-02 07:30:00 +0100"/>
+ <Record type="HKQuantityTypeIdentifierStepCount" unit="count" value="1234" startDate="2023-01-02 09:00:00 +0100" endDate="2023-01-02 10:00:00 +0100">
+  <MetadataEntry key="HKWasUserEntered" value="0"/>
+ </Record>
+ <Record type="HKQuantityTypeIdentifierHeartRate" unit="count/min" value="62" startDate="2023-01-02 09:05:00 +0100" endDate="2023-01-02 09:05:00 +0100"/>
+ <Record type="HKCategoryTypeIdentifierSleepAnalysis" value="HKCategoryValueSleepAnalysisInBed" startDate="2023-01-01 23:00:00 +0100" endDate="2023-01-02 07:00:00 +0100"/>
+ <Record type="HKCategoryTypeIdentifierSleepAnalysis" value="HKCategoryValueSleepAnalysisAsleepCore" startDate="2023-01-01 23:15:00 +0100" endDate="2023-01-02 06:45:00 +0100"/>
+ <Record type="HKQuantityTypeIdentifierDietaryWater" unit="mL" value="250" startDate="2023-01-02 12:00:00 +0100" endDate="2023-01-02 12:00:00 +0100"/>
+</HealthData>"#;
+
+    const GOOGLE_FIT_EXPORT: &str = r#"{
+  "Data Source": "derived:com.google.weight:com.google.android.gms:merge_weight",
+  "Data Points": [
+    {"fitValue": [{"value": {"fpVal": 80.1}}], "originDataSourceId": "", "endTimeNanos": 1672641000000000000, "dataTypeName": "com.google.weight", "startTimeNanos": 1672641000000000000, "modifiedTimeMillis": 1672641000000},
+    {"fitValue": [{"value": {"intVal": 1234}}], "originDataSourceId": "", "endTimeNanos": 1672650000000000000, "dataTypeName": "com.google.step_count.delta", "startTimeNanos": 1672646400000000000, "modifiedTimeMillis": 1672650000000},
+    {"fitValue": [{"value": {"intVal": 4}}], "originDataSourceId": "", "endTimeNanos": 1672638300000000000, "dataTypeName": "com.google.sleep.segment", "startTimeNanos": 1672611300000000000, "modifiedTimeMillis": 1672638300000},
+    {"fitValue": [{"value": {"intVal": 1}}], "originDataSourceId": "", "endTimeNanos": 1672639200000000000, "dataTypeName": "com.google.sleep.segment", "startTimeNanos": 1672638300000000000, "modifiedTimeMillis": 1672639200000},
+    {"fitValue": [{"value": {"fpVal": 1.5}}], "originDataSourceId": "", "endTimeNanos": 1672639200000000000, "dataTypeName": "com.google.distance.delta", "startTimeNanos": 1672638300000000000, "modifiedTimeMillis": 1672639200000}
+  ]
+}"#;
+
+    #[test]
+    fn apple_health_records_of_known_types_are_read() {
+        let records = parse_apple_health(APPLE_EXPORT).unwrap();
+
+        let types: Vec<RecordType> = records
+            .iter()
+            .map(|record| record.get_record_type())
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                RecordType::Weight,
+                RecordType::Steps,
+                RecordType::HeartRate,
+                RecordType::Sleep
+            ]
+        );
+        assert_eq!(records[0].get_value(), 80.1);
+        assert_eq!(
+            records[0].get_start(),
+            &FixedOffset::east_opt(3600)
+                .unwrap()
+                .with_ymd_and_hms(2023, 1, 2, 7, 30, 0)
+                .unwrap()
+        );
+        assert_eq!(records[3].get_value(), 7.5);
+    }
+
+    #[test]
+    fn apple_health_weight_in_pounds_is_converted_to_kilograms() {
+        let xml = r#"<HealthData><Record type="HKQuantityTypeIdentifierBodyMass" unit="lb" value="100" startDate="2023-01-02 07:30:00 +0100"/></HealthData>"#;
+
+        let records = parse_apple_health(xml).unwrap();
+
+        assert!((records[0].get_value() - 45.359237).abs() < 1e-9);
+    }
+
+    #[test]
+    fn malformed_apple_health_dates_are_reported() {
+        let xml = r#"<HealthData><Record type="HKQuantityTypeIdentifierStepCount" value="10" startDate="yesterday"/></HealthData>"#;
+
+        assert_eq!(
+            parse_apple_health(xml),
+            Err("could not parse record date 'yesterday'".to_string())
+        );
+    }
+
+    #[test]
+    fn google_fit_data_points_of_known_types_are_read() {
+        let records = parse_google_fit(GOOGLE_FIT_EXPORT).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].get_record_type(), RecordType::Weight);
+        assert_eq!(
+            records[0].get_start().with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2023, 1, 2, 6, 30, 0).unwrap()
+        );
+        assert_eq!(records[1].get_value(), 1234.0);
+        assert_eq!(records[2].get_record_type(), RecordType::Sleep);
+        assert_eq!(records[2].get_value(), 7.5);
+    }
+
+    #[test]
+    fn google_fit_export_without_data_points_is_refused() {
+        assert!(parse_google_fit("{}").is_err());
+        assert!(parse_google_fit("not json").is_err());
+    }
+
+    #[test]
+    fn records_become_datapoints_tagged_per_mapping() {
+        let records = parse_apple_health(APPLE_EXPORT).unwrap();
+        let mapping = TagMapping::new().with_tags(RecordType::Weight, "+weight +morning");
+
+        let datapoints = records_to_datapoints(records, &mapping);
+
+        assert_eq!(datapoints[0].get_data(), "80.1kg");
+        assert_eq!(
+            datapoints[0].get_tags(),
+            &vec!["weight".to_string(), "morning".to_string()]
+        );
+        assert_eq!(datapoints[1].get_data(), "1234");
+        assert_eq!(datapoints[2].get_data(), "62bpm");
+        assert_eq!(datapoints[3].get_data(), "7.5h");
+        assert_eq!(datapoints[3].get_tags(), &vec!["sleep".to_string()]);
+    }
+
+    #[test]
+    fn duplicates_by_instant_and_value_are_dropped() {
+        let records = parse_apple_health(APPLE_EXPORT).unwrap();
+        let datapoints = records_to_datapoints(records, &TagMapping::new());
+        let existing = vec![Datapoint::new(
+            datapoints[0].get_utc_datetime().fixed_offset(),
+            "80.1 kg".to_string(),
+            vec!["weight".to_string()],
+            4,
+        )];
+        let mut candidates = datapoints.clone();
+        candidates.push(datapoints[1].clone());
+
+        let kept = deduplicate(candidates, &existing);
+
+        assert_eq!(kept, datapoints[1..].to_vec());
+    }
+}
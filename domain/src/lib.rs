@@ -1,8 +1,19 @@
+pub mod apitoken;
+pub mod backup;
+pub mod batchcommand;
+pub mod csvimport;
 pub mod datapoint;
+pub mod datapointevent;
 pub mod datastore;
+pub mod healthimport;
 pub mod linearfunction;
 pub mod numericaldata;
 pub mod parsedquery;
 pub mod plotter;
+pub mod querypage;
 pub mod queryresult;
+pub mod revision;
 pub mod stats;
+pub mod trasheddatapoint;
+pub mod user;
+pub mod webhook;
@@ -1,45 +1,66 @@
 use crate::numericaldata::NumericalData;
 use crate::plotter::plotcolors::PlotColors;
+use crate::plotter::plotoptions::PlotOptions;
 use crate::plotter::util::*;
 use crate::queryresult::QueryResult;
 use crate::stats::preprocess::into_categorical;
-use chrono::prelude::*;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 
-pub fn categorical_plot(dataset: &Vec<QueryResult>) -> Option<String> {
-    let plot_x_start = 0;
-    let plot_x_end = dataset.len() + 1;
-
+pub fn categorical_plot(
+    dataset: &Vec<QueryResult>,
+    options: &PlotOptions,
+) -> Result<Vec<u8>, PlotError> {
     let titled_datasets = into_categorical(dataset);
     if titled_datasets.len() == 0 {
-        return None;
+        return Err(PlotError::NoNumericData);
     }
-    let (lower, upper) = apply_margin(get_total_upper_lower(&titled_datasets));
 
-    let filename = generate_filename(Local::now());
-    let location: String = format!("generated/{}", filename);
-    let plot_colors = PlotColors::new();
-    let root = BitMapBackend::new(&location, (640, 480)).into_drawing_area();
-    root.fill(plot_colors.background()).unwrap();
+    let image = match options.get_format() {
+        PlotFormat::Png => render_png(options.get_size(), |root| {
+            draw_categorical_plot(root, &titled_datasets, options)
+        }),
+        PlotFormat::Svg => render_svg(options.get_size(), |root| {
+            draw_categorical_plot(root, &titled_datasets, options)
+        }),
+    }?;
+    Ok(image)
+}
+
+fn draw_categorical_plot<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    titled_datasets: &[NumericalData],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let plot_x_start = 0;
+    let plot_x_end = titled_datasets.len() + 1;
+    let (lower, upper) = apply_margin(get_total_upper_lower(titled_datasets));
+
+    let plot_colors = PlotColors::new(options.get_theme());
+    root.fill(plot_colors.background())?;
 
-    let (title, font_size) = generate_title(&titled_datasets);
+    let (generated_title, generated_font_size) = generate_title(titled_datasets);
+    let title = options.get_title().unwrap_or(&generated_title);
+    let font_size = options.get_title_font_size().unwrap_or(generated_font_size);
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .caption(
             title,
             ("sans-serif", font_size)
                 .with_color(plot_colors.textcolor())
-                .into_text_style(&root),
+                .into_text_style(root),
         )
         .margin(10)
         .x_label_area_size(30)
         .y_label_area_size(30)
-        .build_cartesian_2d(plot_x_start..plot_x_end, lower..upper)
-        .unwrap();
+        .build_cartesian_2d(plot_x_start..plot_x_end, lower..upper)?;
 
     chart
         .configure_mesh()
-        .label_style(plot_colors.highlight())
+        .label_style(label_style(options, &plot_colors))
         .axis_style(plot_colors.textcolor())
         .bold_line_style(plot_colors.highlight())
         .light_line_style(plot_colors.darklight())
@@ -52,24 +73,22 @@ pub fn categorical_plot(dataset: &Vec<QueryResult>) -> Option<String> {
                 None => "".to_string(),
             }
         })
-        .draw()
-        .unwrap();
-
-    for (i, titled_data) in titled_datasets.into_iter().enumerate() {
-        chart
-            .draw_series(
-                titled_data
-                    .get_data()
-                    .iter()
-                    .map(|value| Circle::new((i + 1, *value), 5, plot_colors.labelstyle().clone())),
+        .draw()?;
+
+    for (i, titled_data) in titled_datasets.iter().enumerate() {
+        chart.draw_series(titled_data.get_data().iter().map(|value| {
+            Circle::new(
+                (i + 1, *value),
+                options.get_marker_size(),
+                plot_colors.labelstyle().clone(),
             )
-            .unwrap();
+        }))?;
     }
 
-    return Some(filename);
+    Ok(())
 }
 
-fn generate_title(titled_data: &Vec<NumericalData>) -> (String, u32) {
+fn generate_title(titled_data: &[NumericalData]) -> (String, u32) {
     let mut title: String = titled_data[0].get_title().clone();
     let mut counter = 1;
     while counter < titled_data.len() {
@@ -80,7 +99,7 @@ fn generate_title(titled_data: &Vec<NumericalData>) -> (String, u32) {
     return (title, font_size);
 }
 
-fn get_total_upper_lower(titled_data: &Vec<NumericalData>) -> (f64, f64) {
+fn get_total_upper_lower(titled_data: &[NumericalData]) -> (f64, f64) {
     let mut collector: Vec<f64> = Vec::new();
     for dataset in titled_data {
         let (lower, upper) = get_upper_lower(&dataset.get_data());
@@ -154,7 +173,7 @@ mod test {
     }
 
     #[test]
-    fn plotting_queries_with_no_results_returns_none() {
+    fn plotting_queries_with_no_results_is_refused() {
         let mut collector: Vec<QueryResult> = Vec::new();
         let datastore = Datastore::new();
         datastore.add_datapoint("6 hours +sleep +coffee");
@@ -163,8 +182,8 @@ mod test {
         datastore.add_datapoint("8 hours +sleep +tea");
         collector.push(datastore.query("totally not findable"));
 
-        let result = categorical_plot(&collector);
+        let result = categorical_plot(&collector, &PlotOptions::default());
 
-        assert_eq!(result, None);
+        assert!(matches!(result, Err(PlotError::NoNumericData)));
     }
 }
@@ -1,4 +1,5 @@
 pub mod categorical;
 mod plotcolors;
+pub mod plotoptions;
 pub mod scatterplot;
 pub mod util;
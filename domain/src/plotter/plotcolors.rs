@@ -1,3 +1,4 @@
+use crate::plotter::plotoptions::Theme;
 use plotters::prelude::*;
 
 pub struct PlotColors {
@@ -6,19 +7,39 @@ pub struct PlotColors {
     highlight: RGBColor,
     darklight: RGBColor,
     labelcolor: ShapeStyle,
+    fitted: RGBColor,
+    target: RGBColor,
 }
 
 impl PlotColors {
-    pub fn new() -> PlotColors {
-        PlotColors {
-            background: RGBColor(12, 22, 24),
-            textcolor: RGBColor(209, 172, 0),
-            highlight: RGBColor(250, 244, 211),
-            darklight: RGBColor(60, 73, 76),
-            labelcolor: ShapeStyle {
-                color: RGBAColor(193, 41, 46, 1.0),
-                filled: true,
-                stroke_width: 2,
+    pub fn new(theme: Theme) -> PlotColors {
+        match theme {
+            Theme::Dark => PlotColors {
+                background: RGBColor(12, 22, 24),
+                textcolor: RGBColor(209, 172, 0),
+                highlight: RGBColor(250, 244, 211),
+                darklight: RGBColor(60, 73, 76),
+                labelcolor: marker(RGBColor(193, 41, 46)),
+                fitted: CYAN,
+                target: GREEN,
+            },
+            Theme::Light => PlotColors {
+                background: RGBColor(252, 252, 250),
+                textcolor: RGBColor(33, 37, 41),
+                highlight: RGBColor(73, 80, 87),
+                darklight: RGBColor(222, 226, 230),
+                labelcolor: marker(RGBColor(193, 41, 46)),
+                fitted: RGBColor(0, 128, 128),
+                target: RGBColor(34, 139, 34),
+            },
+            Theme::ColorBlind => PlotColors {
+                background: WHITE,
+                textcolor: BLACK,
+                highlight: RGBColor(64, 64, 64),
+                darklight: RGBColor(224, 224, 224),
+                labelcolor: marker(RGBColor(0, 114, 178)),
+                fitted: RGBColor(230, 159, 0),
+                target: RGBColor(204, 121, 167),
             },
         }
     }
@@ -42,4 +63,22 @@ impl PlotColors {
     pub fn labelstyle(&self) -> &ShapeStyle {
         &self.labelcolor
     }
+
+    /// The colour of regression lines.
+    pub fn fitted(&self) -> &RGBColor {
+        &self.fitted
+    }
+
+    /// The colour of the goal line of predictions.
+    pub fn target(&self) -> &RGBColor {
+        &self.target
+    }
+}
+
+fn marker(color: RGBColor) -> ShapeStyle {
+    ShapeStyle {
+        color: color.to_rgba(),
+        filled: true,
+        stroke_width: 2,
+    }
 }
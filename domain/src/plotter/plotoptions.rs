@@ -0,0 +1,220 @@
+use crate::plotter::util::PlotFormat;
+
+pub const MIN_PLOT_SIZE: u32 = 100;
+pub const MAX_PLOT_SIZE: u32 = 4000;
+pub const MAX_MARKER_SIZE: u32 = 50;
+pub const MAX_FONT_SIZE: u32 = 200;
+pub const MAX_TITLE_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    /// A light theme drawn in the Okabe-Ito palette, which stays distinguishable with
+    /// every common form of colour blindness.
+    ColorBlind,
+}
+
+impl Theme {
+    pub fn parse(theme: &str) -> Option<Theme> {
+        match theme {
+            "dark" => Some(Theme::Dark),
+            "light" => Some(Theme::Light),
+            "colorblind" => Some(Theme::ColorBlind),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::ColorBlind => "colorblind",
+        }
+    }
+}
+
+/// How a plot is rendered. Font sizes and the title left unset are chosen per plot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotOptions {
+    format: PlotFormat,
+    width: u32,
+    height: u32,
+    theme: Theme,
+    marker_size: u32,
+    title_font_size: Option<u32>,
+    label_font_size: Option<u32>,
+    title: Option<String>,
+}
+
+impl Default for PlotOptions {
+    fn default() -> PlotOptions {
+        PlotOptions {
+            format: PlotFormat::default(),
+            width: 640,
+            height: 480,
+            theme: Theme::default(),
+            marker_size: 5,
+            title_font_size: None,
+            label_font_size: None,
+            title: None,
+        }
+    }
+}
+
+impl PlotOptions {
+    pub fn with_format(self, format: PlotFormat) -> PlotOptions {
+        PlotOptions { format, ..self }
+    }
+
+    pub fn with_size(self, width: u32, height: u32) -> PlotOptions {
+        PlotOptions {
+            width,
+            height,
+            ..self
+        }
+    }
+
+    pub fn with_theme(self, theme: Theme) -> PlotOptions {
+        PlotOptions { theme, ..self }
+    }
+
+    pub fn with_marker_size(self, marker_size: u32) -> PlotOptions {
+        PlotOptions {
+            marker_size,
+            ..self
+        }
+    }
+
+    pub fn with_title_font_size(self, title_font_size: u32) -> PlotOptions {
+        PlotOptions {
+            title_font_size: Some(title_font_size),
+            ..self
+        }
+    }
+
+    pub fn with_label_font_size(self, label_font_size: u32) -> PlotOptions {
+        PlotOptions {
+            label_font_size: Some(label_font_size),
+            ..self
+        }
+    }
+
+    /// Replaces the title generated from the query.
+    pub fn with_title(self, title: &str) -> PlotOptions {
+        PlotOptions {
+            title: Some(title.to_string()),
+            ..self
+        }
+    }
+
+    pub fn get_format(&self) -> PlotFormat {
+        self.format
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get_theme(&self) -> Theme {
+        self.theme
+    }
+
+    pub fn get_marker_size(&self) -> u32 {
+        self.marker_size
+    }
+
+    pub fn get_title_font_size(&self) -> Option<u32> {
+        self.title_font_size
+    }
+
+    pub fn get_label_font_size(&self) -> Option<u32> {
+        self.label_font_size
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Returns why the options can't be rendered.
+    pub fn check(&self) -> Result<(), String> {
+        let sizes = MIN_PLOT_SIZE..=MAX_PLOT_SIZE;
+        if !sizes.contains(&self.width) || !sizes.contains(&self.height) {
+            return Err(format!(
+                "width and height must be between {} and {} pixels",
+                MIN_PLOT_SIZE, MAX_PLOT_SIZE
+            ));
+        }
+        if !(1..=MAX_MARKER_SIZE).contains(&self.marker_size) {
+            return Err(format!(
+                "marker size must be between 1 and {}",
+                MAX_MARKER_SIZE
+            ));
+        }
+        let font_sizes = [self.title_font_size, self.label_font_size];
+        if font_sizes
+            .iter()
+            .flatten()
+            .any(|size| !(1..=MAX_FONT_SIZE).contains(size))
+        {
+            return Err(format!(
+                "font sizes must be between 1 and {}",
+                MAX_FONT_SIZE
+            ));
+        }
+        if self.title.as_ref().map_or(0, |title| title.chars().count()) > MAX_TITLE_LENGTH {
+            return Err(format!(
+                "title must be at most {} characters",
+                MAX_TITLE_LENGTH
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_options_match_the_original_plots() {
+        let options = PlotOptions::default();
+
+        assert_eq!(options.get_size(), (640, 480));
+        assert_eq!(options.get_theme(), Theme::Dark);
+        assert_eq!(options.get_format(), PlotFormat::Png);
+        assert_eq!(options.get_marker_size(), 5);
+        assert!(options.check().is_ok());
+    }
+
+    #[test]
+    fn options_outside_their_bounds_are_refused() {
+        let options = PlotOptions::default();
+
+        assert!(options.clone().with_size(50, 480).check().is_err());
+        assert!(options.clone().with_size(640, 5000).check().is_err());
+        assert!(options.clone().with_marker_size(0).check().is_err());
+        assert!(options.clone().with_label_font_size(500).check().is_err());
+        assert!(options
+            .clone()
+            .with_title(&"t".repeat(201))
+            .check()
+            .is_err());
+        assert!(options
+            .with_size(1920, 1080)
+            .with_theme(Theme::ColorBlind)
+            .with_title_font_size(48)
+            .with_title("Weight")
+            .check()
+            .is_ok());
+    }
+
+    #[test]
+    fn themes_are_parsed_by_name() {
+        for theme in [Theme::Dark, Theme::Light, Theme::ColorBlind] {
+            assert_eq!(Theme::parse(theme.as_str()), Some(theme));
+        }
+        assert_eq!(Theme::parse("neon"), None);
+    }
+}
@@ -1,89 +1,92 @@
+use crate::plotter::plotoptions::PlotOptions;
 use crate::plotter::util::*;
 use crate::queryresult::QueryResult;
 use crate::stats::model_fit::linear_regression;
 use crate::{linearfunction::LinearFunction, plotter::plotcolors::PlotColors};
-use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use chrono::{DateTime, Local, TimeZone};
+use plotters::coord::Shift;
 use plotters::prelude::*;
-use std::io::Error;
 
-pub fn scatterplot(
-    data: &QueryResult,
-    with_regression: bool,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let (datetimes, num_data) = match get_numeric_data(&data.get_datapoints()) {
-        Some(result) => result,
-        None => return Err(Box::new(Error::new(std::io::ErrorKind::NotFound, "test"))),
-    };
+type Series = Vec<(DateTime<Local>, f64)>;
+
+/// Everything a plot over time draws: the numeric datapoints of a query, the lines fitted
+/// through or drawn along them, and the ranges of both axes. Plots are rendered from it,
+/// and the API hands it out as is for charts drawn by the client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartData {
+    title: String,
+    x_range: (DateTime<Local>, DateTime<Local>),
+    y_range: (f64, f64),
+    as_dates: bool,
+    points: Series,
+    fitted_line: Option<Series>,
+    target_line: Option<Series>,
+}
 
-    let (lower_date, upper_date): (DateTime<Local>, DateTime<Local>) = get_daterange(&datetimes);
-    let (lower_num, upper_num): (f64, f64) = apply_margin(get_upper_lower(&num_data));
-    let as_date: bool = plot_as_dates((lower_date, upper_date));
-    let datapoints: Vec<(DateTime<Local>, f64)> = datetimes.into_iter().zip(num_data).collect();
+impl ChartData {
+    pub fn get_title(&self) -> &str {
+        &self.title
+    }
 
-    let filename = generate_filename(Local::now());
-    let location: String = format!("generated/{}", filename);
-    let plot_title: String = data.get_query().generate_plot_title();
+    pub fn get_x_range(&self) -> (DateTime<Local>, DateTime<Local>) {
+        self.x_range
+    }
 
-    let plot_colors = PlotColors::new();
-    let root = BitMapBackend::new(&location, (640, 480)).into_drawing_area();
-    root.fill(plot_colors.background())?;
+    pub fn get_y_range(&self) -> (f64, f64) {
+        self.y_range
+    }
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(
-            plot_title,
-            ("sans-serif", 35)
-                .with_color(plot_colors.textcolor())
-                .into_text_style(&root),
-        )
-        .margin(10)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(lower_date..upper_date, lower_num..upper_num)?;
+    /// Whether the x axis is labelled with dates rather than times of day.
+    pub fn labels_dates(&self) -> bool {
+        self.as_dates
+    }
 
-    chart
-        .configure_mesh()
-        .label_style(plot_colors.highlight())
-        .axis_style(plot_colors.textcolor())
-        .bold_line_style(plot_colors.highlight())
-        .light_line_style(plot_colors.darklight())
-        .x_label_formatter(&|datetime| format_datetime(datetime, as_date))
-        .draw()?;
+    pub fn get_points(&self) -> &Series {
+        &self.points
+    }
 
-    chart
-        .draw_series(
-            datapoints
-                .iter()
-                .map(|coord| Circle::new(*coord, 5, plot_colors.labelstyle().clone())),
-        )
-        .unwrap();
-
-    if with_regression {
-        let linear_function = linear_regression(datapoints.clone(), 50);
-        let fitted_line = linear_function.function();
-        chart
-            .draw_series(LineSeries::new(
-                datapoints
-                    .iter()
-                    .map(|(datetime, _)| (*datetime, fitted_line(datetime.timestamp().as_f64()))),
-                &CYAN,
-            ))
-            .unwrap();
+    pub fn get_fitted_line(&self) -> Option<&Series> {
+        self.fitted_line.as_ref()
     }
 
-    root.present()?;
+    pub fn get_target_line(&self) -> Option<&Series> {
+        self.target_line.as_ref()
+    }
+}
 
-    Ok(filename.to_owned())
+/// The chart of the numeric datapoints of the query, optionally with a regression line.
+/// `None` when none of the datapoints are numeric.
+pub fn scatter_chart(data: &QueryResult, with_regression: bool) -> Option<ChartData> {
+    let (datetimes, num_data) = get_numeric_data(&data.get_datapoints())?;
+    let x_range = get_daterange(&datetimes);
+    let y_range = apply_margin(get_upper_lower(&num_data));
+    let points: Series = datetimes.into_iter().zip(num_data).collect();
+    let fitted_line = with_regression.then(|| {
+        let linear_function = linear_regression(points.clone(), 50);
+        fitted_line(&linear_function, &points)
+    });
+
+    Some(ChartData {
+        title: data.get_query().generate_plot_title(),
+        x_range,
+        y_range,
+        as_dates: plot_as_dates(x_range),
+        points,
+        fitted_line,
+        target_line: None,
+    })
 }
 
-pub fn predictionplot(
+/// The chart of the numeric datapoints of the query with the fitted line extended to the
+/// date the target is predicted to be reached, and the target drawn across.
+pub fn prediction_chart(
     data: &QueryResult,
     linear_function: LinearFunction,
     target: f64,
-    date: NaiveDateTime,
-) -> String {
-    let (datetimes, num_data) = get_numeric_data(&data.get_datapoints()).unwrap();
+    date: DateTime<Local>,
+) -> Option<ChartData> {
+    let (datetimes, num_data) = get_numeric_data(&data.get_datapoints())?;
 
-    let date = Local.from_local_datetime(&date).unwrap();
     let (lower_date, mut upper_date): (DateTime<Local>, DateTime<Local>) =
         get_daterange(&datetimes);
     let timebuffer = (upper_date - lower_date) / 10;
@@ -93,71 +96,125 @@ pub fn predictionplot(
 
     let mut allnums = num_data.clone();
     allnums.push(target);
-    let (lower_num, upper_num): (f64, f64) = apply_margin(get_upper_lower(&allnums));
+    let y_range = apply_margin(get_upper_lower(&allnums));
+
+    let points: Series = datetimes.into_iter().zip(num_data).collect();
+    let mut line_points = points.clone();
+    line_points.push((date, target));
+
+    Some(ChartData {
+        title: data.get_query().generate_plot_title(),
+        x_range: (lower_date, upper_date),
+        y_range,
+        as_dates: plot_as_dates((lower_date, upper_date)),
+        fitted_line: Some(fitted_line(&linear_function, &line_points)),
+        target_line: Some(
+            line_points
+                .iter()
+                .map(|(datetime, _)| (*datetime, target))
+                .collect(),
+        ),
+        points,
+    })
+}
+
+fn fitted_line(linear_function: &LinearFunction, points: &[(DateTime<Local>, f64)]) -> Series {
+    let fitted = linear_function.function();
+    points
+        .iter()
+        .map(|(datetime, _)| (*datetime, fitted(datetime.timestamp() as f64)))
+        .collect()
+}
+
+pub fn scatterplot(
+    data: &QueryResult,
+    with_regression: bool,
+    options: &PlotOptions,
+) -> Result<Vec<u8>, PlotError> {
+    let chart = scatter_chart(data, with_regression).ok_or(PlotError::NoNumericData)?;
+
+    render_chart(&chart, options)
+}
 
-    let as_date: bool = plot_as_dates((lower_date, upper_date));
-    let mut datapoints: Vec<(DateTime<Local>, f64)> = datetimes.into_iter().zip(num_data).collect();
+pub fn predictionplot(
+    data: &QueryResult,
+    linear_function: LinearFunction,
+    target: f64,
+    date: DateTime<Local>,
+    options: &PlotOptions,
+) -> Result<Vec<u8>, PlotError> {
+    let chart =
+        prediction_chart(data, linear_function, target, date).ok_or(PlotError::NoNumericData)?;
+
+    render_chart(&chart, options)
+}
 
-    let filename = generate_filename(Local::now());
-    let location: String = format!("generated/{}", filename);
-    let plot_title: String = data.get_query().generate_plot_title();
+fn render_chart(chart: &ChartData, options: &PlotOptions) -> Result<Vec<u8>, PlotError> {
+    let image = match options.get_format() {
+        PlotFormat::Png => render_png(options.get_size(), |root| draw_chart(root, chart, options)),
+        PlotFormat::Svg => render_svg(options.get_size(), |root| draw_chart(root, chart, options)),
+    }?;
+    Ok(image)
+}
 
-    let plot_colors = PlotColors::new();
-    let root = BitMapBackend::new(&location, (640, 480)).into_drawing_area();
-    root.fill(plot_colors.background()).unwrap();
+fn draw_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    chart_data: &ChartData,
+    options: &PlotOptions,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let (lower_date, upper_date) = chart_data.x_range;
+    let (lower_num, upper_num) = chart_data.y_range;
+
+    let plot_colors = PlotColors::new(options.get_theme());
+    root.fill(plot_colors.background())?;
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .caption(
-            plot_title,
-            ("sans-serif", 35)
+            options.get_title().unwrap_or(&chart_data.title),
+            ("sans-serif", options.get_title_font_size().unwrap_or(35))
                 .with_color(plot_colors.textcolor())
-                .into_text_style(&root),
+                .into_text_style(root),
         )
         .margin(10)
         .x_label_area_size(30)
         .y_label_area_size(30)
-        .build_cartesian_2d(lower_date..upper_date, lower_num..upper_num)
-        .unwrap();
+        .build_cartesian_2d(lower_date..upper_date, lower_num..upper_num)?;
 
     chart
         .configure_mesh()
-        .label_style(plot_colors.highlight())
+        .label_style(label_style(options, &plot_colors))
         .axis_style(plot_colors.textcolor())
         .bold_line_style(plot_colors.highlight())
         .light_line_style(plot_colors.darklight())
-        .x_label_formatter(&|datetime| format_datetime(datetime, as_date))
-        .draw()
-        .unwrap();
+        .x_label_formatter(&|datetime| format_datetime(datetime, chart_data.as_dates))
+        .draw()?;
 
-    chart
-        .draw_series(
-            datapoints
-                .iter()
-                .map(|coord| Circle::new(*coord, 5, plot_colors.labelstyle().clone())),
+    chart.draw_series(chart_data.points.iter().map(|coord| {
+        Circle::new(
+            *coord,
+            options.get_marker_size(),
+            plot_colors.labelstyle().clone(),
         )
-        .unwrap();
-
-    let fitted_line = linear_function.function();
-    datapoints.push((date, target));
-    chart
-        .draw_series(LineSeries::new(
-            datapoints
-                .iter()
-                .map(|(datetime, _)| (*datetime, fitted_line(datetime.timestamp().as_f64()))),
-            &CYAN,
-        ))
-        .unwrap();
+    }))?;
 
-    chart
-        .draw_series(LineSeries::new(
-            datapoints.iter().map(|(datetime, _)| (*datetime, target)),
-            &GREEN,
-        ))
-        .unwrap();
+    if let Some(fitted_line) = &chart_data.fitted_line {
+        chart.draw_series(LineSeries::new(
+            fitted_line.iter().copied(),
+            plot_colors.fitted(),
+        ))?;
+    }
 
-    root.present().unwrap();
+    if let Some(target_line) = &chart_data.target_line {
+        chart.draw_series(LineSeries::new(
+            target_line.iter().copied(),
+            plot_colors.target(),
+        ))?;
+    }
 
-    return filename.to_string();
+    Ok(())
 }
 
 fn get_daterange(data: &Vec<DateTime<Local>>) -> (DateTime<Local>, DateTime<Local>) {
@@ -191,6 +248,7 @@ mod test {
     use crate::datapoint::create_datapoint;
     use crate::datapoint::Datapoint;
     use crate::parsedquery::ParsedQuery;
+    use crate::plotter::plotoptions::Theme;
     use chrono::Duration;
 
     use super::*;
@@ -234,20 +292,115 @@ mod test {
         let datapoints: Vec<Datapoint> = Vec::new();
         let data: QueryResult = QueryResult::from(datapoints, ParsedQuery::from(Vec::new()));
 
-        let output = scatterplot(&data, false);
+        let output = scatterplot(&data, false, &PlotOptions::default());
+
+        assert!(matches!(output, Err(PlotError::NoNumericData)));
+        assert_eq!(
+            output.unwrap_err().to_string(),
+            "no numeric datapoints match the query"
+        );
+    }
+
+    #[test]
+    fn scatterplot_can_be_rendered_as_svg() {
+        let datapoints = vec![
+            create_datapoint("80 +weight +DATE:2023-10-01"),
+            create_datapoint("79 +weight +DATE:2023-10-08"),
+        ];
+        let data = QueryResult::from(datapoints, ParsedQuery::from("weight"));
+        let options = PlotOptions::default().with_format(PlotFormat::Svg);
+
+        let svg = String::from_utf8(scatterplot(&data, true, &options).unwrap()).unwrap();
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("<circle"));
+        assert!(svg.contains("weight"));
+    }
+
+    #[test]
+    fn scatterplot_is_drawn_with_the_given_options() {
+        let datapoints = vec![
+            create_datapoint("80 +weight +DATE:2023-10-01"),
+            create_datapoint("79 +weight +DATE:2023-10-08"),
+        ];
+        let data = QueryResult::from(datapoints, ParsedQuery::from("weight"));
+        let options = PlotOptions::default()
+            .with_format(PlotFormat::Svg)
+            .with_size(800, 600)
+            .with_theme(Theme::ColorBlind)
+            .with_marker_size(9)
+            .with_title("My weight");
+
+        let svg = String::from_utf8(scatterplot(&data, false, &options).unwrap()).unwrap();
+
+        assert!(svg.contains("width=\"800\" height=\"600\""));
+        assert!(svg.contains("My weight"));
+        assert!(svg.contains("r=\"9\""));
+        assert!(svg.contains("#0072B2"));
+    }
+
+    #[test]
+    fn scatter_chart_holds_the_points_and_the_line_fitted_through_them() {
+        let datapoints = vec![
+            create_datapoint("80 +weight +DATE:2023-10-01"),
+            create_datapoint("79 +weight +DATE:2023-10-08"),
+            create_datapoint("78 +weight +DATE:2023-10-15"),
+        ];
+        let data = QueryResult::from(datapoints, ParsedQuery::from("weight"));
+
+        let chart = scatter_chart(&data, true).unwrap();
+
+        let values: Vec<f64> = chart.get_points().iter().map(|(_, y)| *y).collect();
+        assert_eq!(values, vec![80.0, 79.0, 78.0]);
+        let (lower, upper) = chart.get_y_range();
+        assert!(lower < 78.0 && upper > 80.0);
+        assert_eq!(chart.get_x_range().0, chart.get_points()[0].0);
+        assert!(chart.labels_dates());
+        let fitted = chart.get_fitted_line().unwrap();
+        assert_eq!(fitted.len(), 3);
+        assert!((fitted[1].1 - 79.0).abs() < 0.01);
+        assert_eq!(chart.get_target_line(), None);
+        assert_eq!(scatter_chart(&data, false).unwrap().get_fitted_line(), None);
+    }
 
-        assert_eq!(output.ok(), None);
+    #[test]
+    fn prediction_chart_extends_to_the_predicted_date() {
+        let datapoints = vec![
+            create_datapoint("80 +weight +DATE:2023-10-01"),
+            create_datapoint("79 +weight +DATE:2023-10-08"),
+        ];
+        let data = QueryResult::from(datapoints, ParsedQuery::from("weight"));
+        let points = scatter_chart(&data, false).unwrap().get_points().clone();
+        let linear_function = linear_regression(points, 50);
+        let date = Local.with_ymd_and_hms(2023, 10, 29, 0, 0, 0).unwrap();
+
+        let chart = prediction_chart(&data, linear_function, 76.0, date).unwrap();
+
+        assert!(chart.get_x_range().1 > date);
+        assert!(chart.get_y_range().0 < 76.0);
+        assert_eq!(chart.get_fitted_line().unwrap().last().unwrap().0, date);
+        let target_line = chart.get_target_line().unwrap();
+        assert!(target_line.iter().all(|(_, y)| *y == 76.0));
+        assert_eq!(target_line.len(), 3);
     }
 
     #[test]
     fn get_daterange_returns_first_and_last_date_for_two_or_more_datapoints() {
         let mut datapoints: Vec<DateTime<Local>> = Vec::new();
-        datapoints.push(create_datapoint("stuff").get_datetime().to_owned());
-        datapoints.push(create_datapoint("more stuff").get_datetime().to_owned());
+        datapoints.push(
+            create_datapoint("stuff")
+                .get_datetime()
+                .with_timezone(&Local),
+        );
+        datapoints.push(
+            create_datapoint("more stuff")
+                .get_datetime()
+                .with_timezone(&Local),
+        );
         datapoints.push(
             create_datapoint("even more stuff")
                 .get_datetime()
-                .to_owned(),
+                .with_timezone(&Local),
         );
         let expected_lower = datapoints[0];
         let expected_upper = datapoints[2];
@@ -261,7 +414,11 @@ mod test {
     #[test]
     fn get_daterange_returns_same_date_for_single_datapoint() {
         let mut datapoints: Vec<DateTime<Local>> = Vec::new();
-        datapoints.push(create_datapoint("stuff").get_datetime().to_owned());
+        datapoints.push(
+            create_datapoint("stuff")
+                .get_datetime()
+                .with_timezone(&Local),
+        );
         let expected = datapoints[0];
 
         let (lower, upper) = get_daterange(&datapoints);
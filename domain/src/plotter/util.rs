@@ -1,8 +1,103 @@
 use crate::datapoint::Datapoint;
+use crate::plotter::plotcolors::PlotColors;
+use crate::plotter::plotoptions::PlotOptions;
 use chrono::prelude::*;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::error::Error;
+use std::fmt;
+
+/// The image format plots are rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PlotFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+impl PlotFormat {
+    pub fn parse(format: &str) -> Option<PlotFormat> {
+        match format.to_lowercase().as_str() {
+            "png" => Some(PlotFormat::Png),
+            "svg" => Some(PlotFormat::Svg),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlotFormat::Png => "png",
+            PlotFormat::Svg => "svg",
+        }
+    }
+}
 
-pub fn generate_filename(timestamp: DateTime<Local>) -> String {
-    format!("{}.png", timestamp.format("%Y%m%d%H%M%S"))
+/// Why a plot could not be drawn.
+#[derive(Debug)]
+pub enum PlotError {
+    /// None of the datapoints to plot hold a number.
+    NoNumericData,
+    /// The backend failed to draw or encode the plot.
+    Render(String),
+}
+
+impl fmt::Display for PlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlotError::NoNumericData => write!(f, "no numeric datapoints match the query"),
+            PlotError::Render(reason) => write!(f, "rendering the plot failed: {}", reason),
+        }
+    }
+}
+
+impl Error for PlotError {}
+
+impl From<Box<dyn Error>> for PlotError {
+    fn from(error: Box<dyn Error>) -> PlotError {
+        PlotError::Render(error.to_string())
+    }
+}
+
+/// Runs `draw` on an in-memory bitmap of the size and returns it encoded as PNG.
+pub fn render_png<F>(size: (u32, u32), draw: F) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    F: FnOnce(&DrawingArea<BitMapBackend, Shift>) -> Result<(), Box<dyn Error>>,
+{
+    let (width, height) = size;
+    let mut pixels = vec![0; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, size).into_drawing_area();
+        draw(&root)?;
+        root.present()?;
+    }
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(&pixels, width, height, ColorType::Rgb8)?;
+    Ok(png)
+}
+
+/// Runs `draw` on an SVG document of the size and returns its text.
+pub fn render_svg<F>(size: (u32, u32), draw: F) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    F: FnOnce(&DrawingArea<SVGBackend, Shift>) -> Result<(), Box<dyn Error>>,
+{
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+        draw(&root)?;
+        root.present()?;
+    }
+    Ok(svg.into_bytes())
+}
+
+/// The style of axis labels: the label font size of the options, if any, in the highlight
+/// colour.
+pub(crate) fn label_style<'a>(options: &PlotOptions, colors: &'a PlotColors) -> TextStyle<'a> {
+    match options.get_label_font_size() {
+        Some(size) => ("sans-serif", size).into_font().color(colors.highlight()),
+        None => TextStyle::from(FontFamily::SansSerif).color(colors.highlight()),
+    }
 }
 
 pub fn get_upper_lower<T: Copy + PartialOrd>(points: &Vec<T>) -> (T, T) {
@@ -26,7 +121,7 @@ pub fn get_numeric_data(data: &Vec<Datapoint>) -> Option<(Vec<DateTime<Local>>,
         match datapoint.get_as_numeric() {
             Ok(num) => {
                 number_collector.push(num);
-                date_collector.push(datapoint.get_datetime().to_owned());
+                date_collector.push(datapoint.get_datetime().with_timezone(&Local));
             }
             Err(_) => (),
         };
@@ -60,12 +155,34 @@ mod test {
     }
 
     #[test]
-    fn generate_filename_function_takes_datetime_and_generates_filename_based_on_time() {
-        let datetime = Local.with_ymd_and_hms(2023, 10, 16, 10, 6, 32).unwrap();
+    fn render_png_returns_the_drawing_encoded_as_png() {
+        let png = render_png((640, 480), |root| {
+            root.fill(&WHITE)?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
 
-        let filename = generate_filename(datetime);
+    #[test]
+    fn render_svg_returns_the_drawing_as_an_svg_document() {
+        let svg = render_svg((640, 480), |root| {
+            root.fill(&WHITE)?;
+            Ok(())
+        })
+        .unwrap();
+
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("width=\"640\" height=\"480\""));
+    }
 
-        assert_eq!(filename, "20231016100632.png".to_string());
+    #[test]
+    fn plot_formats_are_parsed_from_their_extension() {
+        assert_eq!(PlotFormat::parse("SVG"), Some(PlotFormat::Svg));
+        assert_eq!(PlotFormat::parse("png").unwrap().extension(), "png");
+        assert_eq!(PlotFormat::parse("gif"), None);
     }
 
     #[test]
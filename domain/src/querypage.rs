@@ -0,0 +1,328 @@
+use crate::datapoint::Datapoint;
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    DateAscending,
+    DateDescending,
+    KeyAscending,
+    KeyDescending,
+}
+
+impl SortOrder {
+    /// Parses `date` or `key`, with a leading `-` for descending order.
+    pub fn parse(sort: &str) -> Option<SortOrder> {
+        match sort {
+            "date" => Some(SortOrder::DateAscending),
+            "-date" => Some(SortOrder::DateDescending),
+            "key" => Some(SortOrder::KeyAscending),
+            "-key" => Some(SortOrder::KeyDescending),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::DateAscending => "date",
+            SortOrder::DateDescending => "-date",
+            SortOrder::KeyAscending => "key",
+            SortOrder::KeyDescending => "-key",
+        }
+    }
+
+    /// Sorts by the chosen field, breaking ties between equal dates by key so every
+    /// datapoint has a fixed position for cursors to point at.
+    pub fn sort(&self, datapoints: &mut [Datapoint]) {
+        match self {
+            SortOrder::DateAscending => datapoints.sort_by_key(date_and_key),
+            SortOrder::DateDescending => {
+                datapoints.sort_by_key(|datapoint| Reverse(date_and_key(datapoint)))
+            }
+            SortOrder::KeyAscending => datapoints.sort_by_key(|datapoint| datapoint.get_key()),
+            SortOrder::KeyDescending => {
+                datapoints.sort_by_key(|datapoint| Reverse(datapoint.get_key()))
+            }
+        }
+    }
+
+    fn comes_after(&self, datapoint: &Datapoint, cursor: &Cursor) -> bool {
+        let position = (cursor.timestamp, cursor.key);
+        match self {
+            SortOrder::DateAscending => date_and_key(datapoint) > position,
+            SortOrder::DateDescending => date_and_key(datapoint) < position,
+            SortOrder::KeyAscending => datapoint.get_key() > cursor.key,
+            SortOrder::KeyDescending => datapoint.get_key() < cursor.key,
+        }
+    }
+}
+
+fn date_and_key(datapoint: &Datapoint) -> (i64, u64) {
+    (
+        datapoint.get_utc_datetime().timestamp_millis(),
+        datapoint.get_key(),
+    )
+}
+
+/// Points just past the last datapoint of a page. It is handed out as an opaque string so
+/// clients can't build their own, and it stays valid when datapoints are added or removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    order: SortOrder,
+    timestamp: i64,
+    key: u64,
+}
+
+impl Cursor {
+    fn after(datapoint: &Datapoint, order: SortOrder) -> Cursor {
+        let (timestamp, key) = date_and_key(datapoint);
+        Cursor {
+            order,
+            timestamp,
+            key,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}:{}:{}", self.order.as_str(), self.timestamp, self.key)
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decode(encoded: &str) -> Option<Cursor> {
+        if !encoded.len().is_multiple_of(2) || !encoded.is_ascii() {
+            return None;
+        }
+        let bytes = (0..encoded.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&encoded[index..index + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let decoded = String::from_utf8(bytes).ok()?;
+        let mut parts = decoded.split(':');
+        let order = SortOrder::parse(parts.next()?)?;
+        let timestamp = parts.next()?.parse().ok()?;
+        let key = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Cursor {
+            order,
+            timestamp,
+            key,
+        })
+    }
+
+    pub fn get_order(&self) -> SortOrder {
+        self.order
+    }
+}
+
+/// Which slice of a query result to return. A cursor continues where an earlier page
+/// ended; the offset is counted from there, or from the start without a cursor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRequest {
+    order: SortOrder,
+    limit: Option<usize>,
+    offset: usize,
+    cursor: Option<Cursor>,
+}
+
+impl PageRequest {
+    pub fn new(order: SortOrder) -> PageRequest {
+        PageRequest {
+            order,
+            limit: None,
+            offset: 0,
+            cursor: None,
+        }
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> PageRequest {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> PageRequest {
+        self.offset = offset;
+        self
+    }
+
+    /// The cursor decides the order, so pages are never continued in a different one.
+    pub fn with_cursor(mut self, cursor: Cursor) -> PageRequest {
+        self.order = cursor.order;
+        self.cursor = Some(cursor);
+        self
+    }
+
+    pub fn get_order(&self) -> SortOrder {
+        self.order
+    }
+
+    pub fn get_limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Sorts the datapoints and cuts out the requested page. `total` counts every
+    /// datapoint given, including those before the cursor.
+    pub fn apply(&self, mut datapoints: Vec<Datapoint>) -> QueryPage {
+        let total = datapoints.len();
+        self.order.sort(&mut datapoints);
+        if let Some(cursor) = &self.cursor {
+            datapoints.retain(|datapoint| self.order.comes_after(datapoint, cursor));
+        }
+        let mut remaining = datapoints.into_iter().skip(self.offset);
+        let page: Vec<Datapoint> = match self.limit {
+            Some(limit) => remaining.by_ref().take(limit).collect(),
+            None => remaining.by_ref().collect(),
+        };
+        let next_cursor = match (remaining.next(), page.last()) {
+            (Some(_), Some(last)) => Some(Cursor::after(last, self.order)),
+            _ => None,
+        };
+        QueryPage {
+            datapoints: page,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+pub struct QueryPage {
+    datapoints: Vec<Datapoint>,
+    total: usize,
+    next_cursor: Option<Cursor>,
+}
+
+impl QueryPage {
+    pub fn get_datapoints(&self) -> &Vec<Datapoint> {
+        &self.datapoints
+    }
+
+    pub fn into_datapoints(self) -> Vec<Datapoint> {
+        self.datapoints
+    }
+
+    pub fn get_total(&self) -> usize {
+        self.total
+    }
+
+    /// Present while more datapoints follow this page.
+    pub fn get_next_cursor(&self) -> Option<&Cursor> {
+        self.next_cursor.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint::tests::keyed;
+
+    fn datapoints() -> Vec<Datapoint> {
+        [
+            (1, "2023-01-03"),
+            (2, "2023-01-01"),
+            (3, "2023-01-02"),
+            (4, "2023-01-02"),
+        ]
+        .map(|(key, date)| keyed(&format!("{} +DATE:{} +TIME:12-00-00", key, date), key))
+        .to_vec()
+    }
+
+    fn keys(datapoints: &[Datapoint]) -> Vec<u64> {
+        datapoints
+            .iter()
+            .map(|datapoint| datapoint.get_key())
+            .collect()
+    }
+
+    #[test]
+    fn sort_orders_by_date_then_key_or_by_key_in_either_direction() {
+        let mut sorted = datapoints();
+
+        SortOrder::DateAscending.sort(&mut sorted);
+        assert_eq!(keys(&sorted), vec![2, 3, 4, 1]);
+        SortOrder::DateDescending.sort(&mut sorted);
+        assert_eq!(keys(&sorted), vec![1, 4, 3, 2]);
+        SortOrder::KeyDescending.sort(&mut sorted);
+        assert_eq!(keys(&sorted), vec![4, 3, 2, 1]);
+        assert_eq!(SortOrder::parse("-date"), Some(SortOrder::DateDescending));
+        assert_eq!(SortOrder::parse("data"), None);
+    }
+
+    #[test]
+    fn limit_and_offset_select_a_slice_and_total_counts_everything() {
+        let page = PageRequest::new(SortOrder::KeyAscending)
+            .with_offset(1)
+            .with_limit(2)
+            .apply(datapoints());
+
+        assert_eq!(keys(page.get_datapoints()), vec![2, 3]);
+        assert_eq!(page.get_total(), 4);
+        assert!(page.get_next_cursor().is_some());
+    }
+
+    #[test]
+    fn the_last_page_has_no_next_cursor() {
+        let page = PageRequest::new(SortOrder::KeyAscending)
+            .with_offset(2)
+            .with_limit(2)
+            .apply(datapoints());
+
+        assert_eq!(keys(page.get_datapoints()), vec![3, 4]);
+        assert!(page.get_next_cursor().is_none());
+    }
+
+    #[test]
+    fn following_cursors_walks_every_datapoint_newest_first() {
+        let mut walked = Vec::new();
+        let mut request = PageRequest::new(SortOrder::DateDescending).with_limit(3);
+        loop {
+            let page = request.apply(datapoints());
+            walked.extend(keys(page.get_datapoints()));
+            match page.get_next_cursor() {
+                Some(cursor) => {
+                    let decoded = Cursor::decode(&cursor.encode()).unwrap();
+                    request = PageRequest::new(SortOrder::KeyAscending)
+                        .with_limit(3)
+                        .with_cursor(decoded);
+                }
+                None => break,
+            }
+        }
+
+        assert_eq!(walked, vec![1, 4, 3, 2]);
+    }
+
+    #[test]
+    fn cursors_survive_removal_of_the_datapoint_they_point_past() {
+        let first = PageRequest::new(SortOrder::KeyAscending)
+            .with_limit(2)
+            .apply(datapoints());
+        let cursor = first.get_next_cursor().unwrap().clone();
+        let mut remaining = datapoints();
+        remaining.retain(|datapoint| datapoint.get_key() != 2);
+
+        let second = PageRequest::new(SortOrder::KeyAscending)
+            .with_cursor(cursor)
+            .apply(remaining);
+
+        assert_eq!(keys(second.get_datapoints()), vec![3, 4]);
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        assert_eq!(Cursor::decode("zz"), None);
+        assert_eq!(Cursor::decode("abc"), None);
+        assert_eq!(Cursor::decode(""), None);
+        let cursor = Cursor {
+            order: SortOrder::KeyAscending,
+            timestamp: 5,
+            key: 7,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+    }
+}
@@ -53,7 +53,7 @@ impl QueryResult {
         }
         let mut collector: Vec<(DateTime<Local>, f64)> = Vec::new();
         for datapoint in datapoints {
-            let datetime = datapoint.get_datetime().to_owned();
+            let datetime = datapoint.get_datetime().with_timezone(&Local);
             let value = match datapoint.get_as_numeric() {
                 Ok(value) => value,
                 Err(_) => return None,
@@ -97,19 +97,17 @@ fn from_date(datapoints: Vec<Datapoint>, date: String, return_before: bool) -> V
     } else {
         date.and_hms_opt(23, 59, 59).expect("23:59:59 is invalid")
     };
-    let mut location = 0;
-    while location < datapoints.len()
-        && datapoints[location].get_datetime().naive_local() < datetime
-    {
-        location += 1;
-    }
-    let mut before = datapoints.clone();
-    let after = before.drain(0..location).collect();
-    if return_before {
-        return before;
-    } else {
-        return after;
-    };
+    datapoints
+        .into_iter()
+        .filter(|datapoint| {
+            let logged_at = datapoint.get_datetime().naive_local();
+            if return_before {
+                logged_at >= datetime
+            } else {
+                logged_at < datetime
+            }
+        })
+        .collect()
 }
 
 fn strip_non_numeric(datapoints: Vec<Datapoint>) -> Vec<Datapoint> {
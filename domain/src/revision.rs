@@ -0,0 +1,168 @@
+use crate::datapoint::Datapoint;
+use chrono::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevisionKind {
+    Create,
+    Update,
+    TagChange,
+    Delete,
+    Restore,
+}
+
+impl RevisionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevisionKind::Create => "create",
+            RevisionKind::Update => "update",
+            RevisionKind::TagChange => "tagchange",
+            RevisionKind::Delete => "delete",
+            RevisionKind::Restore => "restore",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<RevisionKind> {
+        match kind {
+            "create" => Some(RevisionKind::Create),
+            "update" => Some(RevisionKind::Update),
+            "tagchange" => Some(RevisionKind::TagChange),
+            "delete" => Some(RevisionKind::Delete),
+            "restore" => Some(RevisionKind::Restore),
+            _ => None,
+        }
+    }
+}
+
+/// A single entry in the append-only change log of a datapoint, holding the datapoint as it
+/// was before and after the change. `before` is empty for creations and restores, `after`
+/// is empty for deletions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revision {
+    id: u64,
+    key: u64,
+    kind: RevisionKind,
+    recorded_at: DateTime<Utc>,
+    before: Option<Datapoint>,
+    after: Option<Datapoint>,
+}
+
+impl Revision {
+    pub fn new(
+        id: u64,
+        key: u64,
+        kind: RevisionKind,
+        recorded_at: DateTime<Utc>,
+        before: Option<Datapoint>,
+        after: Option<Datapoint>,
+    ) -> Revision {
+        Revision {
+            id,
+            key,
+            kind,
+            recorded_at,
+            before,
+            after,
+        }
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_key(&self) -> u64 {
+        self.key
+    }
+
+    pub fn get_kind(&self) -> RevisionKind {
+        self.kind
+    }
+
+    pub fn get_recorded_at(&self) -> &DateTime<Utc> {
+        &self.recorded_at
+    }
+
+    pub fn get_before(&self) -> Option<&Datapoint> {
+        self.before.as_ref()
+    }
+
+    pub fn get_after(&self) -> Option<&Datapoint> {
+        self.after.as_ref()
+    }
+
+    /// Moves the revision, including its snapshots, over to another datapoint key.
+    pub fn with_key(self, key: u64) -> Revision {
+        let rekey = |mut datapoint: Datapoint| {
+            datapoint.set_key(key);
+            datapoint
+        };
+        Revision {
+            key,
+            before: self.before.map(rekey),
+            after: self.after.map(rekey),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint::create_datapoint;
+
+    #[test]
+    fn revision_kinds_survive_a_round_trip_through_their_names() {
+        let kinds = vec![
+            RevisionKind::Create,
+            RevisionKind::Update,
+            RevisionKind::TagChange,
+            RevisionKind::Delete,
+            RevisionKind::Restore,
+        ];
+
+        for kind in kinds {
+            assert_eq!(RevisionKind::parse(kind.as_str()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn unknown_revision_kind_parses_to_none() {
+        assert_eq!(RevisionKind::parse("rename"), None);
+    }
+
+    #[test]
+    fn revision_exposes_its_contents() {
+        let before = create_datapoint("80kg +weight");
+        let after = create_datapoint("81kg +weight");
+        let recorded_at = Utc.with_ymd_and_hms(2023, 11, 1, 12, 0, 0).unwrap();
+
+        let revision = Revision::new(
+            2,
+            7,
+            RevisionKind::Update,
+            recorded_at,
+            Some(before.clone()),
+            Some(after.clone()),
+        );
+
+        assert_eq!(revision.get_id(), 2);
+        assert_eq!(revision.get_key(), 7);
+        assert_eq!(revision.get_kind(), RevisionKind::Update);
+        assert_eq!(revision.get_recorded_at(), &recorded_at);
+        assert_eq!(revision.get_before(), Some(&before));
+        assert_eq!(revision.get_after(), Some(&after));
+    }
+
+    #[test]
+    fn revision_can_be_moved_to_another_key() {
+        let mut before = create_datapoint("80kg +weight");
+        before.set_key(7);
+        let revision = Revision::new(2, 7, RevisionKind::Delete, Utc::now(), Some(before), None);
+
+        let moved = revision.with_key(12);
+
+        assert_eq!(moved.get_key(), 12);
+        assert_eq!(moved.get_before().unwrap().get_key(), 12);
+        assert_eq!(moved.get_after(), None);
+        assert_eq!(moved.get_id(), 2);
+    }
+}
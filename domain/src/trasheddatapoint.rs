@@ -0,0 +1,66 @@
+use crate::datapoint::Datapoint;
+use chrono::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashedDatapoint {
+    datapoint: Datapoint,
+    deleted_at: DateTime<Utc>,
+}
+
+impl TrashedDatapoint {
+    pub fn new(datapoint: Datapoint, deleted_at: DateTime<Utc>) -> TrashedDatapoint {
+        TrashedDatapoint {
+            datapoint,
+            deleted_at,
+        }
+    }
+
+    pub fn get_datapoint(&self) -> &Datapoint {
+        &self.datapoint
+    }
+
+    pub fn get_deleted_at(&self) -> &DateTime<Utc> {
+        &self.deleted_at
+    }
+
+    pub fn get_key(&self) -> u64 {
+        self.datapoint.get_key()
+    }
+
+    pub fn deleted_before(&self, cutoff: &DateTime<Utc>) -> bool {
+        &self.deleted_at < cutoff
+    }
+
+    pub fn into_datapoint(self) -> Datapoint {
+        self.datapoint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint::create_datapoint;
+
+    #[test]
+    fn trashed_datapoint_exposes_datapoint_and_deletion_time() {
+        let mut datapoint = create_datapoint("80kg +weight");
+        datapoint.set_key(3);
+        let deleted_at = Utc.with_ymd_and_hms(2023, 11, 1, 12, 0, 0).unwrap();
+
+        let trashed = TrashedDatapoint::new(datapoint.clone(), deleted_at);
+
+        assert_eq!(trashed.get_datapoint(), &datapoint);
+        assert_eq!(trashed.get_deleted_at(), &deleted_at);
+        assert_eq!(trashed.get_key(), 3);
+        assert_eq!(trashed.into_datapoint(), datapoint);
+    }
+
+    #[test]
+    fn trashed_datapoint_knows_whether_it_was_deleted_before_a_cutoff() {
+        let deleted_at = Utc.with_ymd_and_hms(2023, 11, 1, 12, 0, 0).unwrap();
+        let trashed = TrashedDatapoint::new(create_datapoint("80kg +weight"), deleted_at);
+
+        assert!(trashed.deleted_before(&Utc.with_ymd_and_hms(2023, 11, 2, 0, 0, 0).unwrap()));
+        assert!(!trashed.deleted_before(&deleted_at));
+    }
+}
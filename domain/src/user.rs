@@ -0,0 +1,202 @@
+use chrono::{DateTime, Duration, Utc};
+use pbkdf2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use pbkdf2::{Params, Pbkdf2};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// PBKDF2-SHA256 rounds recommended by OWASP. Lower counts only make sense in tests.
+pub const PASSWORD_ROUNDS: u32 = 600_000;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_USERNAME_LENGTH: usize = 64;
+
+/// An account owning its own datapoints. Only a salted PBKDF2 hash of the password is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    id: u64,
+    username: String,
+    password_hash: String,
+}
+
+impl User {
+    pub fn new(id: u64, username: &str, password_hash: &str) -> User {
+        User {
+            id,
+            username: username.to_string(),
+            password_hash: password_hash.to_string(),
+        }
+    }
+
+    pub fn with_id(self, id: u64) -> User {
+        User { id, ..self }
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn get_password_hash(&self) -> &str {
+        &self.password_hash
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        match PasswordHash::new(&self.password_hash) {
+            Ok(hash) => Pbkdf2.verify_password(password.as_bytes(), &hash).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Why an account could not be created.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistrationError {
+    /// The username or password can't be used, and why.
+    Invalid(String),
+    UsernameTaken,
+    /// Hashing the password or storing the account failed.
+    Failed,
+}
+
+/// Returns why the username or password can't be used for a new account.
+pub fn check_new_account(username: &str, password: &str) -> Result<(), String> {
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return Err(format!(
+            "username must be 1 to {} characters long",
+            MAX_USERNAME_LENGTH
+        ));
+    }
+    if username.chars().any(char::is_whitespace) {
+        return Err("username must not contain whitespace".to_string());
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "password must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Hashes the password with a fresh random salt into a PHC string, which records the
+/// rounds used so verification doesn't need to know them.
+pub fn hash_password(password: &str, rounds: u32) -> Option<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let params = Params {
+        rounds,
+        ..Params::default()
+    };
+    Pbkdf2
+        .hash_password_customized(password.as_bytes(), None, None, params, &salt)
+        .ok()
+        .map(|hash| hash.to_string())
+}
+
+/// A login of a user. The token identifying it is only handed to the client once; what is
+/// stored is its SHA-256 hash, so a leaked session table can't be used to log in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    user_id: u64,
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn new(user_id: u64, token_hash: &str, expires_at: DateTime<Utc>) -> Session {
+        Session {
+            user_id,
+            token_hash: token_hash.to_string(),
+            expires_at,
+        }
+    }
+
+    /// Starts a session lasting `lifetime` from `now`, returned with its token.
+    pub fn start(user_id: u64, now: DateTime<Utc>, lifetime: Duration) -> (Session, String) {
+        let token = generate_token();
+        let session = Session::new(user_id, &hash_token(&token), now + lifetime);
+        (session, token)
+    }
+
+    pub fn get_user_id(&self) -> u64 {
+        self.user_id
+    }
+
+    pub fn get_token_hash(&self) -> &str {
+        &self.token_hash
+    }
+
+    pub fn get_expires_at(&self) -> &DateTime<Utc> {
+        &self.expires_at
+    }
+
+    pub fn is_expired(&self, now: &DateTime<Utc>) -> bool {
+        self.expires_at <= *now
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_passwords_verify_only_the_original_password() {
+        let hash = hash_password("correct horse", 1000).unwrap();
+        let user = User::new(1, "alice", &hash);
+
+        assert!(hash.starts_with("$pbkdf2-sha256$"));
+        assert!(user.verify_password("correct horse"));
+        assert!(!user.verify_password("battery staple"));
+    }
+
+    #[test]
+    fn the_same_password_hashes_differently_every_time() {
+        assert_ne!(
+            hash_password("password", 1000),
+            hash_password("password", 1000)
+        );
+    }
+
+    #[test]
+    fn malformed_hashes_never_verify() {
+        let user = User::new(1, "alice", "not a hash");
+
+        assert!(!user.verify_password("not a hash"));
+    }
+
+    #[test]
+    fn new_accounts_need_a_plain_username_and_a_long_enough_password() {
+        assert_eq!(check_new_account("alice", "12345678"), Ok(()));
+        assert!(check_new_account("", "12345678").is_err());
+        assert!(check_new_account("al ice", "12345678").is_err());
+        assert!(check_new_account("alice", "1234567").is_err());
+    }
+
+    #[test]
+    fn sessions_store_the_hash_of_their_token_and_expire() {
+        let now = Utc::now();
+        let (session, token) = Session::start(3, now, Duration::days(1));
+
+        assert_eq!(token.len(), 64);
+        assert_eq!(session.get_token_hash(), hash_token(&token));
+        assert_ne!(session.get_token_hash(), token);
+        assert_eq!(session.get_user_id(), 3);
+        assert!(!session.is_expired(&now));
+        assert!(session.is_expired(&(now + Duration::days(1))));
+    }
+}
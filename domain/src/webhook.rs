@@ -0,0 +1,501 @@
+use crate::datapoint::Datapoint;
+use crate::datapointevent::{ChangeKind, DatapointEvent};
+use crate::parsedquery::ParsedQuery;
+use crate::queryresult::QueryResult;
+use chrono::{DateTime, Duration, Utc};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebhookTrigger {
+    /// A datapoint matching the query is created.
+    Created,
+    /// The value of a datapoint matching the query is on the other side of the threshold
+    /// than the value of the matching datapoint before it.
+    Threshold(f64),
+}
+
+impl WebhookTrigger {
+    /// Parses `created`, or `threshold` together with the threshold value.
+    pub fn parse(trigger: &str, threshold: Option<f64>) -> Option<WebhookTrigger> {
+        match (trigger, threshold) {
+            ("created", None) => Some(WebhookTrigger::Created),
+            ("threshold", Some(threshold)) if threshold.is_finite() => {
+                Some(WebhookTrigger::Threshold(threshold))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookTrigger::Created => "created",
+            WebhookTrigger::Threshold(_) => "threshold",
+        }
+    }
+
+    pub fn get_threshold(&self) -> Option<f64> {
+        match self {
+            WebhookTrigger::Created => None,
+            WebhookTrigger::Threshold(threshold) => Some(*threshold),
+        }
+    }
+}
+
+/// A URL that is sent a JSON payload whenever a change to the datapoints of its owner
+/// matching its query sets off its trigger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    id: u64,
+    owner: u64,
+    url: String,
+    query: String,
+    trigger: WebhookTrigger,
+    created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub fn new(
+        id: u64,
+        owner: u64,
+        url: &str,
+        query: &str,
+        trigger: WebhookTrigger,
+        created_at: DateTime<Utc>,
+    ) -> Webhook {
+        Webhook {
+            id,
+            owner,
+            url: url.to_string(),
+            query: query.to_string(),
+            trigger,
+            created_at,
+        }
+    }
+
+    pub fn with_id(self, id: u64) -> Webhook {
+        Webhook { id, ..self }
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_owner(&self) -> u64 {
+        self.owner
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn get_trigger(&self) -> WebhookTrigger {
+        self.trigger
+    }
+
+    pub fn get_created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn matches(&self, datapoint: &Datapoint) -> bool {
+        let parsed = ParsedQuery::from(self.query.as_str());
+        if parsed.empty() {
+            return true;
+        }
+        parsed.can_all_be_found_in(datapoint.get_tags())
+            && !QueryResult::from(vec![datapoint.clone()], parsed)
+                .apply_query_commands()
+                .get_datapoints()
+                .is_empty()
+    }
+
+    /// Whether the change sets the webhook off. `previous` is the value of the datapoint
+    /// matching the query just before the changed one, see `value_before`.
+    pub fn fires_on(&self, event: &DatapointEvent, previous: Option<f64>) -> bool {
+        if !self.matches(event.get_datapoint()) {
+            return false;
+        }
+        match (self.trigger, event.get_kind()) {
+            (WebhookTrigger::Created, ChangeKind::Created) => true,
+            (WebhookTrigger::Threshold(threshold), ChangeKind::Created | ChangeKind::Updated) => {
+                match (previous, event.get_datapoint().get_as_numeric()) {
+                    (Some(previous), Ok(value)) => (previous < threshold) != (value < threshold),
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The value of the latest numeric datapoint dated no later than `changed`, other than
+/// `changed` itself. `datapoints` must be sorted by time, as query results are.
+pub fn value_before(datapoints: &[Datapoint], changed: &Datapoint) -> Option<f64> {
+    datapoints
+        .iter()
+        .rev()
+        .filter(|datapoint| datapoint.get_key() != changed.get_key())
+        .filter(|datapoint| datapoint.get_datetime() <= changed.get_datetime())
+        .find_map(|datapoint| datapoint.get_as_numeric().ok())
+}
+
+/// Returns why the URL can't be given to a webhook. Hosts that are, or are named like, an
+/// address on the server's own network are refused, as a webhook could otherwise be used
+/// to reach services that are not meant to be reachable from outside.
+pub fn check_webhook_url(url: &str) -> Result<(), String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| "url must start with http:// or https://".to_string())?;
+    if rest.is_empty() || rest.starts_with('/') || url.chars().any(char::is_whitespace) {
+        return Err("url must name a host and not contain whitespace".to_string());
+    }
+    let host = host_of(rest).to_ascii_lowercase();
+    let host = host.trim_end_matches('.');
+    let private = match host.parse::<IpAddr>() {
+        Ok(address) => !is_public_address(address),
+        Err(_) => host == "localhost" || host.ends_with(".localhost"),
+    };
+    if private {
+        return Err("url must not point to a loopback, private or link-local address".to_string());
+    }
+    Ok(())
+}
+
+/// The host of what follows the scheme of a URL, without user info, port or brackets.
+fn host_of(authority_and_path: &str) -> &str {
+    let authority = authority_and_path
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    let host_and_port = authority.rsplit('@').next().unwrap_or_default();
+    match host_and_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host_and_port.split(':').next().unwrap_or_default(),
+    }
+}
+
+/// Whether the address is reachable from the internet at large, rather than being a
+/// loopback, private, link-local or otherwise reserved one.
+pub fn is_public_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            let [first, second, ..] = address.octets();
+            !(address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_unspecified()
+                || address.is_broadcast()
+                || address.is_multicast()
+                || address.is_documentation()
+                || first == 0
+                || (first == 100 && (64..128).contains(&second)))
+        }
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(mapped) => is_public_address(IpAddr::V4(mapped)),
+            None => {
+                !(address.is_loopback()
+                    || address.is_unspecified()
+                    || address.is_multicast()
+                    || address.is_unique_local()
+                    || address.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn parse(status: &str) -> Option<DeliveryStatus> {
+        match status {
+            "pending" => Some(DeliveryStatus::Pending),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+/// How often and how soon failed deliveries are tried again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, initial_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_delay,
+        }
+    }
+
+    /// The wait after the given number of failed attempts, doubling after each one.
+    fn delay_after(&self, attempts: u32) -> Duration {
+        self.initial_delay * 2_i32.saturating_pow(attempts.saturating_sub(1))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(5, Duration::seconds(30))
+    }
+}
+
+/// One payload queued for a webhook, which doubles as its entry in the delivery log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    id: u64,
+    webhook_id: u64,
+    url: String,
+    payload: String,
+    status: DeliveryStatus,
+    attempts: u32,
+    next_attempt_at: DateTime<Utc>,
+    last_result: Option<String>,
+}
+
+impl Delivery {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: u64,
+        webhook_id: u64,
+        url: &str,
+        payload: &str,
+        status: DeliveryStatus,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+        last_result: Option<String>,
+    ) -> Delivery {
+        Delivery {
+            id,
+            webhook_id,
+            url: url.to_string(),
+            payload: payload.to_string(),
+            status,
+            attempts,
+            next_attempt_at,
+            last_result,
+        }
+    }
+
+    /// A delivery of the payload to the webhook's URL, due right away.
+    pub fn queue(webhook: &Webhook, payload: &str, now: DateTime<Utc>) -> Delivery {
+        Delivery::new(
+            0,
+            webhook.get_id(),
+            webhook.get_url(),
+            payload,
+            DeliveryStatus::Pending,
+            0,
+            now,
+            None,
+        )
+    }
+
+    pub fn with_id(self, id: u64) -> Delivery {
+        Delivery { id, ..self }
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_webhook_id(&self) -> u64 {
+        self.webhook_id
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_payload(&self) -> &str {
+        &self.payload
+    }
+
+    pub fn get_status(&self) -> DeliveryStatus {
+        self.status
+    }
+
+    pub fn get_attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn get_next_attempt_at(&self) -> &DateTime<Utc> {
+        &self.next_attempt_at
+    }
+
+    pub fn get_last_result(&self) -> Option<&str> {
+        self.last_result.as_deref()
+    }
+
+    pub fn is_due(&self, now: &DateTime<Utc>) -> bool {
+        self.status == DeliveryStatus::Pending && self.next_attempt_at <= *now
+    }
+
+    /// Records an attempt answered with the HTTP status, or failed with the error. Any
+    /// 2xx status delivers the payload; otherwise it is tried again later, until the
+    /// policy's attempts are used up.
+    pub fn record_attempt(
+        self,
+        result: Result<u16, String>,
+        now: DateTime<Utc>,
+        policy: &RetryPolicy,
+    ) -> Delivery {
+        let attempts = self.attempts + 1;
+        let (delivered, last_result) = match result {
+            Ok(status) => ((200..300).contains(&status), format!("HTTP {}", status)),
+            Err(error) => (false, error),
+        };
+        let status = if delivered {
+            DeliveryStatus::Delivered
+        } else if attempts >= policy.max_attempts {
+            DeliveryStatus::Failed
+        } else {
+            DeliveryStatus::Pending
+        };
+        Delivery {
+            status,
+            attempts,
+            next_attempt_at: now + policy.delay_after(attempts),
+            last_result: Some(last_result),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datapoint::tests::keyed;
+
+    fn webhook(query: &str, trigger: WebhookTrigger) -> Webhook {
+        Webhook::new(1, 1, "http://localhost/hook", query, trigger, Utc::now())
+    }
+
+    #[test]
+    fn created_webhooks_fire_on_new_matching_datapoints_only() {
+        let webhook = webhook("weight", WebhookTrigger::Created);
+        let weight = keyed("80 +weight", 1);
+
+        assert!(webhook.fires_on(
+            &DatapointEvent::new(ChangeKind::Created, weight.clone()),
+            None
+        ));
+        assert!(!webhook.fires_on(&DatapointEvent::new(ChangeKind::Updated, weight), None));
+        assert!(!webhook.fires_on(
+            &DatapointEvent::new(ChangeKind::Created, keyed("80 +steps", 2)),
+            None
+        ));
+    }
+
+    #[test]
+    fn threshold_webhooks_fire_when_the_value_crosses_in_either_direction() {
+        let webhook = webhook("weight", WebhookTrigger::Threshold(80.0));
+        let created = |input: &str| DatapointEvent::new(ChangeKind::Created, keyed(input, 2));
+
+        assert!(webhook.fires_on(&created("81 +weight"), Some(79.5)));
+        assert!(webhook.fires_on(&created("79 +weight"), Some(80.0)));
+        assert!(!webhook.fires_on(&created("82 +weight"), Some(81.0)));
+        assert!(!webhook.fires_on(&created("82 +weight"), None));
+        assert!(!webhook.fires_on(&created("heavy +weight"), Some(79.0)));
+    }
+
+    #[test]
+    fn the_value_before_is_the_latest_earlier_numeric_datapoint() {
+        let datapoints = vec![
+            keyed("79 +weight +DATE:2023-01-01", 1),
+            keyed("heavy +weight +DATE:2023-01-02", 2),
+            keyed("81 +weight +DATE:2023-01-03", 3),
+            keyed("83 +weight +DATE:2023-01-05", 4),
+        ];
+        let changed = keyed("82 +weight +DATE:2023-01-04", 5);
+
+        assert_eq!(value_before(&datapoints, &changed), Some(81.0));
+        assert_eq!(value_before(&datapoints, &datapoints[0]), None);
+    }
+
+    #[test]
+    fn webhook_urls_need_an_http_scheme_and_a_host() {
+        assert!(check_webhook_url("https://example.com/hook").is_ok());
+        assert!(check_webhook_url("http://93.184.216.34:8080/hook").is_ok());
+        assert!(check_webhook_url("ftp://example.com").is_err());
+        assert!(check_webhook_url("https://").is_err());
+        assert!(check_webhook_url("https://exa mple.com").is_err());
+        assert_eq!(WebhookTrigger::parse("threshold", None), None);
+        assert_eq!(
+            WebhookTrigger::parse("created", None),
+            Some(WebhookTrigger::Created)
+        );
+    }
+
+    #[test]
+    fn webhook_urls_on_the_servers_own_network_are_refused() {
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "https://api.LOCALHOST./hook",
+            "http://10.0.0.5",
+            "http://192.168.1.1:80/",
+            "http://user@172.16.0.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://0.0.0.0",
+            "http://[::1]:8000/hook",
+            "http://[fe80::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(check_webhook_url(url).is_err(), "{} was accepted", url);
+        }
+        assert!(check_webhook_url("https://hooks.example.com:8443/in?to=10.0.0.1").is_ok());
+        assert!(check_webhook_url("http://[2606:4700::1111]/hook").is_ok());
+    }
+
+    #[test]
+    fn failed_deliveries_back_off_until_their_attempts_are_used_up() {
+        let now = Utc::now();
+        let policy = RetryPolicy::new(3, Duration::seconds(10));
+        let delivery = Delivery::queue(&webhook("", WebhookTrigger::Created), "{}", now);
+        assert!(delivery.is_due(&now));
+
+        let delivery = delivery.record_attempt(Ok(500), now, &policy);
+        assert_eq!(delivery.get_status(), DeliveryStatus::Pending);
+        assert_eq!(*delivery.get_next_attempt_at(), now + Duration::seconds(10));
+        assert!(!delivery.is_due(&now));
+
+        let delivery = delivery.record_attempt(Err("refused".to_string()), now, &policy);
+        assert_eq!(*delivery.get_next_attempt_at(), now + Duration::seconds(20));
+        let delivery = delivery.record_attempt(Ok(503), now, &policy);
+
+        assert_eq!(delivery.get_status(), DeliveryStatus::Failed);
+        assert_eq!(delivery.get_attempts(), 3);
+        assert_eq!(delivery.get_last_result(), Some("HTTP 503"));
+    }
+
+    #[test]
+    fn any_2xx_answer_delivers() {
+        let delivery = Delivery::queue(&webhook("", WebhookTrigger::Created), "{}", Utc::now())
+            .record_attempt(Ok(204), Utc::now(), &RetryPolicy::default());
+
+        assert_eq!(delivery.get_status(), DeliveryStatus::Delivered);
+        assert!(!delivery.is_due(&Utc::now()));
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::prelude::*;
+use domain::apitoken::{ApiToken, TokenScope};
+use sqlx::{mysql::MySqlRow, Row};
+
+pub struct ApiTokenDSO {
+    id: u64,
+    user_id: u64,
+    name: String,
+    scope: String,
+    token_hash: String,
+    created_at: i64,
+    last_used_at: Option<i64>,
+}
+
+impl ApiTokenDSO {
+    pub fn get_user_id(&self) -> u64 {
+        self.user_id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_scope(&self) -> &str {
+        &self.scope
+    }
+
+    pub fn get_token_hash(&self) -> &str {
+        &self.token_hash
+    }
+
+    pub fn get_created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+impl From<MySqlRow> for ApiTokenDSO {
+    fn from(row: MySqlRow) -> Self {
+        ApiTokenDSO {
+            id: row.try_get("id").unwrap(),
+            user_id: row.try_get("user_id").unwrap(),
+            name: row.try_get("name").unwrap(),
+            scope: row.try_get("scope").unwrap(),
+            token_hash: row.try_get("token_hash").unwrap(),
+            created_at: row.try_get("created_at").unwrap(),
+            last_used_at: row.try_get("last_used_at").unwrap(),
+        }
+    }
+}
+
+impl From<&ApiToken> for ApiTokenDSO {
+    fn from(api_token: &ApiToken) -> Self {
+        ApiTokenDSO {
+            id: api_token.get_id(),
+            user_id: api_token.get_user_id(),
+            name: api_token.get_name().to_string(),
+            scope: api_token.get_scope().as_str().to_string(),
+            token_hash: api_token.get_token_hash().to_string(),
+            created_at: api_token.get_created_at().timestamp(),
+            last_used_at: api_token.get_last_used_at().map(|used| used.timestamp()),
+        }
+    }
+}
+
+impl From<ApiTokenDSO> for ApiToken {
+    fn from(dso: ApiTokenDSO) -> Self {
+        let to_utc = |timestamp: i64| Utc.timestamp_opt(timestamp, 0).single().unwrap_or_default();
+        ApiToken::new(
+            dso.id,
+            dso.user_id,
+            &dso.name,
+            TokenScope::parse(&dso.scope).unwrap_or(TokenScope::Read),
+            &dso.token_hash,
+            to_utc(dso.created_at),
+            dso.last_used_at.map(to_utc),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_tokens_round_trip_at_second_precision() {
+        let created_at = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let api_token = ApiToken::new(7, 4, "cron", TokenScope::Write, "abc", created_at, None)
+            .used_at(created_at);
+
+        let restored = ApiToken::from(ApiTokenDSO::from(&api_token));
+
+        assert_eq!(restored, api_token);
+    }
+}
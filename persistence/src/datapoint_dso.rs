@@ -1,12 +1,15 @@
 use chrono::prelude::*;
 use domain::datapoint::Datapoint;
+use domain::trasheddatapoint::TrashedDatapoint;
 use sqlx::{mysql::MySqlRow, Row};
 
 pub struct DatapointDSO {
     datetime: i64,
+    utc_offset: Option<i32>,
     data: String,
     tags: Vec<String>,
     key: u64,
+    deleted_at: Option<i64>,
 }
 
 impl DatapointDSO {
@@ -18,6 +21,10 @@ impl DatapointDSO {
         self.datetime
     }
 
+    pub fn get_utc_offset(&self) -> Option<i32> {
+        self.utc_offset
+    }
+
     pub fn get_data(&self) -> String {
         self.data.clone()
     }
@@ -33,15 +40,42 @@ impl DatapointDSO {
     pub fn get_stringified_tags(&self) -> String {
         self.tags.join("_")
     }
+
+    pub fn get_deleted_at(&self) -> Option<i64> {
+        self.deleted_at
+    }
+
+    pub fn with_key(self, key: u64) -> DatapointDSO {
+        DatapointDSO { key, ..self }
+    }
+
+    /// Reads a datapoint snapshot from the `<prefix>data`, `<prefix>tags`, `<prefix>datetime`
+    /// and `<prefix>utc_offset` columns of a revision row, if the snapshot is present.
+    pub fn from_snapshot_row(row: &MySqlRow, prefix: &str, key: u64) -> Option<DatapointDSO> {
+        let column = |name: &str| format!("{}{}", prefix, name);
+        let data: Option<String> = row.try_get(column("data").as_str()).unwrap();
+        let tags: Option<String> = row.try_get(column("tags").as_str()).unwrap();
+        let datetime: Option<i64> = row.try_get(column("datetime").as_str()).unwrap();
+        Some(DatapointDSO {
+            data: data?,
+            tags: split_tags(tags?),
+            datetime: datetime?,
+            utc_offset: row.try_get(column("utc_offset").as_str()).unwrap(),
+            key,
+            deleted_at: None,
+        })
+    }
 }
 
 impl From<Datapoint> for DatapointDSO {
     fn from(datapoint: Datapoint) -> Self {
         DatapointDSO {
             datetime: datapoint.get_datetime().timestamp(),
+            utc_offset: Some(datapoint.get_utc_offset_seconds()),
             data: datapoint.get_data().to_owned(),
             tags: datapoint.get_tags().to_owned(),
             key: datapoint.get_key(),
+            deleted_at: None,
         }
     }
 }
@@ -51,21 +85,24 @@ impl From<MySqlRow> for DatapointDSO {
         DatapointDSO {
             data: row.try_get("data").unwrap(),
             datetime: row.try_get("datetime").unwrap(),
-            tags: row
-                .try_get::<String, &str>("tags")
-                .unwrap()
-                .split("_")
-                .map(|tag| tag.to_string())
-                .collect(),
-            key: row.try_get("data_key").unwrap(),
+            utc_offset: row.try_get("utc_offset").unwrap(),
+            tags: split_tags(row.try_get("tags").unwrap()),
+            key: row.try_get("id").unwrap(),
+            deleted_at: row.try_get("deleted_at").unwrap(),
         }
     }
 }
 
 impl Into<Datapoint> for DatapointDSO {
     fn into(self) -> Datapoint {
+        let instant = NaiveDateTime::from_timestamp_opt(self.datetime, 0).unwrap();
+        // rows stored before offsets were recorded were logged in the server's local time
+        let offset = match self.utc_offset.and_then(FixedOffset::east_opt) {
+            Some(offset) => offset,
+            None => Local.offset_from_utc_datetime(&instant).fix(),
+        };
         Datapoint::new(
-            Local.from_utc_datetime(&NaiveDateTime::from_timestamp_opt(self.datetime, 0).unwrap()),
+            offset.from_utc_datetime(&instant),
             self.data,
             self.tags,
             self.key,
@@ -73,6 +110,20 @@ impl Into<Datapoint> for DatapointDSO {
     }
 }
 
+impl From<DatapointDSO> for TrashedDatapoint {
+    fn from(dso: DatapointDSO) -> Self {
+        let deleted_at = Utc
+            .timestamp_opt(dso.deleted_at.unwrap_or(0), 0)
+            .single()
+            .unwrap_or_default();
+        TrashedDatapoint::new(dso.into(), deleted_at)
+    }
+}
+
+fn split_tags(tags: String) -> Vec<String> {
+    tags.split('_').map(|tag| tag.to_string()).collect()
+}
+
 #[cfg(test)]
 pub mod tests {
     use domain::datapoint::create_datapoint;
@@ -83,9 +134,11 @@ pub mod tests {
     fn datapoint_dso_exposes_data() {
         let datapoint_dso = DatapointDSO {
             datetime: 1698218241,
+            utc_offset: Some(7200),
             data: "Stuff".to_string(),
             tags: vec!["tag".to_string()],
             key: 5,
+            deleted_at: None,
         };
         assert_eq!(datapoint_dso.get_data(), "Stuff".to_string());
     }
@@ -94,9 +147,11 @@ pub mod tests {
     fn datapoint_dso_exposes_tags() {
         let datapoint_dso = DatapointDSO {
             datetime: 1698218241,
+            utc_offset: Some(7200),
             data: "Stuff".to_string(),
             tags: vec!["tag".to_string()],
             key: 5,
+            deleted_at: None,
         };
         assert_eq!(datapoint_dso.get_tags(), vec!["tag".to_string()]);
     }
@@ -105,9 +160,11 @@ pub mod tests {
     fn datapoint_dso_exposes_stringified_tags() {
         let datapoint_dso = DatapointDSO {
             datetime: 1698218241,
+            utc_offset: Some(7200),
             data: "Stuff".to_string(),
             tags: vec!["tag".to_string(), "another".to_string()],
             key: 5,
+            deleted_at: None,
         };
         assert_eq!(datapoint_dso.get_stringified_tags(), "tag_another");
     }
@@ -116,9 +173,11 @@ pub mod tests {
     fn datapoint_dso_exposes_key() {
         let datapoint_dso = DatapointDSO {
             datetime: 1698218241,
+            utc_offset: Some(7200),
             data: "Stuff".to_string(),
             tags: vec!["tag".to_string()],
             key: 5,
+            deleted_at: None,
         };
         assert_eq!(datapoint_dso.get_key(), 5);
     }
@@ -127,13 +186,65 @@ pub mod tests {
     fn datapoint_dso_exposes_datetime() {
         let datapoint_dso = DatapointDSO {
             datetime: 1698218241,
+            utc_offset: Some(7200),
             data: "Stuff".to_string(),
             tags: vec!["tag".to_string()],
             key: 5,
+            deleted_at: None,
         };
         assert_eq!(datapoint_dso.get_datetime(), 1698218241);
     }
 
+    #[test]
+    fn datapoint_dso_exposes_utc_offset() {
+        let datapoint_dso = DatapointDSO {
+            datetime: 1698218241,
+            utc_offset: Some(7200),
+            data: "Stuff".to_string(),
+            tags: vec!["tag".to_string()],
+            key: 5,
+            deleted_at: None,
+        };
+        assert_eq!(datapoint_dso.get_utc_offset(), Some(7200));
+    }
+
+    #[test]
+    fn datapoint_dso_exposes_deletion_time() {
+        let datapoint_dso = DatapointDSO {
+            datetime: 1698218241,
+            utc_offset: Some(7200),
+            data: "Stuff".to_string(),
+            tags: vec!["tag".to_string()],
+            key: 5,
+            deleted_at: Some(1698218300),
+        };
+        assert_eq!(datapoint_dso.get_deleted_at(), Some(1698218300));
+    }
+
+    #[test]
+    fn trashed_datapoint_dso_can_convert_into_trashed_datapoint() {
+        let datapoint_dso = DatapointDSO {
+            datetime: 1698216313,
+            utc_offset: Some(7200),
+            data: "Some stuff".to_string(),
+            tags: vec!["tag".to_string()],
+            key: 4,
+            deleted_at: Some(1698218300),
+        };
+
+        let trashed = TrashedDatapoint::from(datapoint_dso);
+
+        assert_eq!(
+            trashed.get_deleted_at(),
+            &Utc.with_ymd_and_hms(2023, 10, 25, 7, 18, 20).unwrap()
+        );
+        assert_eq!(
+            trashed.get_datapoint().get_data(),
+            &"Some stuff".to_string()
+        );
+        assert_eq!(trashed.get_key(), 4);
+    }
+
     #[test]
     fn datapoint_vec_can_convert_into_dso_vec() {
         let datapoints = vec![
@@ -152,15 +263,19 @@ pub mod tests {
         let datapoint_dsos = vec![
             DatapointDSO {
                 datetime: 1698218241,
+                utc_offset: Some(7200),
                 data: "Stuff".to_string(),
                 tags: vec!["tag".to_string()],
                 key: 5,
+                deleted_at: None,
             },
             DatapointDSO {
                 datetime: 1698218242,
+                utc_offset: Some(7200),
                 data: "More".to_string(),
                 tags: vec!["another".to_string()],
                 key: 6,
+                deleted_at: None,
             },
         ];
 
@@ -174,9 +289,11 @@ pub mod tests {
     fn datapoint_dso_can_convert_into_datapoint() {
         let datapoint_dso = DatapointDSO {
             datetime: 1698216313,
+            utc_offset: Some(7200),
             data: "Some stuff".to_string(),
             tags: vec!["tag".to_string()],
             key: 4,
+            deleted_at: None,
         };
 
         let datapoint: Datapoint = datapoint_dso.into();
@@ -190,12 +307,55 @@ pub mod tests {
         assert_eq!(datapoint.get_key(), 4);
     }
 
+    #[test]
+    fn datapoint_dso_keeps_the_offset_it_was_logged_in() {
+        let datapoint_dso = DatapointDSO {
+            datetime: 1698216313,
+            utc_offset: Some(-5 * 3600),
+            data: "Some stuff".to_string(),
+            tags: vec!["tag".to_string()],
+            key: 4,
+            deleted_at: None,
+        };
+
+        let datapoint: Datapoint = datapoint_dso.into();
+
+        assert_eq!(datapoint.get_utc_offset_seconds(), -5 * 3600);
+        assert_eq!(
+            datapoint.get_datetime().naive_local(),
+            NaiveDate::from_ymd_opt(2023, 10, 25)
+                .unwrap()
+                .and_hms_opt(1, 45, 13)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn datapoint_dso_without_offset_falls_back_on_local_time() {
+        let datapoint_dso = DatapointDSO {
+            datetime: 1698216313,
+            utc_offset: None,
+            data: "Some stuff".to_string(),
+            tags: vec!["tag".to_string()],
+            key: 4,
+            deleted_at: None,
+        };
+
+        let datapoint: Datapoint = datapoint_dso.into();
+
+        assert_eq!(
+            datapoint.get_datetime().naive_local(),
+            Local.timestamp_opt(1698216313, 0).unwrap().naive_local()
+        );
+    }
+
     #[test]
     fn datapoint_can_be_converted_into_dso() {
         let datapoint = Datapoint::new(
-            Utc.with_ymd_and_hms(2023, 10, 25, 6, 45, 13)
+            FixedOffset::east_opt(3600)
                 .unwrap()
-                .into(),
+                .with_ymd_and_hms(2023, 10, 25, 7, 45, 13)
+                .unwrap(),
             "Some stuff".to_string(),
             vec!["tag".to_string()],
             4,
@@ -204,6 +364,7 @@ pub mod tests {
         let datapoint_dso = DatapointDSO::from(datapoint);
 
         assert_eq!(datapoint_dso.datetime, 1698216313);
+        assert_eq!(datapoint_dso.utc_offset, Some(3600));
         assert_eq!(datapoint_dso.data, "Some stuff".to_string());
         assert_eq!(datapoint_dso.tags, vec!["tag".to_string()]);
         assert_eq!(datapoint_dso.key, 4);
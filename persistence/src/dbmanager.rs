@@ -1,7 +1,18 @@
+use crate::apitoken_dso::ApiTokenDSO;
 use crate::datapoint_dso::DatapointDSO;
+use crate::revision_dso::RevisionDSO;
+use crate::user_dso::{SessionDSO, UserDSO};
+use crate::webhook_dso::{DeliveryDSO, WebhookDSO};
+use chrono::{DateTime, Utc};
+use domain::apitoken::ApiToken;
+use domain::backup::{Backup, RestoreMode};
 use domain::datapoint::Datapoint;
+use domain::revision::{Revision, RevisionKind};
+use domain::trasheddatapoint::TrashedDatapoint;
+use domain::user::{RegistrationError, Session, User};
+use domain::webhook::{Delivery, Webhook};
 use sqlx::mysql::{MySqlPoolOptions, MySqlRow};
-use sqlx::MySqlPool;
+use sqlx::{MySql, MySqlPool, Transaction};
 use std::env;
 
 pub struct DBManager {
@@ -18,67 +29,704 @@ impl DBManager {
         DBManager { pool }
     }
 
-    pub async fn insert_datapoint(&self, datapoint: Datapoint) -> bool {
-        let dso: DatapointDSO = datapoint.into();
-        match sqlx::query(
-            "INSERT INTO datapoints(data, tags, datetime, data_key) VALUES (?, ?, ?, ?)",
+    pub async fn insert_datapoint(&self, owner: u64, datapoint: Datapoint) -> Option<u64> {
+        let keys = self.insert_datapoints(owner, vec![datapoint]).await?;
+        keys.first().copied()
+    }
+
+    /// Inserts all datapoints in a single transaction, returning their keys in order.
+    /// Nothing is inserted if any of the inserts fails.
+    pub async fn insert_datapoints(
+        &self,
+        owner: u64,
+        datapoints: Vec<Datapoint>,
+    ) -> Option<Vec<u64>> {
+        let mut transaction = self.pool.begin().await.ok()?;
+        let mut keys = Vec::new();
+        for datapoint in datapoints {
+            let dso: DatapointDSO = datapoint.into();
+            match insert_in_transaction(&mut transaction, owner, dso).await {
+                Some(key) => keys.push(key),
+                None => {
+                    let _ = transaction.rollback().await;
+                    return None;
+                }
+            }
+        }
+        transaction.commit().await.ok()?;
+        Some(keys)
+    }
+
+    pub async fn update_datapoint(&self, owner: u64, datapoint: Datapoint) -> bool {
+        self.update_datapoints(owner, vec![datapoint], RevisionKind::Update)
+            .await
+    }
+
+    pub async fn batch_update_datapoints(&self, owner: u64, datapoints: Vec<Datapoint>) -> bool {
+        self.update_datapoints(owner, datapoints, RevisionKind::TagChange)
+            .await
+    }
+
+    async fn update_datapoints(
+        &self,
+        owner: u64,
+        datapoints: Vec<Datapoint>,
+        kind: RevisionKind,
+    ) -> bool {
+        let mut transaction = match self.pool.begin().await {
+            Ok(transaction) => transaction,
+            Err(_) => return false,
+        };
+        for datapoint in datapoints {
+            let dso: DatapointDSO = datapoint.into();
+            let before = fetch_for_update(&mut transaction, owner, dso.get_key()).await;
+            let result = sqlx::query(
+                "UPDATE datapoints SET data = ?, tags = ?, datetime = ?, utc_offset = ? \
+                WHERE id = ? AND owner_id = ?",
+            )
+            .bind(dso.get_data())
+            .bind(dso.get_stringified_tags())
+            .bind(dso.get_datetime())
+            .bind(dso.get_utc_offset())
+            .bind(dso.get_key())
+            .bind(owner)
+            .execute(&mut *transaction)
+            .await;
+            if before.is_none()
+                || result.is_err()
+                || !record_revision(
+                    &mut transaction,
+                    dso.get_key(),
+                    kind,
+                    before.as_ref(),
+                    Some(&dso),
+                )
+                .await
+            {
+                let _ = transaction.rollback().await;
+                return false;
+            }
+        }
+        transaction.commit().await.is_ok()
+    }
+
+    pub async fn trash_datapoint(&self, owner: u64, key: u64, deleted_at: DateTime<Utc>) -> bool {
+        self.trash_datapoints(owner, vec![key], deleted_at).await
+    }
+
+    /// Moves all datapoints to the trash in one transaction, or none of them.
+    pub async fn trash_datapoints(
+        &self,
+        owner: u64,
+        keys: Vec<u64>,
+        deleted_at: DateTime<Utc>,
+    ) -> bool {
+        let mut transaction = match self.pool.begin().await {
+            Ok(transaction) => transaction,
+            Err(_) => return false,
+        };
+        for key in keys {
+            let before = fetch_for_update(&mut transaction, owner, key).await;
+            let trashed = match sqlx::query(
+                "UPDATE datapoints SET deleted_at = ? \
+                WHERE id = ? AND owner_id = ? AND deleted_at IS NULL",
+            )
+            .bind(deleted_at.timestamp())
+            .bind(key)
+            .bind(owner)
+            .execute(&mut *transaction)
+            .await
+            {
+                Ok(result) => result.rows_affected() == 1,
+                Err(_) => false,
+            };
+            if !trashed
+                || !record_revision(
+                    &mut transaction,
+                    key,
+                    RevisionKind::Delete,
+                    before.as_ref(),
+                    None,
+                )
+                .await
+            {
+                let _ = transaction.rollback().await;
+                return false;
+            }
+        }
+        transaction.commit().await.is_ok()
+    }
+
+    pub async fn restore_datapoint(&self, owner: u64, key: u64) -> bool {
+        let mut transaction = match self.pool.begin().await {
+            Ok(transaction) => transaction,
+            Err(_) => return false,
+        };
+        let after = fetch_for_update(&mut transaction, owner, key).await;
+        let restored = match sqlx::query(
+            "UPDATE datapoints SET deleted_at = NULL \
+            WHERE id = ? AND owner_id = ? AND deleted_at IS NOT NULL",
         )
-        .bind(dso.get_data())
-        .bind(dso.get_stringified_tags())
-        .bind(dso.get_datetime())
-        .bind(dso.get_key())
-        .execute(&self.pool)
+        .bind(key)
+        .bind(owner)
+        .execute(&mut *transaction)
         .await
         {
-            Ok(_) => true,
+            Ok(result) => result.rows_affected() == 1,
             Err(_) => false,
+        };
+        if !restored
+            || !record_revision(
+                &mut transaction,
+                key,
+                RevisionKind::Restore,
+                None,
+                after.as_ref(),
+            )
+            .await
+        {
+            let _ = transaction.rollback().await;
+            return false;
+        }
+        transaction.commit().await.is_ok()
+    }
+
+    /// Permanently deletes the datapoints. Their revisions are kept, as the revision log
+    /// only ever grows and is what records that they were deleted.
+    pub async fn delete_datapoints(&self, owner: u64, keys: Vec<u64>) -> bool {
+        let mut transaction = match self.pool.begin().await {
+            Ok(transaction) => transaction,
+            Err(_) => return false,
+        };
+        for key in keys {
+            let result = sqlx::query("DELETE FROM datapoints WHERE id = ? AND owner_id = ?")
+                .bind(key)
+                .bind(owner)
+                .execute(&mut *transaction)
+                .await;
+            if result.is_err() {
+                let _ = transaction.rollback().await;
+                return false;
+            }
         }
+        transaction.commit().await.is_ok()
+    }
+
+    pub async fn load_datapoints(&self, owner: u64) -> Vec<Datapoint> {
+        let query_rows = self
+            .fetch_db_datapoints(
+                "SELECT * FROM datapoints WHERE owner_id = ? AND deleted_at IS NULL ORDER BY datetime;",
+                owner,
+            )
+            .await;
+        let datapoint_dsos: Vec<DatapointDSO> = query_rows
+            .into_iter()
+            .map(|row| DatapointDSO::from(row))
+            .collect();
+        datapoint_dsos.into_iter().map(|dso| dso.into()).collect()
     }
 
-    pub async fn update_datapoint(&self, datapoint: Datapoint) -> bool {
-        let dso: DatapointDSO = datapoint.into();
-        match sqlx::query(
-            "UPDATE datapoints SET data = ?, tags = ?, datetime = ? WHERE data_key = ?",
+    pub async fn load_trash(&self, owner: u64) -> Vec<TrashedDatapoint> {
+        let query_rows = self
+            .fetch_db_datapoints(
+                "SELECT * FROM datapoints WHERE owner_id = ? AND deleted_at IS NOT NULL ORDER BY deleted_at;",
+                owner,
+            )
+            .await;
+        query_rows
+            .into_iter()
+            .map(|row| TrashedDatapoint::from(DatapointDSO::from(row)))
+            .collect()
+    }
+
+    /// Returns every revision recorded for the datapoint, oldest first, or None when
+    /// the revision log could not be read.
+    pub async fn load_revisions(&self, owner: u64, key: u64) -> Option<Vec<Revision>> {
+        let rows = sqlx::query(
+            "SELECT r.* FROM datapoint_revisions r JOIN datapoints d ON d.id = r.datapoint_id \
+            WHERE r.datapoint_id = ? AND d.owner_id = ? ORDER BY r.id",
         )
-        .bind(dso.get_data())
-        .bind(dso.get_stringified_tags())
-        .bind(dso.get_datetime())
-        .bind(dso.get_key())
-        .execute(&self.pool)
+        .bind(key)
+        .bind(owner)
+        .fetch_all(&self.pool)
+        .await
+        .ok()?;
+        Some(
+            rows.into_iter()
+                .map(|row| Revision::from(RevisionDSO::from(row)))
+                .collect(),
+        )
+    }
+
+    pub async fn load_all_revisions(&self, owner: u64) -> Option<Vec<Revision>> {
+        let rows = sqlx::query(
+            "SELECT r.* FROM datapoint_revisions r JOIN datapoints d ON d.id = r.datapoint_id \
+            WHERE d.owner_id = ? ORDER BY r.id",
+        )
+        .bind(owner)
+        .fetch_all(&self.pool)
         .await
+        .ok()?;
+        Some(
+            rows.into_iter()
+                .map(|row| Revision::from(RevisionDSO::from(row)))
+                .collect(),
+        )
+    }
+
+    /// Restores a backup in a single transaction, returning `(backed up, new)` pairs of
+    /// the keys storage allocated for the restored datapoints.
+    pub async fn restore_backup(
+        &self,
+        owner: u64,
+        backup: Backup,
+        mode: RestoreMode,
+    ) -> Option<Vec<(u64, u64)>> {
+        let mut transaction = self.pool.begin().await.ok()?;
+        match restore_in_transaction(&mut transaction, owner, backup, mode).await {
+            Some(keys) => {
+                transaction.commit().await.ok()?;
+                Some(keys)
+            }
+            None => {
+                let _ = transaction.rollback().await;
+                None
+            }
+        }
+    }
+
+    /// Stores a new account, returning its id. The first account to be registered adopts
+    /// the datapoints logged before there were accounts. A username that is already taken,
+    /// as reported by the unique key on usernames, fails with `UsernameTaken`.
+    pub async fn insert_user(&self, user: User) -> Result<u64, RegistrationError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| RegistrationError::Failed)?;
+        let id = match insert_user_in_transaction(&mut transaction, &user).await {
+            Ok(id) => id,
+            Err(error) => {
+                let _ = transaction.rollback().await;
+                return Err(error);
+            }
+        };
+        transaction
+            .commit()
+            .await
+            .map_err(|_| RegistrationError::Failed)?;
+        Ok(id)
+    }
+
+    pub async fn load_user(&self, username: &str) -> Option<User> {
+        sqlx::query("SELECT * FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await
+            .ok()?
+            .map(|row| User::from(UserDSO::from(row)))
+    }
+
+    pub async fn load_user_ids(&self) -> Vec<u64> {
+        match sqlx::query_scalar("SELECT id FROM users")
+            .fetch_all(&self.pool)
+            .await
         {
-            Ok(_) => true,
-            Err(_) => false,
+            Ok(ids) => ids,
+            Err(_) => panic!("Horrible failure in fetching database-stored users"),
         }
     }
 
-    pub async fn delete_datapoint(&self, key: u64) -> bool {
-        match sqlx::query("DELETE FROM datapoints WHERE data_key = ?")
-            .bind(key)
+    /// Stores the session, dropping the user's expired sessions along the way.
+    pub async fn insert_session(&self, session: &Session) -> bool {
+        let dso = SessionDSO::from(session);
+        let purged = sqlx::query("DELETE FROM sessions WHERE user_id = ? AND expires_at <= ?")
+            .bind(dso.get_user_id())
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool)
+            .await;
+        if purged.is_err() {
+            return false;
+        }
+        sqlx::query("INSERT INTO sessions(token_hash, user_id, expires_at) VALUES (?, ?, ?)")
+            .bind(dso.get_token_hash())
+            .bind(dso.get_user_id())
+            .bind(dso.get_expires_at())
+            .execute(&self.pool)
+            .await
+            .is_ok()
+    }
+
+    pub async fn load_session(&self, token_hash: &str) -> Option<Session> {
+        sqlx::query("SELECT * FROM sessions WHERE token_hash = ?")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .ok()?
+            .map(|row| Session::from(SessionDSO::from(row)))
+    }
+
+    pub async fn delete_session(&self, token_hash: &str) -> bool {
+        sqlx::query("DELETE FROM sessions WHERE token_hash = ?")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await
+            .is_ok()
+    }
+
+    pub async fn insert_api_token(&self, api_token: &ApiToken) -> Option<u64> {
+        let dso = ApiTokenDSO::from(api_token);
+        sqlx::query(
+            "INSERT INTO api_tokens(user_id, name, scope, token_hash, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(dso.get_user_id())
+        .bind(dso.get_name())
+        .bind(dso.get_scope())
+        .bind(dso.get_token_hash())
+        .bind(dso.get_created_at())
+        .execute(&self.pool)
+        .await
+        .ok()
+        .map(|result| result.last_insert_id())
+    }
+
+    pub async fn load_api_tokens(&self, user_id: u64) -> Option<Vec<ApiToken>> {
+        let rows = sqlx::query("SELECT * FROM api_tokens WHERE user_id = ? ORDER BY id")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await
+            .ok()?;
+        Some(
+            rows.into_iter()
+                .map(|row| ApiToken::from(ApiTokenDSO::from(row)))
+                .collect(),
+        )
+    }
+
+    pub async fn load_api_token(&self, token_hash: &str) -> Option<ApiToken> {
+        sqlx::query("SELECT * FROM api_tokens WHERE token_hash = ?")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .ok()?
+            .map(|row| ApiToken::from(ApiTokenDSO::from(row)))
+    }
+
+    /// Returns false when the user has no token with the id.
+    pub async fn delete_api_token(&self, user_id: u64, id: u64) -> bool {
+        match sqlx::query("DELETE FROM api_tokens WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
             .execute(&self.pool)
             .await
         {
-            Ok(_) => true,
+            Ok(result) => result.rows_affected() > 0,
             Err(_) => false,
         }
     }
 
-    pub async fn load_datapoints(&self) -> Vec<Datapoint> {
-        let query_rows = self.fetch_db_datapoints().await;
-        let datapoint_dsos: Vec<DatapointDSO> = query_rows
-            .into_iter()
-            .map(|row| DatapointDSO::from(row))
-            .collect();
-        datapoint_dsos.into_iter().map(|dso| dso.into()).collect()
+    pub async fn record_api_token_use(&self, id: u64, used_at: DateTime<Utc>) -> bool {
+        sqlx::query("UPDATE api_tokens SET last_used_at = ? WHERE id = ?")
+            .bind(used_at.timestamp())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .is_ok()
+    }
+
+    pub async fn insert_webhook(&self, webhook: &Webhook) -> Option<u64> {
+        let dso = WebhookDSO::from(webhook);
+        sqlx::query(
+            "INSERT INTO webhooks(owner_id, url, query, trigger_kind, threshold, created_at) \
+            VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(dso.get_owner())
+        .bind(dso.get_url())
+        .bind(dso.get_query())
+        .bind(dso.get_trigger_kind())
+        .bind(dso.get_threshold())
+        .bind(dso.get_created_at())
+        .execute(&self.pool)
+        .await
+        .ok()
+        .map(|result| result.last_insert_id())
     }
 
-    async fn fetch_db_datapoints(&self) -> Vec<MySqlRow> {
-        match sqlx::query("SELECT * FROM datapoints ORDER BY datetime;")
+    pub async fn load_webhooks(&self, owner: u64) -> Option<Vec<Webhook>> {
+        let rows = sqlx::query("SELECT * FROM webhooks WHERE owner_id = ? ORDER BY id")
+            .bind(owner)
             .fetch_all(&self.pool)
             .await
-        {
+            .ok()?;
+        Some(
+            rows.into_iter()
+                .filter_map(|row| Option::<Webhook>::from(WebhookDSO::from(row)))
+                .collect(),
+        )
+    }
+
+    /// Deletes the webhook together with its delivery log. Returns false when the owner has
+    /// no webhook with the id.
+    pub async fn delete_webhook(&self, owner: u64, id: u64) -> bool {
+        let Ok(mut transaction) = self.pool.begin().await else {
+            return false;
+        };
+        let deleted = sqlx::query("DELETE FROM webhooks WHERE id = ? AND owner_id = ?")
+            .bind(id)
+            .bind(owner)
+            .execute(&mut *transaction)
+            .await;
+        if !matches!(deleted, Ok(result) if result.rows_affected() > 0) {
+            return false;
+        }
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ?")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .is_ok()
+            && transaction.commit().await.is_ok()
+    }
+
+    pub async fn insert_delivery(&self, delivery: &Delivery) -> Option<u64> {
+        let dso = DeliveryDSO::from(delivery);
+        sqlx::query(
+            "INSERT INTO webhook_deliveries(webhook_id, url, payload, status, attempts, \
+            next_attempt_at, last_result) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(dso.get_webhook_id())
+        .bind(dso.get_url())
+        .bind(dso.get_payload())
+        .bind(dso.get_status())
+        .bind(dso.get_attempts())
+        .bind(dso.get_next_attempt_at())
+        .bind(dso.get_last_result())
+        .execute(&self.pool)
+        .await
+        .ok()
+        .map(|result| result.last_insert_id())
+    }
+
+    pub async fn update_delivery(&self, delivery: &Delivery) -> bool {
+        let dso = DeliveryDSO::from(delivery);
+        sqlx::query(
+            "UPDATE webhook_deliveries SET status = ?, attempts = ?, next_attempt_at = ?, \
+            last_result = ? WHERE id = ?",
+        )
+        .bind(dso.get_status())
+        .bind(dso.get_attempts())
+        .bind(dso.get_next_attempt_at())
+        .bind(dso.get_last_result())
+        .bind(dso.get_id())
+        .execute(&self.pool)
+        .await
+        .is_ok()
+    }
+
+    pub async fn load_deliveries(&self, webhook_id: u64) -> Option<Vec<Delivery>> {
+        let rows =
+            sqlx::query("SELECT * FROM webhook_deliveries WHERE webhook_id = ? ORDER BY id DESC")
+                .bind(webhook_id)
+                .fetch_all(&self.pool)
+                .await
+                .ok()?;
+        Some(
+            rows.into_iter()
+                .map(|row| Delivery::from(DeliveryDSO::from(row)))
+                .collect(),
+        )
+    }
+
+    pub async fn load_due_deliveries(&self, now: DateTime<Utc>) -> Option<Vec<Delivery>> {
+        let rows = sqlx::query(
+            "SELECT * FROM webhook_deliveries WHERE status = 'pending' AND next_attempt_at <= ? \
+            ORDER BY next_attempt_at, id",
+        )
+        .bind(now.timestamp())
+        .fetch_all(&self.pool)
+        .await
+        .ok()?;
+        Some(
+            rows.into_iter()
+                .map(|row| Delivery::from(DeliveryDSO::from(row)))
+                .collect(),
+        )
+    }
+
+    async fn fetch_db_datapoints(&self, query: &str, owner: u64) -> Vec<MySqlRow> {
+        match sqlx::query(query).bind(owner).fetch_all(&self.pool).await {
             Ok(rows) => rows,
             Err(_) => panic!("Horrible failure in fetching database-stored datapoints"),
         }
     }
 }
+
+async fn insert_in_transaction(
+    transaction: &mut Transaction<'_, MySql>,
+    owner: u64,
+    dso: DatapointDSO,
+) -> Option<u64> {
+    let key = insert_row(transaction, owner, &dso, None).await?;
+    let after = dso.with_key(key);
+    if !record_revision(transaction, key, RevisionKind::Create, None, Some(&after)).await {
+        return None;
+    }
+    Some(key)
+}
+
+async fn insert_row(
+    transaction: &mut Transaction<'_, MySql>,
+    owner: u64,
+    dso: &DatapointDSO,
+    deleted_at: Option<i64>,
+) -> Option<u64> {
+    let result = sqlx::query(
+        "INSERT INTO datapoints(data, tags, datetime, utc_offset, deleted_at, owner_id) \
+        VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(dso.get_data())
+    .bind(dso.get_stringified_tags())
+    .bind(dso.get_datetime())
+    .bind(dso.get_utc_offset())
+    .bind(deleted_at)
+    .bind(owner)
+    .execute(&mut **transaction)
+    .await
+    .ok()?;
+    Some(result.last_insert_id())
+}
+
+async fn restore_in_transaction(
+    transaction: &mut Transaction<'_, MySql>,
+    owner: u64,
+    backup: Backup,
+    mode: RestoreMode,
+) -> Option<Vec<(u64, u64)>> {
+    if mode == RestoreMode::Replace {
+        sqlx::query(
+            "DELETE r FROM datapoint_revisions r JOIN datapoints d ON d.id = r.datapoint_id \
+            WHERE d.owner_id = ?",
+        )
+        .bind(owner)
+        .execute(&mut **transaction)
+        .await
+        .ok()?;
+        sqlx::query("DELETE FROM datapoints WHERE owner_id = ?")
+            .bind(owner)
+            .execute(&mut **transaction)
+            .await
+            .ok()?;
+    }
+    let mut keys = Vec::new();
+    for datapoint in backup.get_datapoints() {
+        let dso = DatapointDSO::from(datapoint.clone());
+        let key = insert_row(transaction, owner, &dso, None).await?;
+        keys.push((datapoint.get_key(), key));
+    }
+    for trashed in backup.get_trash() {
+        let dso = DatapointDSO::from(trashed.get_datapoint().clone());
+        let deleted_at = Some(trashed.get_deleted_at().timestamp());
+        let key = insert_row(transaction, owner, &dso, deleted_at).await?;
+        keys.push((trashed.get_key(), key));
+    }
+    for revision in backup.remap_keys(&keys).get_revisions() {
+        let before = revision.get_before().cloned().map(DatapointDSO::from);
+        let after = revision.get_after().cloned().map(DatapointDSO::from);
+        let inserted = insert_revision(
+            transaction,
+            revision.get_key(),
+            revision.get_kind(),
+            revision.get_recorded_at(),
+            before.as_ref(),
+            after.as_ref(),
+        )
+        .await;
+        if !inserted {
+            return None;
+        }
+    }
+    Some(keys)
+}
+
+async fn insert_user_in_transaction(
+    transaction: &mut Transaction<'_, MySql>,
+    user: &User,
+) -> Result<u64, RegistrationError> {
+    let id = match sqlx::query("INSERT INTO users(username, password_hash) VALUES (?, ?)")
+        .bind(user.get_username())
+        .bind(user.get_password_hash())
+        .execute(&mut **transaction)
+        .await
+    {
+        Ok(result) => result.last_insert_id(),
+        Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+            return Err(RegistrationError::UsernameTaken)
+        }
+        Err(_) => return Err(RegistrationError::Failed),
+    };
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&mut **transaction)
+        .await
+        .map_err(|_| RegistrationError::Failed)?;
+    if users == 1 {
+        sqlx::query("UPDATE datapoints SET owner_id = ? WHERE owner_id IS NULL")
+            .bind(id)
+            .execute(&mut **transaction)
+            .await
+            .map_err(|_| RegistrationError::Failed)?;
+    }
+    Ok(id)
+}
+
+async fn fetch_for_update(
+    transaction: &mut Transaction<'_, MySql>,
+    owner: u64,
+    key: u64,
+) -> Option<DatapointDSO> {
+    sqlx::query("SELECT * FROM datapoints WHERE id = ? AND owner_id = ? FOR UPDATE")
+        .bind(key)
+        .bind(owner)
+        .fetch_optional(&mut **transaction)
+        .await
+        .ok()?
+        .map(DatapointDSO::from)
+}
+
+async fn record_revision(
+    transaction: &mut Transaction<'_, MySql>,
+    key: u64,
+    kind: RevisionKind,
+    before: Option<&DatapointDSO>,
+    after: Option<&DatapointDSO>,
+) -> bool {
+    insert_revision(transaction, key, kind, &Utc::now(), before, after).await
+}
+
+async fn insert_revision(
+    transaction: &mut Transaction<'_, MySql>,
+    key: u64,
+    kind: RevisionKind,
+    recorded_at: &DateTime<Utc>,
+    before: Option<&DatapointDSO>,
+    after: Option<&DatapointDSO>,
+) -> bool {
+    sqlx::query(
+        "INSERT INTO datapoint_revisions(datapoint_id, kind, recorded_at, \
+        before_data, before_tags, before_datetime, before_utc_offset, \
+        after_data, after_tags, after_datetime, after_utc_offset) \
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(key)
+    .bind(kind.as_str())
+    .bind(recorded_at.timestamp())
+    .bind(before.map(|dso| dso.get_data()))
+    .bind(before.map(|dso| dso.get_stringified_tags()))
+    .bind(before.map(|dso| dso.get_datetime()))
+    .bind(before.and_then(|dso| dso.get_utc_offset()))
+    .bind(after.map(|dso| dso.get_data()))
+    .bind(after.map(|dso| dso.get_stringified_tags()))
+    .bind(after.map(|dso| dso.get_datetime()))
+    .bind(after.and_then(|dso| dso.get_utc_offset()))
+    .execute(&mut **transaction)
+    .await
+    .is_ok()
+}
@@ -1,2 +1,8 @@
+pub mod apitoken_dso;
 pub mod datapoint_dso;
 pub mod dbmanager;
+pub mod repository;
+pub mod revision_dso;
+pub mod storage;
+pub mod user_dso;
+pub mod webhook_dso;
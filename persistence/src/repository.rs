@@ -0,0 +1,2052 @@
+use crate::dbmanager::DBManager;
+use crate::storage::Storage;
+use chrono::{Duration, Utc};
+use domain::apitoken::{check_token_name, is_api_token, ApiToken, TokenScope};
+use domain::backup::{Backup, RestoreMode};
+use domain::batchcommand::{BatchCommand, CommandHistory};
+use domain::datapoint::{Datapoint, EntryTimezone};
+use domain::datapointevent::{ChangeKind, DatapointEvent};
+use domain::datastore::Datastore;
+use domain::healthimport::{deduplicate, records_to_datapoints, HealthRecord, TagMapping};
+use domain::querypage::{PageRequest, QueryPage};
+use domain::queryresult::QueryResult;
+use domain::revision::Revision;
+use domain::trasheddatapoint::TrashedDatapoint;
+use domain::user::{
+    check_new_account, hash_password, hash_token, RegistrationError, Session, User, PASSWORD_ROUNDS,
+};
+use domain::webhook::{
+    check_webhook_url, value_before, Delivery, RetryPolicy, Webhook, WebhookTrigger,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Mutex as AsyncMutex, Notify};
+use tokio::task::spawn_blocking;
+
+/// The datapoints of one user, with the undo history of their batch operations.
+struct Workspace {
+    datastore: Datastore,
+    commands: CommandHistory,
+    /// Held by every change from reading the datapoints it is prepared from until it is
+    /// applied to the datastore, so concurrent changes can't overwrite each other.
+    writes: AsyncMutex<()>,
+}
+
+impl Workspace {
+    fn new(datastore: Datastore) -> Workspace {
+        Workspace {
+            datastore,
+            commands: CommandHistory::new(),
+            writes: AsyncMutex::new(()),
+        }
+    }
+}
+
+/// Called with the owner and the change for every change to the datapoints of any user.
+pub type RepositoryListener = Arc<dyn Fn(u64, DatapointEvent) + Send + Sync>;
+
+/// Renders the payload delivered to a webhook set off by a change, given the value a
+/// threshold was crossed from.
+pub type PayloadFormat =
+    Arc<dyn Fn(&Webhook, &DatapointEvent, Option<f64>) -> Option<String> + Send + Sync>;
+
+/// Owns an in-memory `Datastore` per user together with the `Storage`, writing every
+/// mutation through to storage first so the datastores only ever reflect persisted state.
+/// Every datapoint operation acts on the datapoints of `owner` alone.
+pub struct Repository<S: Storage = DBManager> {
+    workspaces: Mutex<HashMap<u64, Arc<Workspace>>>,
+    storage: S,
+    trash_retention: Duration,
+    session_lifetime: Duration,
+    password_rounds: u32,
+    retry_policy: RetryPolicy,
+    listener: Option<RepositoryListener>,
+    payload_format: Option<PayloadFormat>,
+    deliveries_queued: Notify,
+}
+
+impl<S: Storage> Repository<S> {
+    pub fn new(storage: S) -> Repository<S> {
+        Repository {
+            workspaces: Mutex::new(HashMap::new()),
+            storage,
+            trash_retention: Duration::days(30),
+            session_lifetime: Duration::days(30),
+            password_rounds: PASSWORD_ROUNDS,
+            retry_policy: RetryPolicy::default(),
+            listener: None,
+            payload_format: None,
+            deliveries_queued: Notify::new(),
+        }
+    }
+
+    pub async fn load(storage: S) -> Repository<S> {
+        let mut repository = Repository::new(storage);
+        for owner in repository.storage.load_user_ids().await {
+            let datapoints = repository.storage.load_datapoints(owner).await;
+            let trash = repository.storage.load_trash(owner).await;
+            repository =
+                repository.with_workspace(owner, Datastore::from(datapoints).with_trash(trash));
+        }
+        repository
+    }
+
+    /// Gives `owner` the datapoints of `datastore`, as already stored.
+    pub fn with_workspace(self, owner: u64, datastore: Datastore) -> Repository<S> {
+        let workspace = self.new_workspace(owner, datastore);
+        self.workspaces.lock().unwrap().insert(owner, workspace);
+        self
+    }
+
+    /// Reports every change to the datapoints of every user, once it has been stored.
+    pub fn with_listener(
+        self,
+        listener: impl Fn(u64, DatapointEvent) + Send + Sync + 'static,
+    ) -> Repository<S> {
+        let repository = Repository {
+            listener: Some(Arc::new(listener)),
+            ..self
+        };
+        for (owner, workspace) in repository.workspaces.lock().unwrap().iter() {
+            repository.listen_to(*owner, &workspace.datastore);
+        }
+        repository
+    }
+
+    /// Queues a delivery rendered in the format for every webhook set off by a change,
+    /// as part of making the change. Without a format, webhooks are never set off.
+    pub fn with_webhook_payloads(
+        self,
+        format: impl Fn(&Webhook, &DatapointEvent, Option<f64>) -> Option<String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Repository<S> {
+        Repository {
+            payload_format: Some(Arc::new(format)),
+            ..self
+        }
+    }
+
+    pub fn with_trash_retention(self, trash_retention: Duration) -> Repository<S> {
+        Repository {
+            trash_retention,
+            ..self
+        }
+    }
+
+    pub fn with_session_lifetime(self, session_lifetime: Duration) -> Repository<S> {
+        Repository {
+            session_lifetime,
+            ..self
+        }
+    }
+
+    /// Rounds used for hashing new passwords. Existing hashes keep the rounds they were
+    /// created with.
+    pub fn with_password_rounds(self, password_rounds: u32) -> Repository<S> {
+        Repository {
+            password_rounds,
+            ..self
+        }
+    }
+
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Repository<S> {
+        Repository {
+            retry_policy,
+            ..self
+        }
+    }
+
+    fn workspace(&self, owner: u64) -> Arc<Workspace> {
+        self.workspaces
+            .lock()
+            .unwrap()
+            .entry(owner)
+            .or_insert_with(|| self.new_workspace(owner, Datastore::new()))
+            .clone()
+    }
+
+    fn new_workspace(&self, owner: u64, datastore: Datastore) -> Arc<Workspace> {
+        self.listen_to(owner, &datastore);
+        Arc::new(Workspace::new(datastore))
+    }
+
+    fn listen_to(&self, owner: u64, datastore: &Datastore) {
+        if let Some(listener) = &self.listener {
+            let listener = listener.clone();
+            datastore.set_listener(Box::new(move |event| listener(owner, event)));
+        }
+    }
+
+    /// Creates an account. The first account adopts the datapoints stored before there
+    /// were accounts, so they are loaded along with it. Hashing runs on a blocking thread,
+    /// as it takes long enough to hold up every other request otherwise.
+    pub async fn register(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<User, RegistrationError> {
+        check_new_account(username, password).map_err(RegistrationError::Invalid)?;
+        let password = password.to_string();
+        let rounds = self.password_rounds;
+        let password_hash = spawn_blocking(move || hash_password(&password, rounds))
+            .await
+            .ok()
+            .flatten()
+            .ok_or(RegistrationError::Failed)?;
+        let user = User::new(0, username, &password_hash);
+        let id = self.storage.insert_user(user.clone()).await?;
+        let datapoints = self.storage.load_datapoints(id).await;
+        let trash = self.storage.load_trash(id).await;
+        let workspace = self.new_workspace(id, Datastore::from(datapoints).with_trash(trash));
+        self.workspaces.lock().unwrap().insert(id, workspace);
+        Ok(user.with_id(id))
+    }
+
+    /// Starts a session for the user when the password matches, returning the token that
+    /// identifies it from now on. An unknown username costs a hash all the same, so the
+    /// time taken doesn't tell which usernames exist.
+    pub async fn login(&self, username: &str, password: &str) -> Option<(User, String, Session)> {
+        let user = self.storage.load_user(username).await;
+        let password = password.to_string();
+        let rounds = self.password_rounds;
+        let user = spawn_blocking(move || match user {
+            Some(user) => user.verify_password(&password).then_some(user),
+            None => {
+                hash_password(&password, rounds);
+                None
+            }
+        })
+        .await
+        .ok()??;
+        let (session, token) = Session::start(user.get_id(), Utc::now(), self.session_lifetime);
+        if !self.storage.insert_session(session.clone()).await {
+            return None;
+        }
+        Some((user, token, session))
+    }
+
+    /// Returns the user a token acts for and what it may do: anything for a session that
+    /// has not expired, and what its scope allows for an API token. Uses of API tokens are
+    /// recorded, though no more than once a minute.
+    pub async fn authenticate(&self, token: &str) -> Option<(u64, TokenScope)> {
+        let now = Utc::now();
+        if is_api_token(token) {
+            let api_token = self.storage.load_api_token(&hash_token(token)).await?;
+            if api_token.needs_use_recorded(&now, Duration::minutes(1)) {
+                self.storage
+                    .record_api_token_use(api_token.get_id(), now)
+                    .await;
+            }
+            return Some((api_token.get_user_id(), api_token.get_scope()));
+        }
+        let session = self.storage.load_session(&hash_token(token)).await?;
+        if session.is_expired(&now) {
+            return None;
+        }
+        Some((session.get_user_id(), TokenScope::Admin))
+    }
+
+    pub async fn logout(&self, token: &str) -> bool {
+        self.storage.delete_session(&hash_token(token)).await
+    }
+
+    /// Creates an API token for the user, returned with the token itself, which can't be
+    /// retrieved again later.
+    pub async fn create_api_token(
+        &self,
+        owner: u64,
+        name: &str,
+        scope: TokenScope,
+    ) -> Option<(ApiToken, String)> {
+        check_token_name(name).ok()?;
+        let (api_token, token) = ApiToken::create(owner, name, scope, Utc::now());
+        let id = self.storage.insert_api_token(api_token.clone()).await?;
+        Some((api_token.with_id(id), token))
+    }
+
+    pub async fn api_tokens(&self, owner: u64) -> Option<Vec<ApiToken>> {
+        self.storage.load_api_tokens(owner).await
+    }
+
+    /// Returns false when the user has no token with the id.
+    pub async fn revoke_api_token(&self, owner: u64, id: u64) -> bool {
+        self.storage.delete_api_token(owner, id).await
+    }
+
+    /// Registers a webhook for the user, or returns `None` when the URL can't be used.
+    pub async fn create_webhook(
+        &self,
+        owner: u64,
+        url: &str,
+        query: &str,
+        trigger: WebhookTrigger,
+    ) -> Option<Webhook> {
+        check_webhook_url(url).ok()?;
+        let webhook = Webhook::new(0, owner, url, query.trim(), trigger, Utc::now());
+        let id = self.storage.insert_webhook(webhook.clone()).await?;
+        Some(webhook.with_id(id))
+    }
+
+    pub async fn webhooks(&self, owner: u64) -> Option<Vec<Webhook>> {
+        self.storage.load_webhooks(owner).await
+    }
+
+    /// Returns false when the user has no webhook with the id.
+    pub async fn delete_webhook(&self, owner: u64, id: u64) -> bool {
+        self.storage.delete_webhook(owner, id).await
+    }
+
+    /// The delivery log of a webhook of the user, newest first, or `None` when the user has
+    /// no webhook with the id.
+    pub async fn deliveries(&self, owner: u64, webhook_id: u64) -> Option<Vec<Delivery>> {
+        let webhooks = self.storage.load_webhooks(owner).await?;
+        if !webhooks
+            .iter()
+            .any(|webhook| webhook.get_id() == webhook_id)
+        {
+            return None;
+        }
+        self.storage.load_deliveries(webhook_id).await
+    }
+
+    /// The webhooks that changes to the datapoints of the user may set off, loaded before
+    /// the changes are made so they can be checked against each change right away.
+    async fn webhooks_to_check(&self, owner: u64) -> Vec<Webhook> {
+        if self.payload_format.is_none() {
+            return Vec::new();
+        }
+        self.storage.load_webhooks(owner).await.unwrap_or_default()
+    }
+
+    /// Queues a delivery for every webhook set off by the changes just made to the
+    /// datapoints. Thresholds are compared against the datapoints as they are right after
+    /// the changes, before anything else can change them.
+    async fn fire_webhooks(
+        &self,
+        workspace: &Workspace,
+        webhooks: &[Webhook],
+        kind: ChangeKind,
+        changed: &[Datapoint],
+    ) {
+        let format = match &self.payload_format {
+            Some(format) if !webhooks.is_empty() => format.clone(),
+            _ => return,
+        };
+        let mut payloads = Vec::new();
+        for webhook in webhooks {
+            let matching = workspace
+                .datastore
+                .query(webhook.get_query())
+                .get_datapoints();
+            for datapoint in changed {
+                let event = DatapointEvent::new(kind, datapoint.clone());
+                let previous = value_before(&matching, datapoint);
+                if webhook.fires_on(&event, previous) {
+                    let payload = format(webhook, &event, previous);
+                    payloads.extend(payload.map(|payload| (webhook, payload)));
+                }
+            }
+        }
+        for (webhook, payload) in payloads {
+            self.queue_delivery(webhook, &payload).await;
+        }
+    }
+
+    pub async fn queue_delivery(&self, webhook: &Webhook, payload: &str) -> Option<Delivery> {
+        let delivery = Delivery::queue(webhook, payload, Utc::now());
+        let id = self.storage.insert_delivery(delivery.clone()).await?;
+        self.deliveries_queued.notify_one();
+        Some(delivery.with_id(id))
+    }
+
+    /// Waits until a delivery is queued, returning at once if one was queued since the
+    /// last wait.
+    pub async fn delivery_queued(&self) {
+        self.deliveries_queued.notified().await;
+    }
+
+    /// The pending deliveries of every user whose next attempt is due.
+    pub async fn due_deliveries(&self) -> Vec<Delivery> {
+        self.storage
+            .load_due_deliveries(Utc::now())
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Records the outcome of an attempt at a delivery, scheduling the next attempt as the
+    /// retry policy says.
+    pub async fn record_delivery_attempt(
+        &self,
+        delivery: Delivery,
+        result: Result<u16, String>,
+    ) -> Option<Delivery> {
+        let delivery = delivery.record_attempt(result, Utc::now(), &self.retry_policy);
+        if !self.storage.update_delivery(delivery.clone()).await {
+            return None;
+        }
+        Some(delivery)
+    }
+
+    /// Adds the entry, reading its date and time in the timezone it was logged in.
+    pub async fn add_datapoint(
+        &self,
+        owner: u64,
+        input: &str,
+        timezone: EntryTimezone,
+    ) -> Option<Datapoint> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let webhooks = self.webhooks_to_check(owner).await;
+        let mut datapoint = workspace.datastore.prepare_datapoint(input, timezone);
+        let key = self
+            .storage
+            .insert_datapoint(owner, datapoint.clone())
+            .await?;
+        datapoint.set_key(key);
+        workspace.datastore.insert_datapoint(datapoint.clone());
+        self.fire_webhooks(
+            &workspace,
+            &webhooks,
+            ChangeKind::Created,
+            std::slice::from_ref(&datapoint),
+        )
+        .await;
+        Some(datapoint)
+    }
+
+    /// Stores already parsed datapoints, such as imported ones, all at once. Either all of
+    /// them are added or, when storage fails, none.
+    pub async fn import_datapoints(
+        &self,
+        owner: u64,
+        datapoints: Vec<Datapoint>,
+    ) -> Option<Vec<Datapoint>> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        self.insert_imported(owner, &workspace, datapoints).await
+    }
+
+    async fn insert_imported(
+        &self,
+        owner: u64,
+        workspace: &Workspace,
+        datapoints: Vec<Datapoint>,
+    ) -> Option<Vec<Datapoint>> {
+        let webhooks = self.webhooks_to_check(owner).await;
+        let keys = self
+            .storage
+            .insert_datapoints(owner, datapoints.clone())
+            .await?;
+        let mut imported = Vec::new();
+        for (mut datapoint, key) in datapoints.into_iter().zip(keys) {
+            datapoint.set_key(key);
+            workspace.datastore.insert_datapoint(datapoint.clone());
+            imported.push(datapoint);
+        }
+        self.fire_webhooks(workspace, &webhooks, ChangeKind::Created, &imported)
+            .await;
+        Some(imported)
+    }
+
+    /// Imports health export records as tagged datapoints, leaving out records that
+    /// duplicate a datapoint already stored, trashed ones included.
+    pub async fn import_health_records(
+        &self,
+        owner: u64,
+        records: Vec<HealthRecord>,
+        mapping: &TagMapping,
+    ) -> Option<Vec<Datapoint>> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let mut existing = workspace.datastore.retrieve_datapoints();
+        existing.extend(
+            workspace
+                .datastore
+                .retrieve_trash()
+                .into_iter()
+                .map(|trashed| trashed.into_datapoint()),
+        );
+        let datapoints = deduplicate(records_to_datapoints(records, mapping), &existing);
+        self.insert_imported(owner, &workspace, datapoints).await
+    }
+
+    pub async fn update_datapoint(
+        &self,
+        owner: u64,
+        input: &str,
+        key: u64,
+        timezone: EntryTimezone,
+    ) -> Option<Datapoint> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        if !workspace.datastore.contains_key(key) {
+            return None;
+        }
+        let webhooks = self.webhooks_to_check(owner).await;
+        let datapoint = workspace.datastore.prepare_update(input, key, timezone);
+        if !self
+            .storage
+            .update_datapoint(owner, datapoint.clone())
+            .await
+        {
+            return None;
+        }
+        workspace
+            .datastore
+            .replace_datapoints(vec![datapoint.clone()]);
+        self.fire_webhooks(
+            &workspace,
+            &webhooks,
+            ChangeKind::Updated,
+            std::slice::from_ref(&datapoint),
+        )
+        .await;
+        Some(datapoint)
+    }
+
+    pub async fn batch_operation(
+        &self,
+        owner: u64,
+        tags: &str,
+        keys: Vec<u64>,
+        add: bool,
+    ) -> Option<Vec<Datapoint>> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let webhooks = self.webhooks_to_check(owner).await;
+        let before = workspace.datastore.get_by_key(keys.clone());
+        let datapoints = workspace.datastore.prepare_batch_operation(tags, keys, add);
+        if !self
+            .storage
+            .batch_update_datapoints(owner, datapoints.clone())
+            .await
+        {
+            return None;
+        }
+        workspace.datastore.replace_datapoints(datapoints.clone());
+        self.fire_webhooks(&workspace, &webhooks, ChangeKind::Updated, &datapoints)
+            .await;
+        workspace
+            .commands
+            .record(BatchCommand::new(&before, &datapoints));
+        Some(datapoints)
+    }
+
+    /// Reverts the last `steps` batch operations, returning the datapoints they touched.
+    pub async fn undo(&self, owner: u64, steps: usize) -> Option<Vec<Datapoint>> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let commands = workspace.commands.take_undo(steps);
+        let datapoints = workspace.datastore.prepare_undo(&commands);
+        if !self
+            .apply_prepared(owner, &workspace, datapoints.clone())
+            .await
+        {
+            workspace.commands.cancel_undo(commands);
+            return None;
+        }
+        workspace.commands.finish_undo(commands);
+        Some(datapoints)
+    }
+
+    /// Reapplies the last `steps` undone batch operations, returning the datapoints they touched.
+    pub async fn redo(&self, owner: u64, steps: usize) -> Option<Vec<Datapoint>> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let commands = workspace.commands.take_redo(steps);
+        let datapoints = workspace.datastore.prepare_redo(&commands);
+        if !self
+            .apply_prepared(owner, &workspace, datapoints.clone())
+            .await
+        {
+            workspace.commands.cancel_redo(commands);
+            return None;
+        }
+        workspace.commands.finish_redo(commands);
+        Some(datapoints)
+    }
+
+    async fn apply_prepared(
+        &self,
+        owner: u64,
+        workspace: &Workspace,
+        datapoints: Vec<Datapoint>,
+    ) -> bool {
+        if datapoints.is_empty() {
+            return true;
+        }
+        let webhooks = self.webhooks_to_check(owner).await;
+        if !self
+            .storage
+            .batch_update_datapoints(owner, datapoints.clone())
+            .await
+        {
+            return false;
+        }
+        workspace.datastore.replace_datapoints(datapoints.clone());
+        self.fire_webhooks(workspace, &webhooks, ChangeKind::Updated, &datapoints)
+            .await;
+        true
+    }
+
+    /// Moves the datapoint to the trash, from where it can be restored until it is purged.
+    pub async fn delete_datapoint(&self, owner: u64, key: u64) -> Option<Datapoint> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        if !workspace.datastore.contains_key(key) {
+            return None;
+        }
+        let deleted_at = Utc::now();
+        if !self.storage.trash_datapoint(owner, key, deleted_at).await {
+            return None;
+        }
+        workspace
+            .datastore
+            .move_to_trash(key, deleted_at)
+            .map(|trashed| trashed.into_datapoint())
+    }
+
+    pub fn find_duplicates(&self, owner: u64, window: Duration) -> Vec<Vec<Datapoint>> {
+        self.workspace(owner).datastore.find_duplicates(window)
+    }
+
+    /// Keeps one datapoint of a group of duplicates and moves the others to the trash.
+    /// Nothing is removed unless every removed datapoint has the data and tags of the one
+    /// that is kept.
+    pub async fn merge_duplicates(
+        &self,
+        owner: u64,
+        keep: u64,
+        remove: Vec<u64>,
+    ) -> Option<Datapoint> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let kept = workspace.datastore.get_by_key(vec![keep]).pop()?;
+        let removed = workspace.datastore.get_by_key(remove.clone());
+        let all_duplicates = removed.len() == remove.len()
+            && !remove.contains(&keep)
+            && removed
+                .iter()
+                .all(|datapoint| datapoint.data_same_as(&kept) && datapoint.tags_same_as(&kept));
+        if !all_duplicates {
+            return None;
+        }
+        let deleted_at = Utc::now();
+        if !self
+            .storage
+            .trash_datapoints(owner, remove.clone(), deleted_at)
+            .await
+        {
+            return None;
+        }
+        for key in remove {
+            workspace.datastore.move_to_trash(key, deleted_at);
+        }
+        Some(kept)
+    }
+
+    pub async fn restore_datapoint(&self, owner: u64, key: u64) -> Option<Datapoint> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        if !workspace.datastore.trash_contains_key(key) {
+            return None;
+        }
+        let webhooks = self.webhooks_to_check(owner).await;
+        if !self.storage.restore_datapoint(owner, key).await {
+            return None;
+        }
+        let restored = workspace.datastore.restore_datapoint(key)?;
+        self.fire_webhooks(
+            &workspace,
+            &webhooks,
+            ChangeKind::Created,
+            std::slice::from_ref(&restored),
+        )
+        .await;
+        Some(restored)
+    }
+
+    /// Permanently deletes datapoints that have been in the trash for longer than the
+    /// retention period, returning the keys of the purged datapoints.
+    pub async fn purge_expired_trash(&self, owner: u64) -> Option<Vec<u64>> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let cutoff = Utc::now() - self.trash_retention;
+        let expired = workspace.datastore.expired_trash(&cutoff);
+        if expired.is_empty() {
+            return Some(expired);
+        }
+        if !self.storage.delete_datapoints(owner, expired.clone()).await {
+            return None;
+        }
+        workspace.datastore.remove_from_trash(&expired);
+        Some(expired)
+    }
+
+    /// Purges the expired trash of every user, returning the keys of the purged datapoints.
+    pub async fn purge_all_expired_trash(&self) -> Option<Vec<u64>> {
+        let owners: Vec<u64> = self.workspaces.lock().unwrap().keys().copied().collect();
+        let mut purged = Vec::new();
+        for owner in owners {
+            purged.extend(self.purge_expired_trash(owner).await?);
+        }
+        Some(purged)
+    }
+
+    pub async fn history(&self, owner: u64, key: u64) -> Option<Vec<Revision>> {
+        self.storage.load_revisions(owner, key).await
+    }
+
+    /// Puts the datapoint back into the state recorded by one of its revisions. The revert
+    /// itself is written as a new revision, so the log is never rewritten.
+    pub async fn revert_datapoint(
+        &self,
+        owner: u64,
+        key: u64,
+        revision_id: u64,
+    ) -> Option<Datapoint> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        if !workspace.datastore.contains_key(key) {
+            return None;
+        }
+        let webhooks = self.webhooks_to_check(owner).await;
+        let revisions = self.storage.load_revisions(owner, key).await?;
+        let revision = revisions
+            .into_iter()
+            .find(|revision| revision.get_id() == revision_id)?;
+        let mut datapoint = revision.get_after()?.clone();
+        datapoint.set_key(key);
+        if !self
+            .storage
+            .update_datapoint(owner, datapoint.clone())
+            .await
+        {
+            return None;
+        }
+        workspace
+            .datastore
+            .replace_datapoints(vec![datapoint.clone()]);
+        self.fire_webhooks(
+            &workspace,
+            &webhooks,
+            ChangeKind::Updated,
+            std::slice::from_ref(&datapoint),
+        )
+        .await;
+        Some(datapoint)
+    }
+
+    pub async fn backup(&self, owner: u64) -> Option<Backup> {
+        let workspace = self.workspace(owner);
+        let revisions = self.storage.load_all_revisions(owner).await?;
+        Some(Backup::new(
+            workspace.datastore.retrieve_datapoints(),
+            workspace.datastore.retrieve_trash(),
+            revisions,
+        ))
+    }
+
+    /// Restores a backup under newly allocated keys, returning the backup as restored. The
+    /// restored datapoints are not reported to the listener, so they set off no webhooks.
+    pub async fn restore_backup(
+        &self,
+        owner: u64,
+        backup: Backup,
+        mode: RestoreMode,
+    ) -> Option<Backup> {
+        let workspace = self.workspace(owner);
+        let _write = workspace.writes.lock().await;
+        let keys = self
+            .storage
+            .restore_backup(owner, backup.clone(), mode)
+            .await?;
+        let restored = backup.remap_keys(&keys);
+        if mode == RestoreMode::Replace {
+            workspace.commands.clear();
+        }
+        workspace.datastore.restore(
+            restored.get_datapoints().clone(),
+            restored.get_trash().clone(),
+            mode,
+        );
+        Some(restored)
+    }
+
+    pub fn retrieve_trash(&self, owner: u64) -> Vec<TrashedDatapoint> {
+        self.workspace(owner).datastore.retrieve_trash()
+    }
+
+    pub fn get_by_key(&self, owner: u64, keys: Vec<u64>) -> Vec<Datapoint> {
+        self.workspace(owner).datastore.get_by_key(keys)
+    }
+
+    pub fn query(&self, owner: u64, query: &str) -> QueryResult {
+        self.workspace(owner).datastore.query(query)
+    }
+
+    pub fn query_page(&self, owner: u64, query: &str, request: &PageRequest) -> QueryPage {
+        self.workspace(owner).datastore.query_page(query, request)
+    }
+
+    pub fn retrieve_taglist(&self, owner: u64) -> Vec<String> {
+        self.workspace(owner).datastore.retrieve_taglist()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use domain::datapoint::create_datapoint;
+    use domain::datapointevent::ChangeKind;
+    use domain::healthimport::RecordType;
+    use domain::revision::RevisionKind;
+    use domain::webhook::DeliveryStatus;
+    use std::sync::Mutex;
+
+    struct FakeStorage {
+        fail: bool,
+        written: Mutex<Vec<Datapoint>>,
+        deleted: Mutex<Vec<u64>>,
+        last_key: Mutex<u64>,
+        revisions: Vec<Revision>,
+        users: Mutex<Vec<User>>,
+        sessions: Mutex<Vec<Session>>,
+        api_tokens: Mutex<Vec<ApiToken>>,
+        webhooks: Mutex<Vec<Webhook>>,
+        deliveries: Mutex<Vec<Delivery>>,
+    }
+
+    impl FakeStorage {
+        fn working() -> FakeStorage {
+            FakeStorage {
+                fail: false,
+                written: Mutex::new(Vec::new()),
+                deleted: Mutex::new(Vec::new()),
+                last_key: Mutex::new(100),
+                revisions: Vec::new(),
+                users: Mutex::new(Vec::new()),
+                sessions: Mutex::new(Vec::new()),
+                api_tokens: Mutex::new(Vec::new()),
+                webhooks: Mutex::new(Vec::new()),
+                deliveries: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn failing() -> FakeStorage {
+            FakeStorage {
+                fail: true,
+                ..FakeStorage::working()
+            }
+        }
+
+        fn record(&self, datapoints: Vec<Datapoint>) -> bool {
+            if self.fail {
+                return false;
+            }
+            self.written.lock().unwrap().extend(datapoints);
+            true
+        }
+    }
+
+    impl Storage for FakeStorage {
+        async fn load_datapoints(&self, _owner: u64) -> Vec<Datapoint> {
+            self.written.lock().unwrap().clone()
+        }
+
+        async fn load_trash(&self, _owner: u64) -> Vec<TrashedDatapoint> {
+            Vec::new()
+        }
+
+        async fn insert_datapoint(&self, _owner: u64, mut datapoint: Datapoint) -> Option<u64> {
+            let mut last_key = self.last_key.lock().unwrap();
+            *last_key += 1;
+            datapoint.set_key(*last_key);
+            if !self.record(vec![datapoint]) {
+                return None;
+            }
+            Some(*last_key)
+        }
+
+        async fn insert_datapoints(
+            &self,
+            owner: u64,
+            datapoints: Vec<Datapoint>,
+        ) -> Option<Vec<u64>> {
+            if self.fail {
+                return None;
+            }
+            let mut keys = Vec::new();
+            for datapoint in datapoints {
+                keys.push(self.insert_datapoint(owner, datapoint).await?);
+            }
+            Some(keys)
+        }
+
+        async fn update_datapoint(&self, _owner: u64, datapoint: Datapoint) -> bool {
+            // Lets other requests run in between, as waiting for a database would.
+            tokio::task::yield_now().await;
+            self.record(vec![datapoint])
+        }
+
+        async fn batch_update_datapoints(&self, _owner: u64, datapoints: Vec<Datapoint>) -> bool {
+            tokio::task::yield_now().await;
+            self.record(datapoints)
+        }
+
+        async fn trash_datapoint(
+            &self,
+            _owner: u64,
+            _key: u64,
+            _deleted_at: DateTime<Utc>,
+        ) -> bool {
+            tokio::task::yield_now().await;
+            !self.fail
+        }
+
+        async fn trash_datapoints(
+            &self,
+            _owner: u64,
+            keys: Vec<u64>,
+            _deleted_at: DateTime<Utc>,
+        ) -> bool {
+            if self.fail {
+                return false;
+            }
+            self.deleted.lock().unwrap().extend(keys);
+            true
+        }
+
+        async fn restore_datapoint(&self, _owner: u64, _key: u64) -> bool {
+            !self.fail
+        }
+
+        async fn delete_datapoints(&self, _owner: u64, keys: Vec<u64>) -> bool {
+            if self.fail {
+                return false;
+            }
+            self.deleted.lock().unwrap().extend(keys);
+            true
+        }
+
+        async fn load_all_revisions(&self, _owner: u64) -> Option<Vec<Revision>> {
+            if self.fail {
+                return None;
+            }
+            Some(self.revisions.clone())
+        }
+
+        async fn restore_backup(
+            &self,
+            owner: u64,
+            backup: Backup,
+            _mode: RestoreMode,
+        ) -> Option<Vec<(u64, u64)>> {
+            if self.fail {
+                return None;
+            }
+            let mut keys = Vec::new();
+            let trashed = backup
+                .get_trash()
+                .iter()
+                .map(|trashed| trashed.get_datapoint().clone());
+            for datapoint in backup.get_datapoints().iter().cloned().chain(trashed) {
+                let old_key = datapoint.get_key();
+                keys.push((old_key, self.insert_datapoint(owner, datapoint).await?));
+            }
+            Some(keys)
+        }
+
+        async fn load_revisions(&self, _owner: u64, key: u64) -> Option<Vec<Revision>> {
+            if self.fail {
+                return None;
+            }
+            Some(
+                self.revisions
+                    .iter()
+                    .filter(|revision| revision.get_key() == key)
+                    .cloned()
+                    .collect(),
+            )
+        }
+
+        async fn insert_user(&self, user: User) -> Result<u64, RegistrationError> {
+            let mut users = self.users.lock().unwrap();
+            if self.fail {
+                return Err(RegistrationError::Failed);
+            }
+            if users
+                .iter()
+                .any(|existing| existing.get_username() == user.get_username())
+            {
+                return Err(RegistrationError::UsernameTaken);
+            }
+            let id = users.len() as u64 + 1;
+            users.push(user.with_id(id));
+            Ok(id)
+        }
+
+        async fn load_user(&self, username: &str) -> Option<User> {
+            self.users
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|user| user.get_username() == username)
+                .cloned()
+        }
+
+        async fn load_user_ids(&self) -> Vec<u64> {
+            self.users
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|user| user.get_id())
+                .collect()
+        }
+
+        async fn insert_session(&self, session: Session) -> bool {
+            if self.fail {
+                return false;
+            }
+            self.sessions.lock().unwrap().push(session);
+            true
+        }
+
+        async fn load_session(&self, token_hash: &str) -> Option<Session> {
+            self.sessions
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|session| session.get_token_hash() == token_hash)
+                .cloned()
+        }
+
+        async fn delete_session(&self, token_hash: &str) -> bool {
+            let mut sessions = self.sessions.lock().unwrap();
+            let before = sessions.len();
+            sessions.retain(|session| session.get_token_hash() != token_hash);
+            sessions.len() < before
+        }
+
+        async fn insert_api_token(&self, api_token: ApiToken) -> Option<u64> {
+            if self.fail {
+                return None;
+            }
+            let mut api_tokens = self.api_tokens.lock().unwrap();
+            let id = api_tokens.len() as u64 + 1;
+            api_tokens.push(api_token.with_id(id));
+            Some(id)
+        }
+
+        async fn load_api_tokens(&self, user_id: u64) -> Option<Vec<ApiToken>> {
+            Some(
+                self.api_tokens
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|api_token| api_token.get_user_id() == user_id)
+                    .cloned()
+                    .collect(),
+            )
+        }
+
+        async fn load_api_token(&self, token_hash: &str) -> Option<ApiToken> {
+            self.api_tokens
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|api_token| api_token.get_token_hash() == token_hash)
+                .cloned()
+        }
+
+        async fn delete_api_token(&self, user_id: u64, id: u64) -> bool {
+            let mut api_tokens = self.api_tokens.lock().unwrap();
+            let before = api_tokens.len();
+            api_tokens
+                .retain(|api_token| api_token.get_id() != id || api_token.get_user_id() != user_id);
+            api_tokens.len() < before
+        }
+
+        async fn record_api_token_use(&self, id: u64, used_at: DateTime<Utc>) -> bool {
+            for api_token in self.api_tokens.lock().unwrap().iter_mut() {
+                if api_token.get_id() == id {
+                    *api_token = api_token.clone().used_at(used_at);
+                }
+            }
+            true
+        }
+
+        async fn insert_webhook(&self, webhook: Webhook) -> Option<u64> {
+            if self.fail {
+                return None;
+            }
+            let mut webhooks = self.webhooks.lock().unwrap();
+            let id = webhooks.len() as u64 + 1;
+            webhooks.push(webhook.with_id(id));
+            Some(id)
+        }
+
+        async fn load_webhooks(&self, owner: u64) -> Option<Vec<Webhook>> {
+            Some(
+                self.webhooks
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|webhook| webhook.get_owner() == owner)
+                    .cloned()
+                    .collect(),
+            )
+        }
+
+        async fn delete_webhook(&self, owner: u64, id: u64) -> bool {
+            let mut webhooks = self.webhooks.lock().unwrap();
+            let before = webhooks.len();
+            webhooks.retain(|webhook| webhook.get_id() != id || webhook.get_owner() != owner);
+            if webhooks.len() == before {
+                return false;
+            }
+            self.deliveries
+                .lock()
+                .unwrap()
+                .retain(|delivery| delivery.get_webhook_id() != id);
+            true
+        }
+
+        async fn insert_delivery(&self, delivery: Delivery) -> Option<u64> {
+            if self.fail {
+                return None;
+            }
+            let mut deliveries = self.deliveries.lock().unwrap();
+            let id = deliveries.len() as u64 + 1;
+            deliveries.push(delivery.with_id(id));
+            Some(id)
+        }
+
+        async fn update_delivery(&self, delivery: Delivery) -> bool {
+            if self.fail {
+                return false;
+            }
+            for stored in self.deliveries.lock().unwrap().iter_mut() {
+                if stored.get_id() == delivery.get_id() {
+                    *stored = delivery.clone();
+                }
+            }
+            true
+        }
+
+        async fn load_deliveries(&self, webhook_id: u64) -> Option<Vec<Delivery>> {
+            Some(
+                self.deliveries
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .rev()
+                    .filter(|delivery| delivery.get_webhook_id() == webhook_id)
+                    .cloned()
+                    .collect(),
+            )
+        }
+
+        async fn load_due_deliveries(&self, now: DateTime<Utc>) -> Option<Vec<Delivery>> {
+            Some(
+                self.deliveries
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|delivery| delivery.is_due(&now))
+                    .cloned()
+                    .collect(),
+            )
+        }
+    }
+
+    const OWNER: u64 = 1;
+    const OTHER_OWNER: u64 = 2;
+
+    fn revision(id: u64, key: u64, before: Option<&str>, after: Option<&str>) -> Revision {
+        let snapshot = |input: &str| {
+            let mut datapoint = create_datapoint(input);
+            datapoint.set_key(key);
+            datapoint
+        };
+        Revision::new(
+            id,
+            key,
+            RevisionKind::Update,
+            Utc::now(),
+            before.map(snapshot),
+            after.map(snapshot),
+        )
+    }
+
+    fn seeded_datastore() -> Datastore {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("one +tag");
+        datastore.add_datapoint("two +tag");
+        datastore
+    }
+
+    #[tokio::test]
+    async fn added_datapoint_is_persisted_and_cached() {
+        let repository = Repository::new(FakeStorage::working());
+
+        let added = repository
+            .add_datapoint(OWNER, "80kg +weight", EntryTimezone::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repository.query(OWNER, "weight").get_datapoints(),
+            vec![added.clone()]
+        );
+        assert_eq!(*repository.storage.written.lock().unwrap(), vec![added]);
+    }
+
+    #[tokio::test]
+    async fn added_datapoint_is_keyed_by_storage() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let added = repository
+            .add_datapoint(OWNER, "80kg +weight", EntryTimezone::default())
+            .await
+            .unwrap();
+
+        assert_eq!(added.get_key(), 101);
+        assert_eq!(repository.get_by_key(OWNER, vec![101]), vec![added]);
+    }
+
+    #[tokio::test]
+    async fn failed_insert_leaves_datastore_untouched() {
+        let repository = Repository::new(FakeStorage::failing());
+
+        let added = repository
+            .add_datapoint(OWNER, "80kg +weight", EntryTimezone::default())
+            .await;
+
+        assert_eq!(added, None);
+        assert!(repository.query(OWNER, "").get_datapoints().is_empty());
+        assert!(repository.retrieve_taglist(OWNER).is_empty());
+    }
+
+    #[tokio::test]
+    async fn imported_datapoints_are_keyed_and_cached() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        let datapoints = vec![
+            create_datapoint("80kg +weight +D:2023-01-02"),
+            create_datapoint("79kg +weight +D:2023-01-03"),
+        ];
+
+        let imported = repository
+            .import_datapoints(OWNER, datapoints)
+            .await
+            .unwrap();
+
+        let keys: Vec<u64> = imported
+            .iter()
+            .map(|datapoint| datapoint.get_key())
+            .collect();
+        assert_eq!(keys, vec![101, 102]);
+        assert_eq!(repository.query(OWNER, "weight").get_datapoints(), imported);
+    }
+
+    #[tokio::test]
+    async fn failed_import_adds_nothing() {
+        let repository =
+            Repository::new(FakeStorage::failing()).with_workspace(OWNER, seeded_datastore());
+
+        let imported = repository
+            .import_datapoints(OWNER, vec![create_datapoint("80kg +weight")])
+            .await;
+
+        assert_eq!(imported, None);
+        assert!(repository
+            .query(OWNER, "weight")
+            .get_datapoints()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn health_records_already_stored_are_not_imported_again() {
+        let repository = Repository::new(FakeStorage::working());
+        let start = Utc::now().fixed_offset();
+        let records = vec![
+            HealthRecord::new(RecordType::Weight, start, 80.1),
+            HealthRecord::new(RecordType::Steps, start, 1234.0),
+        ];
+        repository
+            .import_health_records(OWNER, records[..1].to_vec(), &TagMapping::new())
+            .await
+            .unwrap();
+
+        let imported = repository
+            .import_health_records(OWNER, records, &TagMapping::new())
+            .await
+            .unwrap();
+
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].get_data(), "1234");
+        assert_eq!(repository.query(OWNER, "").get_datapoints().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_update_leaves_datastore_untouched() {
+        let repository =
+            Repository::new(FakeStorage::failing()).with_workspace(OWNER, seeded_datastore());
+
+        let updated = repository
+            .update_datapoint(OWNER, "changed +other", 1, EntryTimezone::default())
+            .await;
+
+        assert_eq!(updated, None);
+        assert_eq!(repository.get_by_key(OWNER, vec![1])[0].get_data(), "one");
+        assert_eq!(repository.retrieve_taglist(OWNER), vec!["tag".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn update_of_unknown_key_is_not_persisted() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let updated = repository
+            .update_datapoint(OWNER, "changed", 5, EntryTimezone::default())
+            .await;
+
+        assert_eq!(updated, None);
+        assert!(repository.storage.written.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_batch_operation_leaves_datastore_untouched() {
+        let repository =
+            Repository::new(FakeStorage::failing()).with_workspace(OWNER, seeded_datastore());
+
+        let edited = repository
+            .batch_operation(OWNER, "tag", vec![1, 2], false)
+            .await;
+
+        assert_eq!(edited, None);
+        assert_eq!(repository.query(OWNER, "tag").get_datapoints().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn successful_batch_operation_is_applied_to_datastore() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let edited = repository
+            .batch_operation(OWNER, "new", vec![2], true)
+            .await
+            .unwrap();
+
+        assert_eq!(repository.query(OWNER, "new").get_datapoints(), edited);
+        assert_eq!(*repository.storage.written.lock().unwrap(), edited);
+    }
+
+    fn datastore_with_duplicates() -> Datastore {
+        let datastore = Datastore::new();
+        datastore.add_datapoint("80kg +weight +D:2023-01-02 +T:07-30-00");
+        datastore.add_datapoint("80kg +weight +D:2023-01-02 +T:07-30-05");
+        datastore.add_datapoint("80kg +weight +D:2023-01-02 +T:07-30-09");
+        datastore.add_datapoint("81kg +weight +D:2023-01-02 +T:07-30-10");
+        datastore
+    }
+
+    #[tokio::test]
+    async fn merging_duplicates_trashes_all_but_the_kept_one() {
+        let repository = Repository::new(FakeStorage::working())
+            .with_workspace(OWNER, datastore_with_duplicates());
+
+        let kept = repository
+            .merge_duplicates(OWNER, 2, vec![1, 3])
+            .await
+            .unwrap();
+
+        assert_eq!(kept.get_key(), 2);
+        assert_eq!(repository.query(OWNER, "weight").get_datapoints().len(), 2);
+        assert_eq!(repository.retrieve_trash(OWNER).len(), 2);
+        assert_eq!(*repository.storage.deleted.lock().unwrap(), vec![1, 3]);
+        assert!(repository
+            .find_duplicates(OWNER, Duration::minutes(1))
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn merging_datapoints_that_differ_is_refused() {
+        let repository = Repository::new(FakeStorage::working())
+            .with_workspace(OWNER, datastore_with_duplicates());
+
+        assert_eq!(
+            repository.merge_duplicates(OWNER, 1, vec![2, 4]).await,
+            None
+        );
+        assert_eq!(
+            repository.merge_duplicates(OWNER, 1, vec![1, 2]).await,
+            None
+        );
+        assert_eq!(
+            repository.merge_duplicates(OWNER, 1, vec![2, 9]).await,
+            None
+        );
+        assert_eq!(repository.query(OWNER, "weight").get_datapoints().len(), 4);
+        assert!(repository.storage.deleted.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_merge_keeps_all_duplicates() {
+        let repository = Repository::new(FakeStorage::failing())
+            .with_workspace(OWNER, datastore_with_duplicates());
+
+        assert_eq!(
+            repository.merge_duplicates(OWNER, 1, vec![2, 3]).await,
+            None
+        );
+        assert_eq!(
+            repository.find_duplicates(OWNER, Duration::minutes(1))[0].len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_delete_keeps_datapoint_in_datastore() {
+        let repository =
+            Repository::new(FakeStorage::failing()).with_workspace(OWNER, seeded_datastore());
+
+        let deleted = repository.delete_datapoint(OWNER, 1).await;
+
+        assert_eq!(deleted, None);
+        assert_eq!(repository.get_by_key(OWNER, vec![1]).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn successful_delete_removes_datapoint_from_datastore() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let deleted = repository.delete_datapoint(OWNER, 1).await.unwrap();
+
+        assert_eq!(deleted.get_data(), "one");
+        assert!(repository.get_by_key(OWNER, vec![1]).is_empty());
+        assert_eq!(repository.retrieve_trash(OWNER)[0].get_key(), 1);
+    }
+
+    #[tokio::test]
+    async fn an_update_racing_a_delete_does_not_bring_the_datapoint_back() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let (deleted, updated) = tokio::join!(
+            repository.delete_datapoint(OWNER, 2),
+            repository.update_datapoint(OWNER, "changed", 2, EntryTimezone::default())
+        );
+
+        assert!(deleted.is_some());
+        assert_eq!(updated, None);
+        assert!(repository.get_by_key(OWNER, vec![2]).is_empty());
+        assert_eq!(repository.retrieve_trash(OWNER).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_batch_operations_on_the_same_datapoints_both_apply() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let (first, second) = tokio::join!(
+            repository.batch_operation(OWNER, "first", vec![1, 2], true),
+            repository.batch_operation(OWNER, "second", vec![1, 2], true)
+        );
+
+        assert!(first.is_some() && second.is_some());
+        for datapoint in repository.get_by_key(OWNER, vec![1, 2]) {
+            assert!(datapoint.get_tags().contains(&"first".to_string()));
+            assert!(datapoint.get_tags().contains(&"second".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn deleted_datapoint_can_be_restored() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        repository.delete_datapoint(OWNER, 1).await.unwrap();
+
+        let restored = repository.restore_datapoint(OWNER, 1).await.unwrap();
+
+        assert_eq!(restored.get_data(), "one");
+        assert_eq!(repository.get_by_key(OWNER, vec![1]), vec![restored]);
+        assert!(repository.retrieve_trash(OWNER).is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_restore_keeps_datapoint_in_trash() {
+        let datastore = seeded_datastore();
+        datastore.move_to_trash(1, Utc::now());
+        let repository = Repository::new(FakeStorage::failing()).with_workspace(OWNER, datastore);
+
+        let restored = repository.restore_datapoint(OWNER, 1).await;
+
+        assert_eq!(restored, None);
+        assert!(repository.get_by_key(OWNER, vec![1]).is_empty());
+        assert_eq!(repository.retrieve_trash(OWNER).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn trash_older_than_retention_is_purged() {
+        let datastore = seeded_datastore();
+        datastore.move_to_trash(1, Utc::now() - Duration::days(8));
+        datastore.move_to_trash(2, Utc::now() - Duration::days(6));
+        let repository = Repository::new(FakeStorage::working())
+            .with_workspace(OWNER, datastore)
+            .with_trash_retention(Duration::days(7));
+
+        let purged = repository.purge_expired_trash(OWNER).await.unwrap();
+
+        assert_eq!(purged, vec![1]);
+        assert_eq!(*repository.storage.deleted.lock().unwrap(), vec![1]);
+        assert_eq!(repository.retrieve_trash(OWNER).len(), 1);
+        assert_eq!(repository.retrieve_trash(OWNER)[0].get_key(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_purge_keeps_trash_intact() {
+        let datastore = seeded_datastore();
+        datastore.move_to_trash(1, Utc::now() - Duration::days(60));
+        let repository = Repository::new(FakeStorage::failing()).with_workspace(OWNER, datastore);
+
+        let purged = repository.purge_expired_trash(OWNER).await;
+
+        assert_eq!(purged, None);
+        assert_eq!(repository.retrieve_trash(OWNER).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn loading_fills_datastore_from_storage() {
+        let storage = FakeStorage::working();
+        storage.record(seeded_datastore().retrieve_datapoints());
+        storage
+            .users
+            .lock()
+            .unwrap()
+            .push(User::new(OWNER, "alice", "hash"));
+
+        let repository = Repository::load(storage).await;
+
+        assert_eq!(repository.query(OWNER, "tag").get_datapoints().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn history_lists_revisions_of_one_datapoint() {
+        let storage = FakeStorage {
+            revisions: vec![
+                revision(1, 1, None, Some("one +tag")),
+                revision(2, 2, None, Some("two +tag")),
+                revision(3, 1, Some("one +tag"), Some("uno +tag")),
+            ],
+            ..FakeStorage::working()
+        };
+        let repository = Repository::new(storage).with_workspace(OWNER, seeded_datastore());
+
+        let history = repository.history(OWNER, 1).await.unwrap();
+
+        let ids: Vec<u64> = history.iter().map(|revision| revision.get_id()).collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn revert_restores_state_of_revision() {
+        let storage = FakeStorage {
+            revisions: vec![
+                revision(1, 1, None, Some("first +tag")),
+                revision(2, 1, Some("first +tag"), Some("one +tag")),
+            ],
+            ..FakeStorage::working()
+        };
+        let repository = Repository::new(storage).with_workspace(OWNER, seeded_datastore());
+
+        let reverted = repository.revert_datapoint(OWNER, 1, 1).await.unwrap();
+
+        assert_eq!(reverted.get_data(), "first");
+        assert_eq!(reverted.get_key(), 1);
+        assert_eq!(
+            repository.get_by_key(OWNER, vec![1]),
+            vec![reverted.clone()]
+        );
+        assert_eq!(*repository.storage.written.lock().unwrap(), vec![reverted]);
+    }
+
+    #[tokio::test]
+    async fn revert_to_unknown_revision_changes_nothing() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let reverted = repository.revert_datapoint(OWNER, 1, 42).await;
+
+        assert_eq!(reverted, None);
+        assert_eq!(repository.get_by_key(OWNER, vec![1])[0].get_data(), "one");
+        assert!(repository.storage.written.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn undo_reverts_last_batch_operation() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        repository
+            .batch_operation(OWNER, "tag", vec![1, 2], false)
+            .await
+            .unwrap();
+
+        let undone = repository.undo(OWNER, 1).await.unwrap();
+
+        assert_eq!(undone.len(), 2);
+        assert_eq!(repository.query(OWNER, "tag").get_datapoints().len(), 2);
+        assert_eq!(repository.storage.written.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn undo_keeps_edits_made_after_the_batch_operation() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        repository
+            .batch_operation(OWNER, "morning", vec![1, 2], true)
+            .await
+            .unwrap();
+        repository
+            .update_datapoint(
+                OWNER,
+                "one, edited +tag +morning +checked",
+                1,
+                EntryTimezone::default(),
+            )
+            .await
+            .unwrap();
+
+        repository.undo(OWNER, 1).await.unwrap();
+
+        let edited = &repository.get_by_key(OWNER, vec![1])[0];
+        assert_eq!(edited.get_data(), "one, edited");
+        assert_eq!(edited.get_tags(), &vec!["tag", "checked"]);
+        assert_eq!(
+            repository.get_by_key(OWNER, vec![2])[0].get_tags(),
+            &vec!["tag"]
+        );
+    }
+
+    #[tokio::test]
+    async fn redo_reapplies_undone_batch_operations() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        repository
+            .batch_operation(OWNER, "a", vec![1], true)
+            .await
+            .unwrap();
+        repository
+            .batch_operation(OWNER, "b", vec![1], true)
+            .await
+            .unwrap();
+        repository.undo(OWNER, 2).await.unwrap();
+        assert_eq!(
+            repository.get_by_key(OWNER, vec![1])[0].get_tags(),
+            &vec!["tag".to_string()]
+        );
+
+        repository.redo(OWNER, 1).await.unwrap();
+
+        assert_eq!(
+            repository.get_by_key(OWNER, vec![1])[0].get_tags(),
+            &vec!["tag".to_string(), "a".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn undo_without_history_changes_nothing() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let undone = repository.undo(OWNER, 3).await.unwrap();
+
+        assert!(undone.is_empty());
+        assert!(repository.storage.written.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_undo_keeps_operation_undoable() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        repository
+            .batch_operation(OWNER, "tag", vec![1], false)
+            .await
+            .unwrap();
+        let repository = Repository {
+            storage: FakeStorage::failing(),
+            ..repository
+        };
+
+        assert_eq!(repository.undo(OWNER, 1).await, None);
+        assert!(repository.get_by_key(OWNER, vec![1])[0]
+            .get_tags()
+            .is_empty());
+        assert_eq!(repository.workspace(OWNER).commands.undo_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn backup_holds_datapoints_trash_and_history() {
+        let storage = FakeStorage {
+            revisions: vec![revision(1, 1, None, Some("one +tag"))],
+            ..FakeStorage::working()
+        };
+        let repository = Repository::new(storage).with_workspace(OWNER, seeded_datastore());
+        repository.delete_datapoint(OWNER, 2).await.unwrap();
+
+        let backup = repository.backup(OWNER).await.unwrap();
+
+        assert_eq!(backup.get_datapoints().len(), 1);
+        assert_eq!(backup.get_trash()[0].get_key(), 2);
+        assert_eq!(backup.get_revisions().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn merged_backup_is_added_under_new_keys() {
+        let source =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        source.delete_datapoint(OWNER, 2).await.unwrap();
+        let backup = source.backup(OWNER).await.unwrap();
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        let restored = repository
+            .restore_backup(OWNER, backup, RestoreMode::Merge)
+            .await
+            .unwrap();
+
+        assert_eq!(restored.get_datapoints()[0].get_key(), 101);
+        assert_eq!(restored.get_trash()[0].get_key(), 102);
+        assert_eq!(repository.query(OWNER, "tag").get_datapoints().len(), 3);
+        assert_eq!(repository.retrieve_trash(OWNER)[0].get_key(), 102);
+    }
+
+    #[tokio::test]
+    async fn replacing_backup_discards_previous_contents() {
+        let backup = Backup::new(
+            vec![create_datapoint("80kg +weight")],
+            Vec::new(),
+            Vec::new(),
+        );
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+        repository
+            .batch_operation(OWNER, "x", vec![1], true)
+            .await
+            .unwrap();
+
+        repository
+            .restore_backup(OWNER, backup, RestoreMode::Replace)
+            .await
+            .unwrap();
+
+        assert!(repository.query(OWNER, "tag").get_datapoints().is_empty());
+        assert_eq!(repository.query(OWNER, "weight").get_datapoints().len(), 1);
+        assert_eq!(
+            repository.retrieve_taglist(OWNER),
+            vec!["weight".to_string()]
+        );
+        assert_eq!(repository.workspace(OWNER).commands.undo_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn restoring_a_backup_is_not_reported_to_the_listener() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let repository = Repository::new(FakeStorage::working())
+            .with_workspace(OWNER, seeded_datastore())
+            .with_listener(move |owner, event: DatapointEvent| {
+                recorded.lock().unwrap().push((owner, event.get_kind()));
+            });
+        let backup = Backup::new(
+            vec![create_datapoint("80kg +weight")],
+            Vec::new(),
+            Vec::new(),
+        );
+
+        repository
+            .restore_backup(OWNER, backup, RestoreMode::Replace)
+            .await
+            .unwrap();
+
+        assert!(events.lock().unwrap().is_empty());
+        assert_eq!(repository.query(OWNER, "weight").get_datapoints().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_restore_of_backup_leaves_datastore_untouched() {
+        let backup = Backup::new(
+            vec![create_datapoint("80kg +weight")],
+            Vec::new(),
+            Vec::new(),
+        );
+        let repository =
+            Repository::new(FakeStorage::failing()).with_workspace(OWNER, seeded_datastore());
+
+        let restored = repository
+            .restore_backup(OWNER, backup, RestoreMode::Replace)
+            .await;
+
+        assert_eq!(restored, None);
+        assert_eq!(repository.query(OWNER, "tag").get_datapoints().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn datapoints_of_one_owner_are_invisible_to_another() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        assert!(repository
+            .query(OTHER_OWNER, "tag")
+            .get_datapoints()
+            .is_empty());
+        assert!(repository.get_by_key(OTHER_OWNER, vec![1]).is_empty());
+        assert!(repository.retrieve_taglist(OTHER_OWNER).is_empty());
+        assert_eq!(repository.query(OWNER, "tag").get_datapoints().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn datapoints_of_one_owner_cannot_be_changed_by_another() {
+        let repository =
+            Repository::new(FakeStorage::working()).with_workspace(OWNER, seeded_datastore());
+
+        assert_eq!(
+            repository
+                .update_datapoint(OTHER_OWNER, "mine", 1, EntryTimezone::default())
+                .await,
+            None
+        );
+        assert_eq!(repository.delete_datapoint(OTHER_OWNER, 1).await, None);
+        let edited = repository
+            .batch_operation(OTHER_OWNER, "x", vec![1, 2], true)
+            .await
+            .unwrap();
+
+        assert!(edited.is_empty());
+        assert!(repository.storage.written.lock().unwrap().is_empty());
+        assert_eq!(repository.get_by_key(OWNER, vec![1])[0].get_data(), "one");
+    }
+
+    #[tokio::test]
+    async fn registered_users_log_in_with_their_password_only() {
+        let repository = Repository::new(FakeStorage::working()).with_password_rounds(1000);
+        let user = repository.register("alice", "correct horse").await.unwrap();
+
+        assert_eq!(repository.login("alice", "battery staple").await, None);
+        assert_eq!(repository.login("bob", "correct horse").await, None);
+        let (logged_in, token, _) = repository.login("alice", "correct horse").await.unwrap();
+
+        assert_eq!(logged_in, user);
+        assert_eq!(
+            repository.authenticate(&token).await,
+            Some((user.get_id(), TokenScope::Admin))
+        );
+        assert_eq!(repository.authenticate("forged").await, None);
+    }
+
+    #[tokio::test]
+    async fn taken_usernames_and_short_passwords_are_refused() {
+        let repository = Repository::new(FakeStorage::working()).with_password_rounds(1000);
+        repository.register("alice", "correct horse").await.unwrap();
+
+        assert_eq!(
+            repository.register("alice", "other password").await,
+            Err(RegistrationError::UsernameTaken)
+        );
+        assert!(matches!(
+            repository.register("bob", "short").await,
+            Err(RegistrationError::Invalid(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn logged_out_and_expired_sessions_no_longer_authenticate() {
+        let repository = Repository::new(FakeStorage::working()).with_password_rounds(1000);
+        repository.register("alice", "correct horse").await.unwrap();
+        let (_, token, _) = repository.login("alice", "correct horse").await.unwrap();
+
+        assert!(repository.logout(&token).await);
+        assert_eq!(repository.authenticate(&token).await, None);
+
+        let repository = repository.with_session_lifetime(Duration::zero());
+        let (_, token, _) = repository.login("alice", "correct horse").await.unwrap();
+        assert_eq!(repository.authenticate(&token).await, None);
+    }
+
+    #[tokio::test]
+    async fn api_tokens_act_within_their_scope_and_record_their_use() {
+        let repository = Repository::new(FakeStorage::working());
+        let (created, token) = repository
+            .create_api_token(OWNER, "cron", TokenScope::Write)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repository.authenticate(&token).await,
+            Some((OWNER, TokenScope::Write))
+        );
+        let listed = repository.api_tokens(OWNER).await.unwrap();
+        assert_eq!(listed[0].get_id(), created.get_id());
+        assert!(listed[0].get_last_used_at().is_some());
+        assert!(repository.api_tokens(OTHER_OWNER).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn revoked_api_tokens_no_longer_authenticate() {
+        let repository = Repository::new(FakeStorage::working());
+        let (created, token) = repository
+            .create_api_token(OWNER, "cron", TokenScope::Read)
+            .await
+            .unwrap();
+
+        assert!(
+            !repository
+                .revoke_api_token(OTHER_OWNER, created.get_id())
+                .await
+        );
+        assert!(repository.revoke_api_token(OWNER, created.get_id()).await);
+        assert_eq!(repository.authenticate(&token).await, None);
+        assert_eq!(
+            repository
+                .create_api_token(OWNER, " ", TokenScope::Read)
+                .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn listener_hears_of_stored_changes_with_their_owner() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let repository = Repository::new(FakeStorage::working())
+            .with_workspace(OWNER, seeded_datastore())
+            .with_listener(move |owner, event: DatapointEvent| {
+                recorded.lock().unwrap().push((owner, event.get_kind()));
+            });
+
+        repository.delete_datapoint(OWNER, 1).await.unwrap();
+        repository
+            .add_datapoint(OTHER_OWNER, "80kg +weight", EntryTimezone::default())
+            .await
+            .unwrap();
+        let failing = Repository {
+            storage: FakeStorage::failing(),
+            ..repository
+        };
+        failing
+            .update_datapoint(OWNER, "changed", 2, EntryTimezone::default())
+            .await;
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (OWNER, ChangeKind::Deleted),
+                (OTHER_OWNER, ChangeKind::Created)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn changes_queue_deliveries_for_the_webhooks_they_set_off() {
+        let repository = Repository::new(FakeStorage::working()).with_webhook_payloads(
+            |webhook, event: &DatapointEvent, previous| {
+                let data = event.get_datapoint().get_data();
+                Some(format!("{} {} {:?}", webhook.get_id(), data, previous))
+            },
+        );
+        let url = "https://example.com/hook";
+        repository
+            .create_webhook(OWNER, url, "weight", WebhookTrigger::Created)
+            .await
+            .unwrap();
+        repository
+            .create_webhook(OWNER, url, "weight", WebhookTrigger::Threshold(80.0))
+            .await
+            .unwrap();
+
+        for (owner, input) in [
+            (OWNER, "79 +weight +DATE:2024-01-01 +TIME:08-00-00"),
+            (OWNER, "81 +weight +DATE:2024-01-02 +TIME:08-00-00"),
+            (OWNER, "500 +steps +DATE:2024-01-03 +TIME:08-00-00"),
+            (OTHER_OWNER, "70 +weight +DATE:2024-01-03 +TIME:08-00-00"),
+        ] {
+            repository
+                .add_datapoint(owner, input, EntryTimezone::default())
+                .await
+                .unwrap();
+        }
+
+        let payloads: Vec<String> = repository
+            .due_deliveries()
+            .await
+            .iter()
+            .map(|delivery| delivery.get_payload().to_string())
+            .collect();
+        assert_eq!(
+            payloads,
+            vec!["1 79 None", "1 81 Some(79.0)", "2 81 Some(79.0)"]
+        );
+    }
+
+    #[tokio::test]
+    async fn webhooks_cant_point_to_the_servers_own_network() {
+        let repository = Repository::new(FakeStorage::working());
+
+        for url in ["localhost", "http://localhost:9000/hook", "http://10.1.2.3"] {
+            assert_eq!(
+                repository
+                    .create_webhook(OWNER, url, "", WebhookTrigger::Created)
+                    .await,
+                None
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_retried_and_logged_for_their_owner() {
+        let repository = Repository::new(FakeStorage::working())
+            .with_retry_policy(RetryPolicy::new(2, Duration::zero()));
+        let webhook = repository
+            .create_webhook(OWNER, "https://example.com", "", WebhookTrigger::Created)
+            .await
+            .unwrap();
+        let delivery = repository.queue_delivery(&webhook, "{}").await.unwrap();
+
+        let due = repository.due_deliveries().await;
+        assert_eq!(due, vec![delivery.clone()]);
+        let retried = repository
+            .record_delivery_attempt(delivery, Ok(502))
+            .await
+            .unwrap();
+        assert_eq!(retried.get_status(), DeliveryStatus::Pending);
+        let failed = repository
+            .record_delivery_attempt(retried, Err("timed out".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(failed.get_status(), DeliveryStatus::Failed);
+        assert!(repository.due_deliveries().await.is_empty());
+        let log = repository
+            .deliveries(OWNER, webhook.get_id())
+            .await
+            .unwrap();
+        assert_eq!(log, vec![failed]);
+        assert_eq!(
+            repository.deliveries(OTHER_OWNER, webhook.get_id()).await,
+            None
+        );
+        assert!(
+            !repository
+                .delete_webhook(OTHER_OWNER, webhook.get_id())
+                .await
+        );
+        assert!(repository.delete_webhook(OWNER, webhook.get_id()).await);
+        assert_eq!(repository.deliveries(OWNER, webhook.get_id()).await, None);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::datapoint_dso::DatapointDSO;
+use chrono::prelude::*;
+use domain::revision::{Revision, RevisionKind};
+use sqlx::{mysql::MySqlRow, Row};
+
+pub struct RevisionDSO {
+    id: u64,
+    key: u64,
+    kind: String,
+    recorded_at: i64,
+    before: Option<DatapointDSO>,
+    after: Option<DatapointDSO>,
+}
+
+impl RevisionDSO {
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_key(&self) -> u64 {
+        self.key
+    }
+
+    pub fn get_kind(&self) -> String {
+        self.kind.clone()
+    }
+
+    pub fn get_recorded_at(&self) -> i64 {
+        self.recorded_at
+    }
+}
+
+impl From<MySqlRow> for RevisionDSO {
+    fn from(row: MySqlRow) -> Self {
+        let key: u64 = row.try_get("datapoint_id").unwrap();
+        RevisionDSO {
+            id: row.try_get("id").unwrap(),
+            key,
+            kind: row.try_get("kind").unwrap(),
+            recorded_at: row.try_get("recorded_at").unwrap(),
+            before: DatapointDSO::from_snapshot_row(&row, "before_", key),
+            after: DatapointDSO::from_snapshot_row(&row, "after_", key),
+        }
+    }
+}
+
+impl From<RevisionDSO> for Revision {
+    fn from(dso: RevisionDSO) -> Self {
+        Revision::new(
+            dso.id,
+            dso.key,
+            RevisionKind::parse(&dso.kind).expect("unknown revision kind in revision log"),
+            Utc.timestamp_opt(dso.recorded_at, 0)
+                .single()
+                .unwrap_or_default(),
+            dso.before.map(|before| before.into()),
+            dso.after.map(|after| after.into()),
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use domain::datapoint::{create_datapoint, Datapoint};
+
+    fn snapshot(input: &str, key: u64) -> DatapointDSO {
+        let mut datapoint = create_datapoint(input);
+        datapoint.set_key(key);
+        DatapointDSO::from(datapoint)
+    }
+
+    #[test]
+    fn revision_dso_exposes_its_fields() {
+        let revision_dso = RevisionDSO {
+            id: 3,
+            key: 9,
+            kind: "update".to_string(),
+            recorded_at: 1698218241,
+            before: None,
+            after: None,
+        };
+
+        assert_eq!(revision_dso.get_id(), 3);
+        assert_eq!(revision_dso.get_key(), 9);
+        assert_eq!(revision_dso.get_kind(), "update".to_string());
+        assert_eq!(revision_dso.get_recorded_at(), 1698218241);
+    }
+
+    #[test]
+    fn revision_dso_can_convert_into_revision() {
+        let revision_dso = RevisionDSO {
+            id: 3,
+            key: 9,
+            kind: "tagchange".to_string(),
+            recorded_at: 1698216313,
+            before: Some(snapshot("80kg +weight", 9)),
+            after: Some(snapshot("80kg +weight +morning", 9)),
+        };
+
+        let revision = Revision::from(revision_dso);
+
+        assert_eq!(revision.get_id(), 3);
+        assert_eq!(revision.get_key(), 9);
+        assert_eq!(revision.get_kind(), RevisionKind::TagChange);
+        assert_eq!(
+            revision.get_recorded_at(),
+            &Utc.with_ymd_and_hms(2023, 10, 25, 6, 45, 13).unwrap()
+        );
+        let before: &Datapoint = revision.get_before().unwrap();
+        let after: &Datapoint = revision.get_after().unwrap();
+        assert_eq!(before.get_tags(), &vec!["weight".to_string()]);
+        assert_eq!(
+            after.get_tags(),
+            &vec!["weight".to_string(), "morning".to_string()]
+        );
+        assert_eq!(after.get_key(), 9);
+    }
+
+    #[test]
+    fn revision_dso_without_snapshots_converts_into_revision_without_snapshots() {
+        let revision_dso = RevisionDSO {
+            id: 1,
+            key: 9,
+            kind: "create".to_string(),
+            recorded_at: 1698216313,
+            before: None,
+            after: None,
+        };
+
+        let revision = Revision::from(revision_dso);
+
+        assert_eq!(revision.get_before(), None);
+        assert_eq!(revision.get_after(), None);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::dbmanager::DBManager;
+use chrono::{DateTime, Utc};
+use domain::apitoken::ApiToken;
+use domain::backup::{Backup, RestoreMode};
+use domain::datapoint::Datapoint;
+use domain::revision::Revision;
+use domain::trasheddatapoint::TrashedDatapoint;
+use domain::user::{RegistrationError, Session, User};
+use domain::webhook::{Delivery, Webhook};
+use std::future::Future;
+
+/// Durable backing store for the datapoints cached in a `Datastore`, and for the accounts
+/// owning them. Every datapoint operation is scoped to the datapoints of `owner`.
+pub trait Storage {
+    fn load_datapoints(&self, owner: u64) -> impl Future<Output = Vec<Datapoint>> + Send;
+
+    fn load_trash(&self, owner: u64) -> impl Future<Output = Vec<TrashedDatapoint>> + Send;
+
+    /// Stores a new datapoint, returning the key the storage allocated for it.
+    fn insert_datapoint(
+        &self,
+        owner: u64,
+        datapoint: Datapoint,
+    ) -> impl Future<Output = Option<u64>> + Send;
+
+    /// Stores all datapoints or none of them, returning the allocated keys in order.
+    fn insert_datapoints(
+        &self,
+        owner: u64,
+        datapoints: Vec<Datapoint>,
+    ) -> impl Future<Output = Option<Vec<u64>>> + Send;
+
+    fn update_datapoint(
+        &self,
+        owner: u64,
+        datapoint: Datapoint,
+    ) -> impl Future<Output = bool> + Send;
+
+    fn batch_update_datapoints(
+        &self,
+        owner: u64,
+        datapoints: Vec<Datapoint>,
+    ) -> impl Future<Output = bool> + Send;
+
+    fn trash_datapoint(
+        &self,
+        owner: u64,
+        key: u64,
+        deleted_at: DateTime<Utc>,
+    ) -> impl Future<Output = bool> + Send;
+
+    /// Moves all datapoints to the trash, or none of them.
+    fn trash_datapoints(
+        &self,
+        owner: u64,
+        keys: Vec<u64>,
+        deleted_at: DateTime<Utc>,
+    ) -> impl Future<Output = bool> + Send;
+
+    fn restore_datapoint(&self, owner: u64, key: u64) -> impl Future<Output = bool> + Send;
+
+    /// Permanently removes the datapoints with the given keys.
+    fn delete_datapoints(&self, owner: u64, keys: Vec<u64>) -> impl Future<Output = bool> + Send;
+
+    /// Returns the revision log of a datapoint, oldest first.
+    fn load_revisions(
+        &self,
+        owner: u64,
+        key: u64,
+    ) -> impl Future<Output = Option<Vec<Revision>>> + Send;
+
+    fn load_all_revisions(&self, owner: u64) -> impl Future<Output = Option<Vec<Revision>>> + Send;
+
+    /// Writes a backup in one go, returning `(backed up, new)` pairs of the keys allocated
+    /// for its datapoints. In `Replace` mode everything the owner stored before is removed
+    /// first.
+    fn restore_backup(
+        &self,
+        owner: u64,
+        backup: Backup,
+        mode: RestoreMode,
+    ) -> impl Future<Output = Option<Vec<(u64, u64)>>> + Send;
+
+    /// Stores a new account, returning the id allocated for it.
+    fn insert_user(
+        &self,
+        user: User,
+    ) -> impl Future<Output = Result<u64, RegistrationError>> + Send;
+
+    fn load_user(&self, username: &str) -> impl Future<Output = Option<User>> + Send;
+
+    fn load_user_ids(&self) -> impl Future<Output = Vec<u64>> + Send;
+
+    fn insert_session(&self, session: Session) -> impl Future<Output = bool> + Send;
+
+    /// Looks a session up by the hash of its token.
+    fn load_session(&self, token_hash: &str) -> impl Future<Output = Option<Session>> + Send;
+
+    fn delete_session(&self, token_hash: &str) -> impl Future<Output = bool> + Send;
+
+    /// Stores a new API token, returning the id allocated for it.
+    fn insert_api_token(&self, api_token: ApiToken) -> impl Future<Output = Option<u64>> + Send;
+
+    fn load_api_tokens(&self, user_id: u64) -> impl Future<Output = Option<Vec<ApiToken>>> + Send;
+
+    /// Looks an API token up by the hash of the token.
+    fn load_api_token(&self, token_hash: &str) -> impl Future<Output = Option<ApiToken>> + Send;
+
+    /// Returns false when the user has no token with the id.
+    fn delete_api_token(&self, user_id: u64, id: u64) -> impl Future<Output = bool> + Send;
+
+    fn record_api_token_use(
+        &self,
+        id: u64,
+        used_at: DateTime<Utc>,
+    ) -> impl Future<Output = bool> + Send;
+
+    /// Stores a new webhook, returning the id allocated for it.
+    fn insert_webhook(&self, webhook: Webhook) -> impl Future<Output = Option<u64>> + Send;
+
+    fn load_webhooks(&self, owner: u64) -> impl Future<Output = Option<Vec<Webhook>>> + Send;
+
+    /// Deletes the webhook and its deliveries. Returns false when the owner has no webhook
+    /// with the id.
+    fn delete_webhook(&self, owner: u64, id: u64) -> impl Future<Output = bool> + Send;
+
+    /// Queues a new delivery, returning the id allocated for it.
+    fn insert_delivery(&self, delivery: Delivery) -> impl Future<Output = Option<u64>> + Send;
+
+    fn update_delivery(&self, delivery: Delivery) -> impl Future<Output = bool> + Send;
+
+    /// The deliveries of the webhook, newest first.
+    fn load_deliveries(
+        &self,
+        webhook_id: u64,
+    ) -> impl Future<Output = Option<Vec<Delivery>>> + Send;
+
+    /// The pending deliveries of every owner whose next attempt is due.
+    fn load_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+    ) -> impl Future<Output = Option<Vec<Delivery>>> + Send;
+}
+
+impl Storage for DBManager {
+    async fn load_datapoints(&self, owner: u64) -> Vec<Datapoint> {
+        DBManager::load_datapoints(self, owner).await
+    }
+
+    async fn load_trash(&self, owner: u64) -> Vec<TrashedDatapoint> {
+        DBManager::load_trash(self, owner).await
+    }
+
+    async fn insert_datapoint(&self, owner: u64, datapoint: Datapoint) -> Option<u64> {
+        DBManager::insert_datapoint(self, owner, datapoint).await
+    }
+
+    async fn insert_datapoints(&self, owner: u64, datapoints: Vec<Datapoint>) -> Option<Vec<u64>> {
+        DBManager::insert_datapoints(self, owner, datapoints).await
+    }
+
+    async fn update_datapoint(&self, owner: u64, datapoint: Datapoint) -> bool {
+        DBManager::update_datapoint(self, owner, datapoint).await
+    }
+
+    async fn batch_update_datapoints(&self, owner: u64, datapoints: Vec<Datapoint>) -> bool {
+        DBManager::batch_update_datapoints(self, owner, datapoints).await
+    }
+
+    async fn trash_datapoint(&self, owner: u64, key: u64, deleted_at: DateTime<Utc>) -> bool {
+        DBManager::trash_datapoint(self, owner, key, deleted_at).await
+    }
+
+    async fn trash_datapoints(
+        &self,
+        owner: u64,
+        keys: Vec<u64>,
+        deleted_at: DateTime<Utc>,
+    ) -> bool {
+        DBManager::trash_datapoints(self, owner, keys, deleted_at).await
+    }
+
+    async fn restore_datapoint(&self, owner: u64, key: u64) -> bool {
+        DBManager::restore_datapoint(self, owner, key).await
+    }
+
+    async fn delete_datapoints(&self, owner: u64, keys: Vec<u64>) -> bool {
+        DBManager::delete_datapoints(self, owner, keys).await
+    }
+
+    async fn load_revisions(&self, owner: u64, key: u64) -> Option<Vec<Revision>> {
+        DBManager::load_revisions(self, owner, key).await
+    }
+
+    async fn load_all_revisions(&self, owner: u64) -> Option<Vec<Revision>> {
+        DBManager::load_all_revisions(self, owner).await
+    }
+
+    async fn restore_backup(
+        &self,
+        owner: u64,
+        backup: Backup,
+        mode: RestoreMode,
+    ) -> Option<Vec<(u64, u64)>> {
+        DBManager::restore_backup(self, owner, backup, mode).await
+    }
+
+    async fn insert_user(&self, user: User) -> Result<u64, RegistrationError> {
+        DBManager::insert_user(self, user).await
+    }
+
+    async fn load_user(&self, username: &str) -> Option<User> {
+        DBManager::load_user(self, username).await
+    }
+
+    async fn load_user_ids(&self) -> Vec<u64> {
+        DBManager::load_user_ids(self).await
+    }
+
+    async fn insert_session(&self, session: Session) -> bool {
+        DBManager::insert_session(self, &session).await
+    }
+
+    async fn load_session(&self, token_hash: &str) -> Option<Session> {
+        DBManager::load_session(self, token_hash).await
+    }
+
+    async fn delete_session(&self, token_hash: &str) -> bool {
+        DBManager::delete_session(self, token_hash).await
+    }
+
+    async fn insert_api_token(&self, api_token: ApiToken) -> Option<u64> {
+        DBManager::insert_api_token(self, &api_token).await
+    }
+
+    async fn load_api_tokens(&self, user_id: u64) -> Option<Vec<ApiToken>> {
+        DBManager::load_api_tokens(self, user_id).await
+    }
+
+    async fn load_api_token(&self, token_hash: &str) -> Option<ApiToken> {
+        DBManager::load_api_token(self, token_hash).await
+    }
+
+    async fn delete_api_token(&self, user_id: u64, id: u64) -> bool {
+        DBManager::delete_api_token(self, user_id, id).await
+    }
+
+    async fn record_api_token_use(&self, id: u64, used_at: DateTime<Utc>) -> bool {
+        DBManager::record_api_token_use(self, id, used_at).await
+    }
+
+    async fn insert_webhook(&self, webhook: Webhook) -> Option<u64> {
+        DBManager::insert_webhook(self, &webhook).await
+    }
+
+    async fn load_webhooks(&self, owner: u64) -> Option<Vec<Webhook>> {
+        DBManager::load_webhooks(self, owner).await
+    }
+
+    async fn delete_webhook(&self, owner: u64, id: u64) -> bool {
+        DBManager::delete_webhook(self, owner, id).await
+    }
+
+    async fn insert_delivery(&self, delivery: Delivery) -> Option<u64> {
+        DBManager::insert_delivery(self, &delivery).await
+    }
+
+    async fn update_delivery(&self, delivery: Delivery) -> bool {
+        DBManager::update_delivery(self, &delivery).await
+    }
+
+    async fn load_deliveries(&self, webhook_id: u64) -> Option<Vec<Delivery>> {
+        DBManager::load_deliveries(self, webhook_id).await
+    }
+
+    async fn load_due_deliveries(&self, now: DateTime<Utc>) -> Option<Vec<Delivery>> {
+        DBManager::load_due_deliveries(self, now).await
+    }
+}
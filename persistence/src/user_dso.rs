@@ -0,0 +1,92 @@
+use chrono::prelude::*;
+use domain::user::{Session, User};
+use sqlx::{mysql::MySqlRow, Row};
+
+pub struct UserDSO {
+    id: u64,
+    username: String,
+    password_hash: String,
+}
+
+impl From<MySqlRow> for UserDSO {
+    fn from(row: MySqlRow) -> Self {
+        UserDSO {
+            id: row.try_get("id").unwrap(),
+            username: row.try_get("username").unwrap(),
+            password_hash: row.try_get("password_hash").unwrap(),
+        }
+    }
+}
+
+impl From<UserDSO> for User {
+    fn from(dso: UserDSO) -> Self {
+        User::new(dso.id, &dso.username, &dso.password_hash)
+    }
+}
+
+pub struct SessionDSO {
+    token_hash: String,
+    user_id: u64,
+    expires_at: i64,
+}
+
+impl SessionDSO {
+    pub fn get_token_hash(&self) -> &str {
+        &self.token_hash
+    }
+
+    pub fn get_user_id(&self) -> u64 {
+        self.user_id
+    }
+
+    pub fn get_expires_at(&self) -> i64 {
+        self.expires_at
+    }
+}
+
+impl From<MySqlRow> for SessionDSO {
+    fn from(row: MySqlRow) -> Self {
+        SessionDSO {
+            token_hash: row.try_get("token_hash").unwrap(),
+            user_id: row.try_get("user_id").unwrap(),
+            expires_at: row.try_get("expires_at").unwrap(),
+        }
+    }
+}
+
+impl From<&Session> for SessionDSO {
+    fn from(session: &Session) -> Self {
+        SessionDSO {
+            token_hash: session.get_token_hash().to_string(),
+            user_id: session.get_user_id(),
+            expires_at: session.get_expires_at().timestamp(),
+        }
+    }
+}
+
+impl From<SessionDSO> for Session {
+    fn from(dso: SessionDSO) -> Self {
+        Session::new(
+            dso.user_id,
+            &dso.token_hash,
+            Utc.timestamp_opt(dso.expires_at, 0)
+                .single()
+                .unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_round_trip_at_second_precision() {
+        let expires_at = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let session = Session::new(4, "abc", expires_at);
+
+        let restored = Session::from(SessionDSO::from(&session));
+
+        assert_eq!(restored, session);
+    }
+}
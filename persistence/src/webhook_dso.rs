@@ -0,0 +1,203 @@
+use chrono::prelude::*;
+use domain::webhook::{Delivery, DeliveryStatus, Webhook, WebhookTrigger};
+use sqlx::{mysql::MySqlRow, Row};
+
+pub struct WebhookDSO {
+    id: u64,
+    owner: u64,
+    url: String,
+    query: String,
+    trigger_kind: String,
+    threshold: Option<f64>,
+    created_at: i64,
+}
+
+impl WebhookDSO {
+    pub fn get_owner(&self) -> u64 {
+        self.owner
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn get_trigger_kind(&self) -> &str {
+        &self.trigger_kind
+    }
+
+    pub fn get_threshold(&self) -> Option<f64> {
+        self.threshold
+    }
+
+    pub fn get_created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+impl From<MySqlRow> for WebhookDSO {
+    fn from(row: MySqlRow) -> Self {
+        WebhookDSO {
+            id: row.try_get("id").unwrap(),
+            owner: row.try_get("owner_id").unwrap(),
+            url: row.try_get("url").unwrap(),
+            query: row.try_get("query").unwrap(),
+            trigger_kind: row.try_get("trigger_kind").unwrap(),
+            threshold: row.try_get("threshold").unwrap(),
+            created_at: row.try_get("created_at").unwrap(),
+        }
+    }
+}
+
+impl From<&Webhook> for WebhookDSO {
+    fn from(webhook: &Webhook) -> Self {
+        WebhookDSO {
+            id: webhook.get_id(),
+            owner: webhook.get_owner(),
+            url: webhook.get_url().to_string(),
+            query: webhook.get_query().to_string(),
+            trigger_kind: webhook.get_trigger().as_str().to_string(),
+            threshold: webhook.get_trigger().get_threshold(),
+            created_at: webhook.get_created_at().timestamp(),
+        }
+    }
+}
+
+impl From<WebhookDSO> for Option<Webhook> {
+    /// None for rows whose trigger this version doesn't know.
+    fn from(dso: WebhookDSO) -> Self {
+        let trigger = WebhookTrigger::parse(&dso.trigger_kind, dso.threshold)?;
+        Some(Webhook::new(
+            dso.id,
+            dso.owner,
+            &dso.url,
+            &dso.query,
+            trigger,
+            to_utc(dso.created_at),
+        ))
+    }
+}
+
+pub struct DeliveryDSO {
+    id: u64,
+    webhook_id: u64,
+    url: String,
+    payload: String,
+    status: String,
+    attempts: u32,
+    next_attempt_at: i64,
+    last_result: Option<String>,
+}
+
+impl DeliveryDSO {
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_webhook_id(&self) -> u64 {
+        self.webhook_id
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_payload(&self) -> &str {
+        &self.payload
+    }
+
+    pub fn get_status(&self) -> &str {
+        &self.status
+    }
+
+    pub fn get_attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn get_next_attempt_at(&self) -> i64 {
+        self.next_attempt_at
+    }
+
+    pub fn get_last_result(&self) -> Option<&str> {
+        self.last_result.as_deref()
+    }
+}
+
+impl From<MySqlRow> for DeliveryDSO {
+    fn from(row: MySqlRow) -> Self {
+        DeliveryDSO {
+            id: row.try_get("id").unwrap(),
+            webhook_id: row.try_get("webhook_id").unwrap(),
+            url: row.try_get("url").unwrap(),
+            payload: row.try_get("payload").unwrap(),
+            status: row.try_get("status").unwrap(),
+            attempts: row.try_get("attempts").unwrap(),
+            next_attempt_at: row.try_get("next_attempt_at").unwrap(),
+            last_result: row.try_get("last_result").unwrap(),
+        }
+    }
+}
+
+impl From<&Delivery> for DeliveryDSO {
+    fn from(delivery: &Delivery) -> Self {
+        DeliveryDSO {
+            id: delivery.get_id(),
+            webhook_id: delivery.get_webhook_id(),
+            url: delivery.get_url().to_string(),
+            payload: delivery.get_payload().to_string(),
+            status: delivery.get_status().as_str().to_string(),
+            attempts: delivery.get_attempts(),
+            next_attempt_at: delivery.get_next_attempt_at().timestamp(),
+            last_result: delivery.get_last_result().map(|result| result.to_string()),
+        }
+    }
+}
+
+impl From<DeliveryDSO> for Delivery {
+    fn from(dso: DeliveryDSO) -> Self {
+        Delivery::new(
+            dso.id,
+            dso.webhook_id,
+            &dso.url,
+            &dso.payload,
+            DeliveryStatus::parse(&dso.status).unwrap_or(DeliveryStatus::Failed),
+            dso.attempts,
+            to_utc(dso.next_attempt_at),
+            dso.last_result,
+        )
+    }
+}
+
+fn to_utc(timestamp: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(timestamp, 0).single().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhooks_and_deliveries_round_trip_at_second_precision() {
+        let at = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let trigger = WebhookTrigger::Threshold(80.5);
+        let webhook = Webhook::new(3, 4, "https://example.com", "weight", trigger, at);
+        let delivery = Delivery::new(
+            9,
+            3,
+            "https://x",
+            "{}",
+            DeliveryStatus::Pending,
+            2,
+            at,
+            None,
+        );
+
+        let restored: Option<Webhook> = WebhookDSO::from(&webhook).into();
+
+        assert_eq!(restored, Some(webhook));
+        assert_eq!(Delivery::from(DeliveryDSO::from(&delivery)), delivery);
+    }
+}
@@ -1,10 +1,85 @@
 USE tapas;
 
 CREATE TABLE datapoints (
-	id INT AUTO_INCREMENT UNIQUE,
+	id BIGINT UNSIGNED AUTO_INCREMENT,
 	data varchar(255),
 	tags varchar(255),
-	datetime INT,
-	data_key BIGINT UNSIGNED UNIQUE,
-	PRIMARY KEY(id)
-)
+	datetime BIGINT,
+	utc_offset INT,
+	deleted_at BIGINT NULL,
+	owner_id BIGINT UNSIGNED NULL,
+	PRIMARY KEY(id),
+	INDEX(owner_id)
+);
+
+CREATE TABLE datapoint_revisions (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	datapoint_id BIGINT UNSIGNED NOT NULL,
+	kind varchar(16),
+	recorded_at BIGINT,
+	before_data varchar(255) NULL,
+	before_tags varchar(255) NULL,
+	before_datetime BIGINT NULL,
+	before_utc_offset INT NULL,
+	after_data varchar(255) NULL,
+	after_tags varchar(255) NULL,
+	after_datetime BIGINT NULL,
+	after_utc_offset INT NULL,
+	PRIMARY KEY(id),
+	INDEX(datapoint_id)
+);
+
+CREATE TABLE users (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	username varchar(64) NOT NULL,
+	password_hash varchar(255) NOT NULL,
+	PRIMARY KEY(id),
+	UNIQUE(username)
+);
+
+CREATE TABLE sessions (
+	token_hash char(64) NOT NULL,
+	user_id BIGINT UNSIGNED NOT NULL,
+	expires_at BIGINT NOT NULL,
+	PRIMARY KEY(token_hash),
+	INDEX(user_id)
+);
+
+CREATE TABLE api_tokens (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	user_id BIGINT UNSIGNED NOT NULL,
+	name varchar(64) NOT NULL,
+	scope varchar(8) NOT NULL,
+	token_hash char(64) NOT NULL,
+	created_at BIGINT NOT NULL,
+	last_used_at BIGINT NULL,
+	PRIMARY KEY(id),
+	UNIQUE(token_hash),
+	INDEX(user_id)
+);
+
+CREATE TABLE webhooks (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	owner_id BIGINT UNSIGNED NOT NULL,
+	url varchar(2048) NOT NULL,
+	query varchar(1024) NOT NULL,
+	trigger_kind varchar(16) NOT NULL,
+	threshold DOUBLE NULL,
+	created_at BIGINT NOT NULL,
+	PRIMARY KEY(id),
+	INDEX(owner_id)
+);
+
+CREATE TABLE webhook_deliveries (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	webhook_id BIGINT UNSIGNED NOT NULL,
+	url varchar(2048) NOT NULL,
+	payload TEXT NOT NULL,
+	status varchar(16) NOT NULL,
+	attempts INT UNSIGNED NOT NULL,
+	next_attempt_at BIGINT NOT NULL,
+	last_result varchar(255) NULL,
+	PRIMARY KEY(id),
+	INDEX(webhook_id),
+	INDEX(status, next_attempt_at)
+);
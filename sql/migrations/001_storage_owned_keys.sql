@@ -0,0 +1,21 @@
+USE tapas;
+
+-- Datapoint keys are now allocated by the auto-increment id column instead of the
+-- separate data_key column. Existing keys are carried over as ids, rows without a key
+-- are assigned a fresh one, and the auto-increment continues past the highest key.
+CREATE TABLE datapoints_migrated (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	data varchar(255),
+	tags varchar(255),
+	datetime INT,
+	PRIMARY KEY(id)
+);
+
+INSERT INTO datapoints_migrated (id, data, tags, datetime)
+	SELECT data_key, data, tags, datetime FROM datapoints WHERE data_key IS NOT NULL ORDER BY data_key;
+
+INSERT INTO datapoints_migrated (data, tags, datetime)
+	SELECT data, tags, datetime FROM datapoints WHERE data_key IS NULL ORDER BY id;
+
+DROP TABLE datapoints;
+RENAME TABLE datapoints_migrated TO datapoints;
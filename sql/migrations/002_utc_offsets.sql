@@ -0,0 +1,8 @@
+USE tapas;
+
+-- Datetimes are stored as a 64-bit UTC timestamp together with the UTC offset (in seconds)
+-- they were logged in. Existing rows keep a NULL offset and are read back in the server's
+-- local time, as they were before.
+ALTER TABLE datapoints
+	MODIFY datetime BIGINT,
+	ADD COLUMN utc_offset INT NULL;
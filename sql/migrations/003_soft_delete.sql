@@ -0,0 +1,6 @@
+USE tapas;
+
+-- Deleted datapoints are kept in the trash with the UTC timestamp of their deletion
+-- until they are purged after the retention period.
+ALTER TABLE datapoints
+	ADD COLUMN deleted_at BIGINT NULL;
@@ -0,0 +1,20 @@
+USE tapas;
+
+-- Append-only log of every change made to a datapoint, holding the datapoint as it
+-- was before and after the change. Rows are only ever inserted.
+CREATE TABLE datapoint_revisions (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	datapoint_id BIGINT UNSIGNED NOT NULL,
+	kind varchar(16),
+	recorded_at BIGINT,
+	before_data varchar(255) NULL,
+	before_tags varchar(255) NULL,
+	before_datetime BIGINT NULL,
+	before_utc_offset INT NULL,
+	after_data varchar(255) NULL,
+	after_tags varchar(255) NULL,
+	after_datetime BIGINT NULL,
+	after_utc_offset INT NULL,
+	PRIMARY KEY(id),
+	INDEX(datapoint_id)
+);
@@ -0,0 +1,24 @@
+USE tapas;
+
+-- Accounts and their login sessions. Sessions are looked up by the SHA-256 hash of the
+-- token handed to the client, never by the token itself.
+CREATE TABLE users (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	username varchar(64) NOT NULL,
+	password_hash varchar(255) NOT NULL,
+	PRIMARY KEY(id),
+	UNIQUE(username)
+);
+
+CREATE TABLE sessions (
+	token_hash char(64) NOT NULL,
+	user_id BIGINT UNSIGNED NOT NULL,
+	expires_at BIGINT NOT NULL,
+	PRIMARY KEY(token_hash),
+	INDEX(user_id)
+);
+
+-- Datapoints logged before accounts existed have no owner; the first account to be
+-- registered adopts them.
+ALTER TABLE datapoints ADD COLUMN owner_id BIGINT UNSIGNED NULL;
+ALTER TABLE datapoints ADD INDEX(owner_id);
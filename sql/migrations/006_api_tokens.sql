@@ -0,0 +1,16 @@
+USE tapas;
+
+-- Long-lived tokens for scripts and integrations. Like sessions they are looked up by the
+-- SHA-256 hash of the token; last_used_at is NULL until the token is first used.
+CREATE TABLE api_tokens (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	user_id BIGINT UNSIGNED NOT NULL,
+	name varchar(64) NOT NULL,
+	scope varchar(8) NOT NULL,
+	token_hash char(64) NOT NULL,
+	created_at BIGINT NOT NULL,
+	last_used_at BIGINT NULL,
+	PRIMARY KEY(id),
+	UNIQUE(token_hash),
+	INDEX(user_id)
+);
@@ -0,0 +1,31 @@
+USE tapas;
+
+-- URLs notified of changes to the datapoints of their owner matching query. threshold is
+-- only set for trigger 'threshold'.
+CREATE TABLE webhooks (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	owner_id BIGINT UNSIGNED NOT NULL,
+	url varchar(2048) NOT NULL,
+	query varchar(1024) NOT NULL,
+	trigger_kind varchar(16) NOT NULL,
+	threshold DOUBLE NULL,
+	created_at BIGINT NOT NULL,
+	PRIMARY KEY(id),
+	INDEX(owner_id)
+);
+
+-- Payloads queued for webhooks, kept after delivery as the delivery log. Pending rows are
+-- picked up once next_attempt_at has passed.
+CREATE TABLE webhook_deliveries (
+	id BIGINT UNSIGNED AUTO_INCREMENT,
+	webhook_id BIGINT UNSIGNED NOT NULL,
+	url varchar(2048) NOT NULL,
+	payload TEXT NOT NULL,
+	status varchar(16) NOT NULL,
+	attempts INT UNSIGNED NOT NULL,
+	next_attempt_at BIGINT NOT NULL,
+	last_result varchar(255) NULL,
+	PRIMARY KEY(id),
+	INDEX(webhook_id),
+	INDEX(status, next_attempt_at)
+);